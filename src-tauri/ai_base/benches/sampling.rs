@@ -0,0 +1,94 @@
+//! 采样开销基准测试
+//!
+//! 以 15 万词表模拟 Qwen 系列模型，测量单个 token 的采样耗时。
+//! 运行：`cargo bench --bench sampling`
+
+use ai_base::sampling::{Sampler, SamplingParams};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+const VOCAB_SIZE: usize = 151_936;
+
+/// 生成伪随机 logits：`peak` 越大，少数 token 的 logit 越突出（越接近真实模型输出）
+fn fake_logits(peak: i32) -> Vec<f32> {
+    // 简单的确定性伪随机序列，避免引入额外依赖
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..VOCAB_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let u = (state % 1_000_000) as f32 / 1_000_000.0;
+            -10.0 + 30.0 * u.powi(peak)
+        })
+        .collect()
+}
+
+fn bench_sampling(c: &mut Criterion) {
+    let context: Vec<u32> = (0..512).map(|i| (i * 97) % VOCAB_SIZE as u32).collect();
+
+    let cases = [
+        (
+            "greedy",
+            SamplingParams {
+                temperature: 0.0,
+                ..Default::default()
+            },
+        ),
+        ("default_top_k_top_p", SamplingParams::default()),
+        (
+            "top_p_only",
+            SamplingParams {
+                top_k: 0,
+                ..Default::default()
+            },
+        ),
+        (
+            "min_p_only",
+            SamplingParams {
+                top_k: 0,
+                top_p: 1.0,
+                min_p: 0.05,
+                ..Default::default()
+            },
+        ),
+        (
+            "typical_p_only",
+            SamplingParams {
+                top_k: 0,
+                top_p: 1.0,
+                typical_p: 0.9,
+                ..Default::default()
+            },
+        ),
+        (
+            "full_chain",
+            SamplingParams {
+                min_p: 0.05,
+                typical_p: 0.95,
+                repetition_penalty: 1.1,
+                frequency_penalty: 0.2,
+                presence_penalty: 0.2,
+                logit_bias: [(1, 2.0), (2, -100.0)].into_iter().collect(),
+                seed: Some(0),
+                ..Default::default()
+            },
+        ),
+    ];
+
+    // peaked 接近真实模型输出；flat 为接近均匀分布的最坏情况
+    for (distribution, peak) in [("peaked", 16), ("flat", 1)] {
+        let logits = fake_logits(peak);
+        let mut group = c.benchmark_group(format!("sample_150k_vocab_{distribution}"));
+        for (name, params) in &cases {
+            let mut sampler = Sampler::from_params(params);
+            group.bench_function(*name, |b| {
+                b.iter(|| sampler.sample_logits(black_box(&logits), &context).unwrap())
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_sampling);
+criterion_main!(benches);
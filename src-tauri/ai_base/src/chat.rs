@@ -0,0 +1,333 @@
+//! 对话模板
+//!
+//! 将 [`ChatMessage`] 列表渲染为模型训练时使用的提示词格式。模板优先取自模型本身
+//! （GGUF 头部的 `tokenizer.chat_template` 或 `tokenizer_config.json` 中的 `chat_template`），
+//! 都没有时根据词表中的特殊 token 选择内置的 ChatML、Llama-3 或 Gemma 模板。
+
+use anyhow::{Context, Result};
+use candle_core::quantized::gguf_file;
+use minijinja::{context, Environment, Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokenizers::Tokenizer;
+
+/// 对话角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+/// 对话消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(ChatRole::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(ChatRole::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(ChatRole::Assistant, content)
+    }
+
+    pub fn tool(content: impl Into<String>) -> Self {
+        Self::new(ChatRole::Tool, content)
+    }
+}
+
+/// ChatML（Qwen、SmolLM 等）
+const CHATML_TEMPLATE: &str = "\
+{%- for message in messages %}\
+{{- '<|im_start|>' + message.role + '\\n' + message.content + '<|im_end|>\\n' }}\
+{%- endfor %}\
+{%- if add_generation_prompt %}{{- '<|im_start|>assistant\\n' }}{%- endif %}";
+
+/// Llama-3（工具消息使用 ipython 角色）
+const LLAMA3_TEMPLATE: &str = "\
+{{- bos_token }}\
+{%- for message in messages %}\
+{%- set role = 'ipython' if message.role == 'tool' else message.role %}\
+{{- '<|start_header_id|>' + role + '<|end_header_id|>\\n\\n' + message.content | trim + '<|eot_id|>' }}\
+{%- endfor %}\
+{%- if add_generation_prompt %}{{- '<|start_header_id|>assistant<|end_header_id|>\\n\\n' }}{%- endif %}";
+
+/// Gemma（没有 system 角色，系统提示并入下一条用户消息）
+const GEMMA_TEMPLATE: &str = "\
+{{- bos_token }}\
+{%- set ns = namespace(system='') %}\
+{%- for message in messages %}\
+{%- if message.role == 'system' %}\
+{%- set ns.system = ns.system + message.content | trim + '\\n\\n' %}\
+{%- else %}\
+{%- set role = 'model' if message.role == 'assistant' else 'user' %}\
+{{- '<start_of_turn>' + role + '\\n' + (ns.system if role == 'user' else '') + message.content | trim + '<end_of_turn>\\n' }}\
+{%- if role == 'user' %}{%- set ns.system = '' %}{%- endif %}\
+{%- endif %}\
+{%- endfor %}\
+{%- if add_generation_prompt %}{{- '<start_of_turn>model\\n' }}{%- endif %}";
+
+/// Jinja 对话模板
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatTemplate {
+    source: String,
+    bos_token: String,
+    eos_token: String,
+}
+
+impl ChatTemplate {
+    pub fn new(
+        source: impl Into<String>,
+        bos_token: impl Into<String>,
+        eos_token: impl Into<String>,
+    ) -> Self {
+        Self {
+            source: source.into(),
+            bos_token: bos_token.into(),
+            eos_token: eos_token.into(),
+        }
+    }
+
+    /// 内置 ChatML 模板
+    pub fn chatml() -> Self {
+        Self::new(CHATML_TEMPLATE, "", "<|im_end|>")
+    }
+
+    /// 内置 Llama-3 模板
+    pub fn llama3() -> Self {
+        Self::new(LLAMA3_TEMPLATE, "<|begin_of_text|>", "<|eot_id|>")
+    }
+
+    /// 内置 Gemma 模板
+    pub fn gemma() -> Self {
+        Self::new(GEMMA_TEMPLATE, "<bos>", "<end_of_turn>")
+    }
+
+    /// 模板源码
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// 根据词表中的特殊 token 选择内置模板，无法判断时使用 ChatML
+    pub fn builtin_for(tokenizer: &Tokenizer) -> Self {
+        if tokenizer.token_to_id("<|start_header_id|>").is_some() {
+            Self::llama3()
+        } else if tokenizer.token_to_id("<start_of_turn>").is_some() {
+            Self::gemma()
+        } else {
+            Self::chatml()
+        }
+    }
+
+    /// 从 GGUF 头部读取 `tokenizer.chat_template`，BOS/EOS 文本取自内嵌词表
+    pub fn from_gguf_metadata(ct: &gguf_file::Content) -> Option<Self> {
+        let source = ct
+            .metadata
+            .get("tokenizer.chat_template")
+            .and_then(|v| v.to_string().ok())?;
+
+        let token_text = |key: &str| -> String {
+            let tokens = ct
+                .metadata
+                .get("tokenizer.ggml.tokens")
+                .and_then(|v| v.to_vec().ok());
+            let id = ct.metadata.get(key).and_then(|v| v.to_u32().ok());
+            match (tokens, id) {
+                (Some(tokens), Some(id)) => tokens
+                    .get(id as usize)
+                    .and_then(|t| t.to_string().ok())
+                    .cloned()
+                    .unwrap_or_default(),
+                _ => String::new(),
+            }
+        };
+
+        Some(Self::new(
+            source.clone(),
+            token_text("tokenizer.ggml.bos_token_id"),
+            token_text("tokenizer.ggml.eos_token_id"),
+        ))
+    }
+
+    /// 从 `tokenizer_config.json` 读取模板，文件中没有 `chat_template` 时返回 `None`
+    ///
+    /// `chat_template` 可以是字符串，也可以是 `{name, template}` 列表（取 `default`）。
+    pub fn from_tokenizer_config(path: &Path) -> Result<Option<Self>> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("无法读取 tokenizer 配置: {:?}", path))?;
+        let config: serde_json::Value = serde_json::from_str(&content)
+            .with_context(|| format!("无法解析 tokenizer 配置: {:?}", path))?;
+
+        let source = match config.get("chat_template") {
+            Some(serde_json::Value::String(source)) => Some(source.clone()),
+            Some(serde_json::Value::Array(templates)) => {
+                let named = |name: &str| {
+                    templates
+                        .iter()
+                        .find(|t| t["name"] == name)
+                        .and_then(|t| t["template"].as_str())
+                };
+                named("default")
+                    .or_else(|| templates.first().and_then(|t| t["template"].as_str()))
+                    .map(str::to_string)
+            }
+            _ => None,
+        };
+
+        // 特殊 token 可以是字符串或 {"content": ...}
+        let special_token = |key: &str| -> String {
+            match &config[key] {
+                serde_json::Value::String(token) => token.clone(),
+                value => value["content"].as_str().unwrap_or_default().to_string(),
+            }
+        };
+
+        Ok(source.map(|source| {
+            Self::new(
+                source,
+                special_token("bos_token"),
+                special_token("eos_token"),
+            )
+        }))
+    }
+
+    /// 渲染对话
+    ///
+    /// `add_generation_prompt` 为 true 时在末尾追加助手回复的开头，供模型续写。
+    pub fn render(&self, messages: &[ChatMessage], add_generation_prompt: bool) -> Result<String> {
+        let mut env = Environment::new();
+        // 兼容 HF 模板中常用的 Python 字符串/字典方法（strip、startswith、items 等）
+        env.set_unknown_method_callback(minijinja_contrib::pycompat::unknown_method_callback);
+        env.add_function("raise_exception", |message: String| -> Result<(), Error> {
+            Err(Error::new(ErrorKind::InvalidOperation, message))
+        });
+
+        let template = env
+            .template_from_str(&self.source)
+            .context("对话模板解析失败")?;
+        template
+            .render(context! {
+                messages => messages,
+                add_generation_prompt => add_generation_prompt,
+                bos_token => &self.bos_token,
+                eos_token => &self.eos_token,
+            })
+            .context("对话模板渲染失败")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation() -> Vec<ChatMessage> {
+        vec![
+            ChatMessage::system("You are helpful."),
+            ChatMessage::user("Hi"),
+            ChatMessage::assistant("Hello!"),
+            ChatMessage::user("2+2?"),
+        ]
+    }
+
+    #[test]
+    fn test_chatml() {
+        let prompt = ChatTemplate::chatml()
+            .render(&conversation(), true)
+            .unwrap();
+        assert_eq!(
+            prompt,
+            "<|im_start|>system\nYou are helpful.<|im_end|>\n\
+             <|im_start|>user\nHi<|im_end|>\n\
+             <|im_start|>assistant\nHello!<|im_end|>\n\
+             <|im_start|>user\n2+2?<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+    }
+
+    #[test]
+    fn test_llama3() {
+        let messages = vec![ChatMessage::user("Hi"), ChatMessage::tool("{\"ok\":1}")];
+        let prompt = ChatTemplate::llama3().render(&messages, false).unwrap();
+        assert_eq!(
+            prompt,
+            "<|begin_of_text|><|start_header_id|>user<|end_header_id|>\n\nHi<|eot_id|>\
+             <|start_header_id|>ipython<|end_header_id|>\n\n{\"ok\":1}<|eot_id|>"
+        );
+    }
+
+    #[test]
+    fn test_gemma_merges_system_prompt() {
+        let prompt = ChatTemplate::gemma().render(&conversation(), true).unwrap();
+        assert_eq!(
+            prompt,
+            "<bos><start_of_turn>user\nYou are helpful.\n\nHi<end_of_turn>\n\
+             <start_of_turn>model\nHello!<end_of_turn>\n\
+             <start_of_turn>user\n2+2?<end_of_turn>\n\
+             <start_of_turn>model\n"
+        );
+    }
+
+    #[test]
+    fn test_hf_template_with_python_methods() {
+        // 节选自 HF 模板的常见写法：raise_exception、字符串方法、bos_token
+        let source = "{{ bos_token }}\
+            {% if messages[0]['role'] == 'assistant' %}{{ raise_exception('bad start') }}{% endif %}\
+            {% for m in messages %}[{{ m['role'].upper() }}] {{ m['content'].strip() }}\n{% endfor %}";
+        let template = ChatTemplate::new(source, "<s>", "</s>");
+        let prompt = template
+            .render(&[ChatMessage::user("  hello  ")], false)
+            .unwrap();
+        assert_eq!(prompt, "<s>[USER] hello\n");
+
+        let err = template
+            .render(&[ChatMessage::assistant("x")], false)
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("bad start"));
+    }
+
+    #[test]
+    fn test_tokenizer_config_template_list() {
+        let dir = std::env::temp_dir().join(format!("ai_base_chat_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tokenizer_config.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "bos_token": {"content": "<s>"},
+                "eos_token": "</s>",
+                "chat_template": [
+                    {"name": "tool_use", "template": "tools"},
+                    {"name": "default", "template": "{{ bos_token }}{{ messages[0].content }}{{ eos_token }}"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let template = ChatTemplate::from_tokenizer_config(&path).unwrap().unwrap();
+        let prompt = template.render(&[ChatMessage::user("x")], false).unwrap();
+        assert_eq!(prompt, "<s>x</s>");
+
+        std::fs::write(&path, r#"{"eos_token": "</s>"}"#).unwrap();
+        assert!(ChatTemplate::from_tokenizer_config(&path)
+            .unwrap()
+            .is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+//! 推理错误
+//!
+//! 调用方需要区分处理的错误以 [`InferenceError`] 返回，包装在 `anyhow::Error` 中，
+//! 可以通过 `downcast_ref` 或遍历 `chain()` 识别，即使外层附加了 context。
+
+use std::fmt;
+
+/// 可识别的推理错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InferenceError {
+    /// 模型没有加载 tokenizer，`action` 为无法执行的操作，`cause` 为构建 tokenizer 失败的原因
+    TokenizerMissing {
+        action: &'static str,
+        cause: Option<String>,
+    },
+    /// 模型架构不支持按会话保存独立的 KV cache，不能指定 `session_id`
+    SessionUnsupported { architecture: &'static str },
+    /// 提示词的 token 数超过模型的最大序列长度
+    ContextOverflow {
+        prompt_tokens: usize,
+        max_seq_len: usize,
+    },
+}
+
+impl fmt::Display for InferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InferenceError::TokenizerMissing { action, cause } => {
+                write!(f, "Tokenizer 未加载，无法{}", action)?;
+                match cause {
+                    Some(cause) => write!(f, "（{}）", cause),
+                    None => Ok(()),
+                }
+            }
+            InferenceError::SessionUnsupported { architecture } => write!(
+                f,
+                "{} 架构的模型不支持独立的会话 KV cache，请不要指定 session_id",
+                architecture
+            ),
+            InferenceError::ContextOverflow {
+                prompt_tokens,
+                max_seq_len,
+            } => write!(
+                f,
+                "输入序列长度 {} 超过最大长度 {}",
+                prompt_tokens, max_seq_len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InferenceError {}
+
+impl InferenceError {
+    /// 在错误链中查找推理错误
+    pub fn find(error: &anyhow::Error) -> Option<&InferenceError> {
+        error.chain().find_map(|cause| cause.downcast_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_find_through_context() {
+        let error = Err::<(), _>(InferenceError::ContextOverflow {
+            prompt_tokens: 4096,
+            max_seq_len: 2048,
+        })
+        .context("生成失败")
+        .unwrap_err();
+
+        assert_eq!(
+            InferenceError::find(&error),
+            Some(&InferenceError::ContextOverflow {
+                prompt_tokens: 4096,
+                max_seq_len: 2048,
+            })
+        );
+        assert_eq!(
+            format!("{:#}", error),
+            "生成失败: 输入序列长度 4096 超过最大长度 2048"
+        );
+        assert_eq!(InferenceError::find(&anyhow::anyhow!("其他错误")), None);
+    }
+
+    #[test]
+    fn test_tokenizer_missing_shows_cause() {
+        let missing = InferenceError::TokenizerMissing {
+            action: "执行文本生成",
+            cause: None,
+        };
+        assert_eq!(missing.to_string(), "Tokenizer 未加载，无法执行文本生成");
+
+        let missing = InferenceError::TokenizerMissing {
+            action: "执行文本生成",
+            cause: Some("无法从 GGUF 元数据构建 tokenizer: 缺少词表".to_string()),
+        };
+        assert_eq!(
+            missing.to_string(),
+            "Tokenizer 未加载，无法执行文本生成（无法从 GGUF 元数据构建 tokenizer: 缺少词表）"
+        );
+    }
+}
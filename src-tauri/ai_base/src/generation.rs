@@ -0,0 +1,730 @@
+use crate::sampling::SamplingParams;
+use anyhow::Result;
+use candle_core::{DType, Tensor};
+use serde::{Deserialize, Serialize};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokenizers::Tokenizer;
+
+/// 生成取消令牌
+///
+/// 可在任意线程中克隆并调用 `cancel`，解码循环在每一步都会检查该标记。
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 请求取消生成
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// 是否已请求取消
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// 单次生成的参数
+///
+/// 未设置的采样参数沿用模型配置中的默认值，因此同一个已加载的模型可以按请求使用不同参数。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationParams {
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub top_k: Option<usize>,
+    pub min_p: Option<f64>,
+    pub typical_p: Option<f64>,
+    pub repetition_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    /// 随机种子，相同种子与相同输入得到相同输出
+    pub seed: Option<u64>,
+    /// 停止序列：生成的文本中出现任意一个时停止，结果不包含停止序列
+    pub stop: Vec<String>,
+    /// KV cache 会话标识：同一会话的后续请求以上一次的完整序列开头时复用其 KV cache
+    /// （只有部分相同时从头预填充），不同会话的缓存互不影响；未设置时使用默认会话
+    pub session_id: Option<String>,
+}
+
+impl GenerationParams {
+    /// 在默认采样参数上应用本次请求设置的值
+    pub fn sampling_params(&self, defaults: SamplingParams) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature.unwrap_or(defaults.temperature),
+            top_p: self.top_p.unwrap_or(defaults.top_p),
+            top_k: self.top_k.unwrap_or(defaults.top_k),
+            min_p: self.min_p.unwrap_or(defaults.min_p),
+            typical_p: self.typical_p.unwrap_or(defaults.typical_p),
+            repetition_penalty: self
+                .repetition_penalty
+                .unwrap_or(defaults.repetition_penalty),
+            frequency_penalty: self.frequency_penalty.unwrap_or(defaults.frequency_penalty),
+            presence_penalty: self.presence_penalty.unwrap_or(defaults.presence_penalty),
+            seed: self.seed.or(defaults.seed),
+            ..defaults
+        }
+    }
+}
+
+/// 流式生成过程中每产生一个 token 触发的事件
+///
+/// 可能构成停止序列开头的文本会被暂缓输出。生成结束时如果还有暂缓的文本，
+/// 会以最后一个 token 的 `token_id` 和 `index` 再触发一次事件，
+/// 因此所有事件的 `text` 拼接起来总是等于最终结果。
+#[derive(Debug, Clone)]
+pub struct TokenEvent {
+    /// 本次采样得到的 token id
+    pub token_id: u32,
+    /// 本次新增的可见文本（多字节字符被拆分到多个 token 或文本被暂缓输出时可能为空）
+    pub text: String,
+    /// 已生成的 token 数（从 1 开始）
+    pub index: usize,
+}
+
+/// 生成结束原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinishReason {
+    /// 采样到结束标记
+    Eos,
+    /// 达到最大生成长度
+    Length,
+    /// 生成的文本中出现了停止序列
+    Stop,
+    /// 被取消令牌或回调中止
+    Cancelled,
+}
+
+impl FinishReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FinishReason::Eos => "eos",
+            FinishReason::Length => "length",
+            FinishReason::Stop => "stop",
+            FinishReason::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// 一次生成的完整结果
+#[derive(Debug, Clone)]
+pub struct GenerationOutput {
+    /// 生成的完整文本
+    pub text: String,
+    /// 结束原因
+    pub finish_reason: FinishReason,
+    /// 提示词 token 数
+    pub prompt_tokens: usize,
+    /// 提示词中直接复用 KV cache、无需重新计算的 token 数
+    pub cached_tokens: usize,
+    /// 生成的 token 数
+    pub completion_tokens: usize,
+}
+
+/// 增量解码器
+///
+/// 逐个接收 token 并返回新增文本。当一个多字节 UTF-8 字符被拆分到多个
+/// byte-fallback token 中时，先暂存，直到字符完整后再一并输出。
+pub struct TokenDecoder<'a> {
+    tokenizer: &'a Tokenizer,
+    tokens: Vec<u32>,
+    prefix_offset: usize,
+    read_offset: usize,
+}
+
+impl<'a> TokenDecoder<'a> {
+    pub fn new(tokenizer: &'a Tokenizer) -> Self {
+        Self {
+            tokenizer,
+            tokens: Vec::new(),
+            prefix_offset: 0,
+            read_offset: 0,
+        }
+    }
+
+    fn decode(&self, tokens: &[u32]) -> Result<String> {
+        self.tokenizer
+            .decode(tokens, true)
+            .map_err(|e| anyhow::anyhow!("解码失败: {}", e))
+    }
+
+    /// 追加一个 token，返回可以安全输出的新增文本
+    pub fn next_token(&mut self, token: u32) -> Result<Option<String>> {
+        self.tokens.push(token);
+        let prefix_text = self.decode(&self.tokens[self.prefix_offset..self.read_offset])?;
+        let new_text = self.decode(&self.tokens[self.prefix_offset..])?;
+
+        // 末尾是替换字符说明字节序列还不完整，继续等待后续 token
+        if new_text.len() > prefix_text.len() && !new_text.ends_with('\u{FFFD}') {
+            if let Some(delta) = new_text.get(prefix_text.len()..) {
+                let delta = delta.to_string();
+                self.prefix_offset = self.read_offset;
+                self.read_offset = self.tokens.len();
+                return Ok(Some(delta));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 取出尚未输出的剩余文本（生成结束时调用）
+    pub fn decode_rest(&self) -> Result<Option<String>> {
+        let prefix_text = self.decode(&self.tokens[self.prefix_offset..self.read_offset])?;
+        let new_text = self.decode(&self.tokens[self.prefix_offset..])?;
+        Ok(new_text
+            .get(prefix_text.len()..)
+            .filter(|rest| !rest.is_empty())
+            .map(str::to_string))
+    }
+
+    /// 已接收的全部 token
+    pub fn tokens(&self) -> &[u32] {
+        &self.tokens
+    }
+}
+
+/// 停止序列匹配器
+///
+/// 停止序列可能横跨多个 token。每次追加文本后，只输出确定不会成为停止序列一部分的文本，
+/// 末尾可能是某个停止序列开头的部分会暂缓，直到确认匹配或不匹配。
+pub struct StopMatcher<'a> {
+    stop: &'a [String],
+    pending: String,
+}
+
+impl<'a> StopMatcher<'a> {
+    pub fn new(stop: &'a [String]) -> Self {
+        Self {
+            stop,
+            pending: String::new(),
+        }
+    }
+
+    /// 追加新文本，返回可以输出的文本以及是否命中停止序列（命中时停止序列及其后的文本被丢弃）
+    pub fn push(&mut self, text: &str) -> (String, bool) {
+        self.pending.push_str(text);
+
+        if let Some(pos) = find_stop(&self.pending, self.stop) {
+            self.pending.truncate(pos);
+            return (std::mem::take(&mut self.pending), true);
+        }
+
+        let split = self.pending.len() - self.partial_match_len();
+        let ready = self.pending[..split].to_string();
+        self.pending.drain(..split);
+        (ready, false)
+    }
+
+    /// 生成结束时取出暂缓的文本
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// 暂缓文本末尾与某个停止序列开头重合的最大长度
+    fn partial_match_len(&self) -> usize {
+        self.stop
+            .iter()
+            .filter_map(|stop| {
+                (1..stop.len().min(self.pending.len() + 1))
+                    .rev()
+                    .find(|&len| stop.is_char_boundary(len) && self.pending.ends_with(&stop[..len]))
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// 常见模型用来表示文本或对话轮次结束的特殊 token
+const END_OF_TEXT_TOKENS: &[&str] = &[
+    "<|endoftext|>",
+    "</s>",
+    "<|im_end|>",
+    "<|eot_id|>",
+    "<|end_of_text|>",
+    "<end_of_turn>",
+    "<|end|>",
+    "<eos>",
+];
+
+/// 汇总结束标记 id：模型声明的 id（如 GGUF 的 `tokenizer.ggml.eos_token_id`）
+/// 加上 tokenizer 词表中存在的常见结束 token，去重并保持顺序
+pub fn collect_eos_token_ids(tokenizer: &Tokenizer, declared: &[u32]) -> Vec<u32> {
+    let mut ids = declared.to_vec();
+    for id in END_OF_TEXT_TOKENS
+        .iter()
+        .filter_map(|token| tokenizer.token_to_id(token))
+    {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// 取出最后一个位置的 logits，统一为 F32 的一维 `[vocab_size]` 张量
+///
+/// 模型可能返回 `[batch, vocab]` 或 `[batch, seq_len, vocab]`。
+pub fn last_token_logits(logits: &Tensor) -> Result<Tensor> {
+    let logits = match logits.rank() {
+        1 => logits.clone(),
+        2 => logits.squeeze(0)?,
+        3 => {
+            let seq_len = logits.dim(1)?;
+            logits.narrow(1, seq_len - 1, 1)?.squeeze(1)?.squeeze(0)?
+        }
+        rank => return Err(anyhow::anyhow!("不支持的 logits 维度: {}", rank)),
+    };
+    Ok(logits.to_dtype(DType::F32)?)
+}
+
+/// 通用自回归解码循环
+///
+/// - `cached_tokens`：提示词开头已在 KV cache 中的 token 数（必须小于提示词长度），只对其后的部分做预填充
+/// - `max_seq_len`：模型的上下文长度，生成的 token 数不超过 `max_seq_len - 提示词长度`，
+///   达到上限时以 [`FinishReason::Length`] 结束
+/// - `forward(tokens, index_pos)`：对新输入执行前向传播并返回 logits
+/// - `sample(logits, context)`：从一维 logits 中采样下一个 token，`context` 为提示词与已生成的 token
+/// - `stop`：停止序列，出现时结束生成并从结果中截掉
+/// - `cancel`：每一步解码前检查，已取消时返回目前为止生成的部分文本
+/// - `on_token`：每生成一个 token 调用一次，返回 `ControlFlow::Break` 时停止生成
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn decode_loop<F, S, C>(
+    tokenizer: &Tokenizer,
+    prompt_ids: &[u32],
+    cached_tokens: usize,
+    max_new_tokens: usize,
+    max_seq_len: usize,
+    eos_token_ids: &[u32],
+    stop: &[String],
+    cancel: &CancellationToken,
+    mut forward: F,
+    mut sample: S,
+    mut on_token: C,
+) -> Result<GenerationOutput>
+where
+    F: FnMut(&[u32], usize) -> Result<Tensor>,
+    S: FnMut(&Tensor, &[u32]) -> Result<u32>,
+    C: FnMut(TokenEvent) -> ControlFlow<()>,
+{
+    // 生成的 token 与提示词一起占用上下文，不能超出模型的上下文窗口
+    let max_new_tokens = max_new_tokens.min(max_seq_len.saturating_sub(prompt_ids.len()));
+    let mut decoder = TokenDecoder::new(tokenizer);
+    let mut context = prompt_ids.to_vec();
+    let mut stop_matcher = StopMatcher::new(stop);
+    // 已通过回调输出的文本，即最终结果
+    let mut text = String::new();
+    let mut finish_reason = FinishReason::Length;
+
+    if cancel.is_cancelled() {
+        return Ok(GenerationOutput {
+            text: String::new(),
+            finish_reason: FinishReason::Cancelled,
+            prompt_tokens: prompt_ids.len(),
+            cached_tokens: 0,
+            completion_tokens: 0,
+        });
+    }
+
+    // 初始前向传播处理输入序列，KV cache 中已有的前缀跳过
+    let mut logits = last_token_logits(&forward(&prompt_ids[cached_tokens..], cached_tokens)?)?;
+    let mut index_pos = prompt_ids.len();
+
+    while decoder.tokens().len() < max_new_tokens {
+        if cancel.is_cancelled() {
+            finish_reason = FinishReason::Cancelled;
+            break;
+        }
+
+        let next_token = sample(&logits, &context)?;
+
+        // 检查是否到达结束标记
+        if eos_token_ids.contains(&next_token) {
+            finish_reason = FinishReason::Eos;
+            break;
+        }
+
+        context.push(next_token);
+        let delta = decoder.next_token(next_token)?.unwrap_or_default();
+        let (ready, hit_stop) = stop_matcher.push(&delta);
+        text.push_str(&ready);
+        let event = TokenEvent {
+            token_id: next_token,
+            text: ready,
+            index: decoder.tokens().len(),
+        };
+        if on_token(event).is_break() {
+            finish_reason = FinishReason::Cancelled;
+            break;
+        }
+        if hit_stop {
+            finish_reason = FinishReason::Stop;
+            break;
+        }
+
+        // 已达到最大长度时无需再做一次前向传播
+        if decoder.tokens().len() >= max_new_tokens {
+            break;
+        }
+
+        // 前向传播（只处理新生成的 token，利用 KV cache）
+        logits = last_token_logits(&forward(&[next_token], index_pos)?)?;
+        index_pos += 1;
+    }
+
+    // 输出解码器和停止序列匹配器中暂缓的剩余文本
+    if finish_reason != FinishReason::Stop {
+        let rest = decoder.decode_rest()?.unwrap_or_default();
+        let (mut ready, hit_stop) = stop_matcher.push(&rest);
+        if hit_stop {
+            if finish_reason != FinishReason::Cancelled {
+                finish_reason = FinishReason::Stop;
+            }
+        } else {
+            ready.push_str(&stop_matcher.finish());
+        }
+        if let (false, Some(&token_id)) = (ready.is_empty(), decoder.tokens().last()) {
+            text.push_str(&ready);
+            let _ = on_token(TokenEvent {
+                token_id,
+                text: ready,
+                index: decoder.tokens().len(),
+            });
+        }
+    }
+
+    Ok(GenerationOutput {
+        text,
+        finish_reason,
+        prompt_tokens: prompt_ids.len(),
+        cached_tokens,
+        completion_tokens: decoder.tokens().len(),
+    })
+}
+
+/// 查找最早出现的停止序列，返回其字节位置
+fn find_stop(text: &str, stop: &[String]) -> Option<usize> {
+    stop.iter()
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| text.find(s.as_str()))
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candle_core::Device;
+
+    /// 带 byte-fallback 的最小 tokenizer："你" = E4 BD A0
+    fn test_tokenizer() -> Tokenizer {
+        let json = r#"{
+            "version": "1.0",
+            "added_tokens": [
+                {"id": 5, "content": "</s>", "single_word": false, "lstrip": false,
+                 "rstrip": false, "normalized": false, "special": true}
+            ],
+            "model": {
+                "type": "BPE",
+                "byte_fallback": true,
+                "vocab": {"<0xE4>": 0, "<0xBD>": 1, "<0xA0>": 2, "h": 3, "i": 4, "</s>": 5},
+                "merges": []
+            },
+            "decoder": {
+                "type": "Sequence",
+                "decoders": [{"type": "ByteFallback"}, {"type": "Fuse"}]
+            }
+        }"#;
+        Tokenizer::from_bytes(json).unwrap()
+    }
+
+    #[test]
+    fn test_decoder_holds_back_partial_utf8() {
+        let tokenizer = test_tokenizer();
+        let mut decoder = TokenDecoder::new(&tokenizer);
+        assert_eq!(decoder.next_token(3).unwrap().as_deref(), Some("h"));
+        assert_eq!(decoder.next_token(0).unwrap(), None);
+        assert_eq!(decoder.next_token(1).unwrap(), None);
+        assert_eq!(decoder.next_token(2).unwrap().as_deref(), Some("你"));
+        assert_eq!(decoder.next_token(4).unwrap().as_deref(), Some("i"));
+        assert_eq!(decoder.decode_rest().unwrap(), None);
+    }
+
+    /// 按脚本依次输出 token 的假模型：logits 在下一个脚本 token 处最大
+    fn scripted_forward(script: Vec<u32>) -> impl FnMut(&[u32], usize) -> Result<Tensor> {
+        let mut step = 0;
+        move |_tokens, _index_pos| {
+            let mut logits = vec![0f32; 6];
+            logits[script[step.min(script.len() - 1)] as usize] = 10.0;
+            step += 1;
+            Ok(Tensor::new(logits.as_slice(), &Device::Cpu)?.unsqueeze(0)?)
+        }
+    }
+
+    fn argmax(logits: &Tensor, _context: &[u32]) -> Result<u32> {
+        Ok(logits.argmax(0)?.to_scalar::<u32>()?)
+    }
+
+    #[test]
+    fn test_decode_loop_streams_and_stops_on_eos() {
+        let tokenizer = test_tokenizer();
+        let mut streamed = String::new();
+        let output = decode_loop(
+            &tokenizer,
+            &[3],
+            0,
+            16,
+            4096,
+            &[5],
+            &[],
+            &CancellationToken::new(),
+            scripted_forward(vec![4, 0, 1, 2, 5]),
+            argmax,
+            |event| {
+                streamed.push_str(&event.text);
+                ControlFlow::Continue(())
+            },
+        )
+        .unwrap();
+        assert_eq!(output.text, "i你");
+        assert_eq!(streamed, output.text);
+        assert_eq!(output.finish_reason, FinishReason::Eos);
+        assert_eq!(output.prompt_tokens, 1);
+        assert_eq!(output.completion_tokens, 4);
+    }
+
+    #[test]
+    fn test_decode_loop_length_and_break() {
+        let tokenizer = test_tokenizer();
+        let output = decode_loop(
+            &tokenizer,
+            &[3],
+            0,
+            2,
+            4096,
+            &[5],
+            &[],
+            &CancellationToken::new(),
+            scripted_forward(vec![3, 4, 3, 4]),
+            argmax,
+            |_| ControlFlow::Continue(()),
+        )
+        .unwrap();
+        assert_eq!(output.text, "hi");
+        assert_eq!(output.finish_reason, FinishReason::Length);
+
+        let output = decode_loop(
+            &tokenizer,
+            &[3],
+            0,
+            16,
+            4096,
+            &[5],
+            &[],
+            &CancellationToken::new(),
+            scripted_forward(vec![3, 4, 3, 4]),
+            argmax,
+            |event| {
+                if event.index == 3 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            },
+        )
+        .unwrap();
+        assert_eq!(output.text, "hih");
+        assert_eq!(output.finish_reason, FinishReason::Cancelled);
+    }
+
+    #[test]
+    fn test_decode_loop_stops_at_context_window() {
+        let tokenizer = test_tokenizer();
+        // 提示词 2 个 token、上下文长度 5：最多生成 3 个 token，即使 max_new_tokens 更大
+        let mut positions = Vec::new();
+        let mut forward = scripted_forward(vec![3, 4, 3, 4, 3, 4]);
+        let output = decode_loop(
+            &tokenizer,
+            &[3, 4],
+            0,
+            16,
+            5,
+            &[5],
+            &[],
+            &CancellationToken::new(),
+            |tokens, index_pos| {
+                positions.push(index_pos + tokens.len());
+                forward(tokens, index_pos)
+            },
+            argmax,
+            |_| ControlFlow::Continue(()),
+        )
+        .unwrap();
+        assert_eq!(output.text, "hih");
+        assert_eq!(output.completion_tokens, 3);
+        assert_eq!(output.finish_reason, FinishReason::Length);
+        // 前向传播处理过的序列从未超过上下文长度
+        assert!(positions.iter().all(|&len| len <= 5));
+    }
+
+    #[test]
+    fn test_decode_loop_stop_sequence() {
+        let tokenizer = test_tokenizer();
+        let output = decode_loop(
+            &tokenizer,
+            &[3],
+            0,
+            16,
+            4096,
+            &[5],
+            &["ih".to_string()],
+            &CancellationToken::new(),
+            scripted_forward(vec![3, 3, 4, 3, 4]),
+            argmax,
+            |_| ControlFlow::Continue(()),
+        )
+        .unwrap();
+        assert_eq!(output.text, "hh");
+        assert_eq!(output.finish_reason, FinishReason::Stop);
+        assert_eq!(output.completion_tokens, 4);
+    }
+
+    #[test]
+    fn test_stop_sequence_across_tokens_is_held_back() {
+        let tokenizer = test_tokenizer();
+        let mut events = Vec::new();
+        let output = decode_loop(
+            &tokenizer,
+            &[3],
+            0,
+            16,
+            4096,
+            &[5],
+            &["hi".to_string()],
+            &CancellationToken::new(),
+            scripted_forward(vec![4, 3, 4, 3]),
+            argmax,
+            |event| {
+                events.push(event.text);
+                ControlFlow::Continue(())
+            },
+        )
+        .unwrap();
+        // "h" 可能是 "hi" 的开头，先暂缓；下一个 token 凑成 "hi" 后整段丢弃
+        assert_eq!(events, vec!["i", "", ""]);
+        assert_eq!(output.text, "i");
+        assert_eq!(output.finish_reason, FinishReason::Stop);
+    }
+
+    #[test]
+    fn test_held_back_text_is_flushed_at_eos() {
+        let tokenizer = test_tokenizer();
+        let mut streamed = String::new();
+        let output = decode_loop(
+            &tokenizer,
+            &[3],
+            0,
+            16,
+            4096,
+            &[5],
+            &["hx".to_string()],
+            &CancellationToken::new(),
+            scripted_forward(vec![4, 3, 5]),
+            argmax,
+            |event| {
+                streamed.push_str(&event.text);
+                ControlFlow::Continue(())
+            },
+        )
+        .unwrap();
+        assert_eq!(output.text, "ih");
+        assert_eq!(streamed, "ih");
+        assert_eq!(output.finish_reason, FinishReason::Eos);
+    }
+
+    #[test]
+    fn test_stop_matcher_multibyte_prefix() {
+        let stop = vec!["你好".to_string(), "END".to_string()];
+        let mut matcher = StopMatcher::new(&stop);
+        assert_eq!(matcher.push("ab你"), ("ab".to_string(), false));
+        assert_eq!(matcher.push("们E"), ("你们".to_string(), false));
+        assert_eq!(matcher.push("ND!"), (String::new(), true));
+    }
+
+    #[test]
+    fn test_collect_eos_token_ids() {
+        let tokenizer = test_tokenizer();
+        assert_eq!(collect_eos_token_ids(&tokenizer, &[]), vec![5]);
+        assert_eq!(collect_eos_token_ids(&tokenizer, &[4, 5]), vec![4, 5]);
+    }
+
+    #[test]
+    fn test_generation_params_override_defaults() {
+        let params = GenerationParams {
+            temperature: Some(0.0),
+            seed: Some(7),
+            ..Default::default()
+        };
+        let sampling = params.sampling_params(SamplingParams::default());
+        assert_eq!(sampling.temperature, 0.0);
+        assert_eq!(sampling.seed, Some(7));
+        assert_eq!(sampling.top_k, SamplingParams::default().top_k);
+    }
+
+    #[test]
+    fn test_decode_loop_cancellation_keeps_partial_text() {
+        let tokenizer = test_tokenizer();
+        let cancel = CancellationToken::new();
+        let output = decode_loop(
+            &tokenizer,
+            &[3],
+            0,
+            16,
+            4096,
+            &[5],
+            &[],
+            &cancel,
+            scripted_forward(vec![3, 4, 3, 4]),
+            argmax,
+            |event| {
+                if event.index == 2 {
+                    cancel.cancel();
+                }
+                ControlFlow::Continue(())
+            },
+        )
+        .unwrap();
+        assert_eq!(output.text, "hi");
+        assert_eq!(output.finish_reason, FinishReason::Cancelled);
+        assert_eq!(output.completion_tokens, 2);
+    }
+
+    #[test]
+    fn test_decode_loop_prefills_only_uncached_suffix() {
+        let tokenizer = test_tokenizer();
+        let mut calls = Vec::new();
+        let mut script = scripted_forward(vec![4, 5]);
+        let output = decode_loop(
+            &tokenizer,
+            &[3, 4, 3, 4],
+            3,
+            16,
+            4096,
+            &[5],
+            &[],
+            &CancellationToken::new(),
+            |tokens, index_pos| {
+                calls.push((tokens.to_vec(), index_pos));
+                script(tokens, index_pos)
+            },
+            argmax,
+            |_| ControlFlow::Continue(()),
+        )
+        .unwrap();
+        assert_eq!(calls, vec![(vec![4], 3), (vec![4], 4)]);
+        assert_eq!(output.prompt_tokens, 4);
+        assert_eq!(output.cached_tokens, 3);
+    }
+}
@@ -1,19 +1,36 @@
 use anyhow::{Context, Result};
 use candle_core::quantized::gguf_file;
 use candle_core::{Device, Tensor};
-use candle_nn;
+use candle_transformers::models::quantized_gemma3::ModelWeights as Gemma3Models;
 use candle_transformers::models::quantized_llama::ModelWeights as LlamaModels;
+use candle_transformers::models::quantized_phi3::ModelWeights as Phi3Models;
+use candle_transformers::models::quantized_qwen2::ModelWeights as Qwen2Models;
 use candle_transformers::models::quantized_qwen3::ModelWeights as Qwen3Models;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::path::PathBuf;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use tokenizers::Tokenizer;
 
+use crate::chat::{ChatMessage, ChatTemplate};
+use crate::error::InferenceError;
+use crate::generation::{
+    collect_eos_token_ids, decode_loop, CancellationToken, GenerationOutput, GenerationParams,
+    TokenEvent,
+};
+use crate::gguf_tokenizer::tokenizer_from_gguf;
+use crate::memory::estimate_gguf_bytes;
+use crate::progress::{ignore_progress, LoadProgress, ProgressCallback, ProgressReader};
+use crate::sampling::{Sampler, SamplingParams};
+
 /// GGUF 模型配置
 #[derive(Debug, Clone)]
 pub struct GGUFConfig {
     /// 模型路径（本地文件路径或 HuggingFace 模型标识符）
     pub model_path: PathBuf,
-    /// Tokenizer 路径
+    /// Tokenizer 路径（为 None 时从 GGUF 内嵌词表构建）
     pub tokenizer_path: Option<PathBuf>,
     /// HuggingFace 模型仓库标识符（如果从 HF Hub 下载）
     pub hf_repo: Option<String>,
@@ -27,8 +44,12 @@ pub struct GGUFConfig {
     pub top_p: f64,
     /// Top-k sampling parameter
     pub top_k: usize,
-    /// Model architecture ("llama" or "qwen3")
+    /// Random seed for sampling (None for a random seed)
+    pub seed: Option<u64>,
+    /// 模型架构（见 [`SUPPORTED_ARCHITECTURES`]），为 None 时读取 GGUF 头部的 `general.architecture`
     pub architecture: Option<String>,
+    /// 最多保留的 KV cache 会话数，超出时淘汰最久未使用的会话
+    pub max_sessions: usize,
 }
 
 impl Default for GGUFConfig {
@@ -42,32 +63,219 @@ impl Default for GGUFConfig {
             temperature: 0.8,
             top_p: 0.9,
             top_k: 40,
+            seed: None,
             architecture: None,
+            max_sessions: 4,
+        }
+    }
+}
+
+impl GGUFConfig {
+    /// 由配置中的采样参数构建 [`SamplingParams`]
+    pub fn sampling_params(&self) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature,
+            top_k: self.top_k,
+            top_p: self.top_p,
+            seed: self.seed,
+            ..Default::default()
         }
     }
 }
 
+/// 支持的 GGUF 架构（`general.architecture` 的取值）
+///
+/// Gemma 系列只支持 Gemma 3：candle 没有 Gemma / Gemma 2 的量化实现，
+/// `gemma`、`gemma2` 的 GGUF 会返回不支持的错误。
+pub const SUPPORTED_ARCHITECTURES: &[&str] =
+    &["llama", "mistral", "qwen2", "qwen3", "phi3", "gemma3"];
+
+/// 确定要加载的架构：优先使用配置中指定的值，否则读取 GGUF 头部的 `general.architecture`
+fn resolve_architecture(configured: Option<&str>, ct: &gguf_file::Content) -> Result<String> {
+    let architecture = match configured {
+        Some(architecture) => architecture.to_lowercase(),
+        None => ct
+            .metadata
+            .get("general.architecture")
+            .and_then(|v| v.to_string().ok())
+            .context("GGUF 头部缺少 general.architecture，请手动指定模型架构")?
+            .to_lowercase(),
+    };
+    // qwen3vl 的语言模型部分与 qwen3 相同
+    let architecture = match architecture.as_str() {
+        "qwen3vl" => "qwen3".to_string(),
+        _ => architecture,
+    };
+    if matches!(architecture.as_str(), "gemma" | "gemma2") {
+        anyhow::bail!(
+            "不支持的模型架构: {}，Gemma 系列目前只支持 Gemma 3（gemma3）",
+            architecture
+        );
+    }
+    if !SUPPORTED_ARCHITECTURES.contains(&architecture.as_str()) {
+        anyhow::bail!(
+            "不支持的模型架构: {}，目前支持: {}",
+            architecture,
+            SUPPORTED_ARCHITECTURES.join(", ")
+        );
+    }
+    Ok(architecture)
+}
+
 /// Enum for different GGUF model architectures
 pub enum GGUFModel {
+    /// Llama 系列，同时用于 Mistral
     Llama(LlamaModels),
+    Qwen2(Qwen2Models),
     Qwen3(Qwen3Models),
+    Phi3(Phi3Models),
+    Gemma3(Gemma3Models),
 }
 
 impl GGUFModel {
+    /// 按架构从 GGUF 内容加载模型权重
+    fn from_gguf<R: std::io::Read + std::io::Seek>(
+        architecture: &str,
+        mut ct: gguf_file::Content,
+        file: &mut R,
+        device: &Device,
+    ) -> Result<Self> {
+        let model = match architecture {
+            "llama" => Self::Llama(LlamaModels::from_gguf(ct, file, device)?),
+            "mistral" => {
+                // Mistral 与 Llama 结构相同，只是元数据使用 mistral. 前缀
+                ct.metadata = ct
+                    .metadata
+                    .into_iter()
+                    .map(|(key, value)| match key.strip_prefix("mistral.") {
+                        Some(rest) => (format!("llama.{}", rest), value),
+                        None => (key, value),
+                    })
+                    .collect();
+                Self::Llama(LlamaModels::from_gguf(ct, file, device)?)
+            }
+            "qwen2" => Self::Qwen2(Qwen2Models::from_gguf(ct, file, device)?),
+            "qwen3" => Self::Qwen3(Qwen3Models::from_gguf(ct, file, device)?),
+            "phi3" => Self::Phi3(Phi3Models::from_gguf(false, ct, file, device)?),
+            "gemma3" => Self::Gemma3(Gemma3Models::from_gguf(ct, file, device)?),
+            other => anyhow::bail!(
+                "不支持的模型架构: {}，目前支持: {}",
+                other,
+                SUPPORTED_ARCHITECTURES.join(", ")
+            ),
+        };
+        Ok(model)
+    }
+
     pub fn forward(&mut self, x: &Tensor, index_pos: usize) -> Result<Tensor> {
+        let logits = match self {
+            Self::Llama(m) => m.forward(x, index_pos),
+            Self::Qwen2(m) => m.forward(x, index_pos),
+            Self::Qwen3(m) => {
+                // Qwen3 的 KV cache 总是追加，新序列开始时需要手动清空
+                if index_pos == 0 {
+                    m.clear_kv_cache();
+                }
+                m.forward(x, index_pos)
+            }
+            Self::Phi3(m) => m.forward(x, index_pos),
+            Self::Gemma3(m) => m.forward(x, index_pos),
+        };
+        logits.map_err(|e| anyhow::anyhow!(e))
+    }
+
+    /// 架构名（[`SUPPORTED_ARCHITECTURES`] 中的取值，Mistral 为 `llama`）
+    fn architecture(&self) -> &'static str {
+        match self {
+            Self::Llama(_) => "llama",
+            Self::Qwen2(_) => "qwen2",
+            Self::Qwen3(_) => "qwen3",
+            Self::Phi3(_) => "phi3",
+            Self::Gemma3(_) => "gemma3",
+        }
+    }
+
+    /// 是否可以复制模型，为每个会话保存独立的 KV cache（与 [`Self::try_clone`] 一致）
+    fn supports_sessions(&self) -> bool {
+        !matches!(self, Self::Qwen2(_))
+    }
+
+    /// 复制一份模型供独立的会话使用
+    ///
+    /// 副本共享量化权重，只复制各层的 KV cache。candle 的 Qwen2 实现不支持克隆，返回 `None`，
+    /// 此时只能使用默认会话，指定 `session_id` 的请求返回 [`InferenceError::SessionUnsupported`]。
+    fn try_clone(&self) -> Option<Self> {
         match self {
-            Self::Llama(m) => m.forward(x, index_pos).map_err(|e| anyhow::anyhow!(e)),
-            Self::Qwen3(m) => m.forward(x, index_pos).map_err(|e| anyhow::anyhow!(e)),
+            Self::Llama(m) => Some(Self::Llama(m.clone())),
+            Self::Qwen2(_) => None,
+            Self::Qwen3(m) => Some(Self::Qwen3(m.clone())),
+            Self::Phi3(m) => Some(Self::Phi3(m.clone())),
+            Self::Gemma3(m) => Some(Self::Gemma3(m.clone())),
         }
     }
 }
 
+/// KV cache 会话
+///
+/// 持有一份模型副本以及其 KV cache 中对应的 token 序列。candle 的 KV cache 只能追加或从头重建，
+/// 因此只有缓存序列整体是新提示词的前缀时才能复用，否则从位置 0 重新预填充。
+/// 模型不支持克隆时只有默认会话，其 `model` 为 `None`，直接使用引擎中的模型。
+struct KvSession {
+    model: Option<GGUFModel>,
+    tokens: Vec<u32>,
+    last_used: Instant,
+}
+
+/// 计算可复用的 KV cache 长度
+///
+/// 缓存序列必须整体是新提示词的前缀，并且至少留下一个 token 用于计算下一个 token 的 logits。
+/// 不支持部分前缀复用：新提示词只与缓存序列的开头一部分相同时（例如编辑或重新生成了中间的消息），
+/// 返回 0 并从头预填充，而不是截断 KV cache 保留公共前缀。
+fn reusable_prefix_len(cached: &[u32], prompt: &[u32]) -> usize {
+    if cached.len() < prompt.len() && prompt.starts_with(cached) {
+        cached.len()
+    } else {
+        0
+    }
+}
+
+/// 未指定会话时使用的会话标识
+const DEFAULT_SESSION: &str = "";
+
 /// GGUF 量化模型推理引擎
 pub struct GGUFInferenceEngine {
     device: Device,
     model: GGUFModel,
     tokenizer: Option<Tokenizer>,
+    /// 未加载 tokenizer 的原因
+    tokenizer_error: Option<String>,
     config: GGUFConfig,
+    /// GGUF 元数据中声明的结束标记 id
+    eos_token_ids: Vec<u32>,
+    /// 对话模板（加载了 tokenizer 时可用）
+    chat_template: Option<ChatTemplate>,
+    /// 按会话标识保存的 KV cache
+    sessions: HashMap<String, KvSession>,
+    /// 最近一次使用引擎自身模型（及其 KV cache）的会话
+    shared_cache_owner: Option<String>,
+}
+
+/// 读取 GGUF 元数据中声明的结束标记（`tokenizer.ggml.eos_token_id`，以及部分对话模型的
+/// `tokenizer.ggml.eot_token_id`）
+fn eos_token_ids_from_metadata(ct: &gguf_file::Content) -> Vec<u32> {
+    let mut ids = Vec::new();
+    for key in ["tokenizer.ggml.eos_token_id", "tokenizer.ggml.eot_token_id"] {
+        let id = ct.metadata.get(key).and_then(|value| {
+            value
+                .to_u32()
+                .ok()
+                .or_else(|| value.to_i32().ok().and_then(|v| u32::try_from(v).ok()))
+        });
+        if let Some(id) = id.filter(|id| !ids.contains(id)) {
+            ids.push(id);
+        }
+    }
+    ids
 }
 
 impl GGUFInferenceEngine {
@@ -78,57 +286,93 @@ impl GGUFInferenceEngine {
 
     /// 从本地文件加载 GGUF 模型（支持指定设备）
     pub fn from_file_with_device(config: GGUFConfig, device: Option<Device>) -> Result<Self> {
-        let device =
-            device.unwrap_or_else(|| Device::cuda_if_available(0).unwrap_or_else(|_| Device::Cpu));
+        Self::from_file_with_progress(config, device, &ignore_progress)
+    }
+
+    /// 从本地文件加载 GGUF 模型，并通过 `on_progress` 报告各加载阶段
+    pub fn from_file_with_progress(
+        config: GGUFConfig,
+        device: Option<Device>,
+        on_progress: ProgressCallback<'_>,
+    ) -> Result<Self> {
+        let device = device.unwrap_or_else(|| Device::cuda_if_available(0).unwrap_or(Device::Cpu));
 
         // 打开 GGUF 文件
         let mut file = File::open(&config.model_path)
             .with_context(|| format!("无法打开模型文件: {:?}", config.model_path))?;
+        on_progress(LoadProgress::Open {
+            path: config.model_path.clone(),
+            file_bytes: file.metadata().map(|m| m.len()).unwrap_or(0),
+        });
 
         // 读取 GGUF 内容
         let ct = gguf_file::Content::read(&mut file).with_context(|| {
             format!("无法读取 GGUF 文件内容，文件路径: {:?}", config.model_path)
         })?;
-
-        // Load model weights based on architecture or default to Llama
-        let architecture = config.architecture.as_deref().unwrap_or("llama");
-
-        let model = match architecture {
-            "qwen3" | "qwen3vl" => {
-                let model = Qwen3Models::from_gguf(ct, &mut file, &device)
-                    .map_err(|e| anyhow::anyhow!("Failed to load Qwen3 GGUF model: {}", e))?;
-                GGUFModel::Qwen3(model)
-            }
-            _ => {
-                // Default to Llama
-                let model = LlamaModels::from_gguf(ct, &mut file, &device).map_err(|e| {
-                    anyhow::anyhow!(
-                        "Failed to load Llama GGUF model: {}\n\
-                            Architecture used: {}\n\
-                            If this is not a Llama model, please specify the architecture.",
-                        e,
-                        architecture
-                    )
-                })?;
-                GGUFModel::Llama(model)
-            }
+        on_progress(LoadProgress::Header {
+            tensor_count: ct.tensor_infos.len(),
+            total_bytes: estimate_gguf_bytes(&ct),
+        });
+
+        let architecture = resolve_architecture(config.architecture.as_deref(), &ct)?;
+        let eos_token_ids = eos_token_ids_from_metadata(&ct);
+        let gguf_chat_template = ChatTemplate::from_gguf_metadata(&ct);
+
+        // 加载 tokenizer：优先使用指定的 tokenizer.json，否则从 GGUF 内嵌词表构建
+        on_progress(LoadProgress::Tokenizer);
+        // 内嵌词表无法构建时仍然加载模型（可以执行前向传播测试），
+        // 失败原因随之后的 TokenizerMissing 错误返回
+        let (tokenizer, tokenizer_error) = match config.tokenizer_path {
+            Some(ref tokenizer_path) => (
+                Some(
+                    Tokenizer::from_file(tokenizer_path)
+                        .map_err(|e| anyhow::anyhow!("无法加载 tokenizer: {}", e))?,
+                ),
+                None,
+            ),
+            None => match tokenizer_from_gguf(&ct) {
+                Ok(tokenizer) => (Some(tokenizer), None),
+                Err(e) => (
+                    None,
+                    Some(format!("无法从 GGUF 元数据构建 tokenizer: {:#}", e)),
+                ),
+            },
         };
 
-        // 加载 tokenizer（如果提供了路径）
-        let tokenizer = if let Some(ref tokenizer_path) = config.tokenizer_path {
-            Some(
-                Tokenizer::from_file(tokenizer_path)
-                    .map_err(|e| anyhow::anyhow!("无法加载 tokenizer: {}", e))?,
-            )
-        } else {
-            None
+        // 按架构加载模型权重，逐个张量报告进度
+        let mut reader = ProgressReader::new(&mut file, &ct, on_progress);
+        let model = GGUFModel::from_gguf(&architecture, ct, &mut reader, &device)
+            .with_context(|| format!("无法加载 {} 架构的 GGUF 模型", architecture))?;
+
+        // 对话模板：GGUF 头部 > tokenizer 同目录的 tokenizer_config.json > 内置模板
+        let chat_template = match (gguf_chat_template, &tokenizer) {
+            (Some(template), _) => Some(template),
+            (None, Some(tokenizer)) => {
+                let config_template = config
+                    .tokenizer_path
+                    .as_ref()
+                    .and_then(|path| path.parent())
+                    .map(|dir| dir.join("tokenizer_config.json"))
+                    .filter(|path| path.exists())
+                    .map(|path| ChatTemplate::from_tokenizer_config(&path))
+                    .transpose()?
+                    .flatten();
+                Some(config_template.unwrap_or_else(|| ChatTemplate::builtin_for(tokenizer)))
+            }
+            (None, None) => None,
         };
+        on_progress(LoadProgress::Done);
 
         Ok(Self {
             device,
             model,
             tokenizer,
+            tokenizer_error,
             config,
+            eos_token_ids,
+            chat_template,
+            sessions: HashMap::new(),
+            shared_cache_owner: None,
         })
     }
 
@@ -142,6 +386,24 @@ impl GGUFInferenceEngine {
         Self::from_hf_hub_with_device(hf_repo, hf_filename, tokenizer_path, architecture, None)
     }
 
+    /// 从 HuggingFace Hub 下载 GGUF 文件（已缓存时直接返回），返回本地路径
+    pub fn download_from_hf_hub(hf_repo: &str, hf_filename: &str) -> Result<PathBuf> {
+        use hf_hub::api::sync::Api;
+
+        // 创建 HuggingFace API
+        let api = Api::new().context("无法创建 HuggingFace API")?;
+
+        // 获取模型仓库并下载模型文件
+        api.model(hf_repo.to_string())
+            .get(hf_filename)
+            .with_context(|| {
+                format!(
+                    "无法从 HuggingFace Hub 下载模型: {}/{}",
+                    hf_repo, hf_filename
+                )
+            })
+    }
+
     /// 从 HuggingFace Hub 下载并加载 GGUF 模型（支持指定设备）
     pub fn from_hf_hub_with_device(
         hf_repo: impl Into<String>,
@@ -150,24 +412,9 @@ impl GGUFInferenceEngine {
         architecture: Option<String>,
         device: Option<Device>,
     ) -> Result<Self> {
-        use hf_hub::api::sync::Api;
-
         let hf_repo_str = hf_repo.into();
         let hf_filename_str = hf_filename.into();
-
-        // 创建 HuggingFace API
-        let api = Api::new().context("无法创建 HuggingFace API")?;
-
-        // 获取模型仓库
-        let repo = api.model(hf_repo_str.clone());
-
-        // 下载模型文件
-        let model_path = repo.get(&hf_filename_str).with_context(|| {
-            format!(
-                "无法从 HuggingFace Hub 下载模型: {}/{}",
-                hf_repo_str, hf_filename_str
-            )
-        })?;
+        let model_path = Self::download_from_hf_hub(&hf_repo_str, &hf_filename_str)?;
 
         let config = GGUFConfig {
             model_path: model_path.clone(),
@@ -183,6 +430,8 @@ impl GGUFInferenceEngine {
 
     /// 执行前向传播
     pub fn forward(&mut self, input_ids: &Tensor, index_pos: usize) -> Result<Tensor> {
+        // 直接使用引擎模型会覆盖共用的 KV cache
+        self.shared_cache_owner = None;
         self.model
             .forward(input_ids, index_pos)
             .context("模型前向传播失败")
@@ -190,104 +439,211 @@ impl GGUFInferenceEngine {
 
     /// 执行文本生成推理
     pub fn generate(&mut self, prompt: &str, max_new_tokens: usize) -> Result<String> {
-        // 提前提取所有需要的信息，避免借用冲突
-        let (input_ids, eos_token_id) = {
-            let tokenizer = self
-                .tokenizer
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("Tokenizer 未加载，无法执行文本生成"))?;
-
-            // 编码输入文本
-            let tokens = tokenizer
-                .encode(prompt, true)
-                .map_err(|e| anyhow::anyhow!("编码失败: {}", e))?;
-
-            let input_ids: Vec<u32> = tokens.get_ids().iter().map(|&id| id as u32).collect();
-            let input_len = input_ids.len();
-
-            if input_len > self.config.max_seq_len {
-                return Err(anyhow::anyhow!(
-                    "输入序列长度 {} 超过最大长度 {}",
-                    input_len,
-                    self.config.max_seq_len
-                ));
-            }
+        let output = self.generate_stream(
+            prompt,
+            max_new_tokens,
+            &GenerationParams::default(),
+            &CancellationToken::new(),
+            |_| ControlFlow::Continue(()),
+        )?;
+        Ok(output.text)
+    }
 
-            // 获取结束标记 ID
-            let eos_token_id = tokenizer
-                .token_to_id("<|endoftext|>")
-                .or_else(|| tokenizer.token_to_id("</s>"))
-                .or_else(|| tokenizer.token_to_id("<|im_end|>"))
-                .unwrap_or_else(|| {
-                    let vocab_size = tokenizer.get_vocab_size(true);
-                    vocab_size.try_into().unwrap_or(u32::MAX)
-                });
-
-            (input_ids, eos_token_id)
-        };
+    /// 流式文本生成
+    ///
+    /// 每生成一个 token 调用一次 `on_token`，事件中的 `text` 为增量解码后的新增文本；
+    /// 回调返回 `ControlFlow::Break(())` 或 `cancel` 被取消时提前结束生成，并返回已生成的部分文本。
+    /// `params` 中未设置的采样参数使用 [`GGUFConfig`] 中的值。
+    pub fn generate_stream<F>(
+        &mut self,
+        prompt: &str,
+        max_new_tokens: usize,
+        params: &GenerationParams,
+        cancel: &CancellationToken,
+        on_token: F,
+    ) -> Result<GenerationOutput>
+    where
+        F: FnMut(TokenEvent) -> ControlFlow<()>,
+    {
+        self.generate_from_prompt(prompt, true, max_new_tokens, params, cancel, on_token)
+    }
 
-        let input_len = input_ids.len();
+    /// 使用模型的对话模板渲染消息后流式生成助手回复
+    ///
+    /// 渲染结果已包含模板需要的特殊 token（如 BOS），编码时不再额外添加。
+    pub fn chat_stream<F>(
+        &mut self,
+        messages: &[ChatMessage],
+        max_new_tokens: usize,
+        params: &GenerationParams,
+        cancel: &CancellationToken,
+        on_token: F,
+    ) -> Result<GenerationOutput>
+    where
+        F: FnMut(TokenEvent) -> ControlFlow<()>,
+    {
+        let prompt = self.apply_chat_template(messages)?;
+        self.generate_from_prompt(&prompt, false, max_new_tokens, params, cancel, on_token)
+    }
 
-        // 转换为 Tensor（在独立作用域中借用 device）
-        let input_tensor = {
-            let device = &self.device;
-            Tensor::new(input_ids.as_slice(), device)?.unsqueeze(0)?
-        };
+    /// 无法执行 `action` 时返回的错误，带有未加载 tokenizer 的原因
+    fn tokenizer_missing(&self, action: &'static str) -> InferenceError {
+        InferenceError::TokenizerMissing {
+            action,
+            cause: self.tokenizer_error.clone(),
+        }
+    }
 
-        // 执行推理
-        let mut generated_tokens = Vec::new();
-        let mut index_pos = 0;
-
-        // 初始前向传播处理输入序列
-        let logits = self.forward(&input_tensor, index_pos)?;
-        index_pos += input_len;
-
-        // 获取最后一个 token 的 logits 并生成第一个 token
-        let seq_len = logits.dim(1)?;
-        let mut last_logits = logits.narrow(1, seq_len - 1, 1)?.squeeze(1)?;
-
-        // 生成循环
-        for _ in 0..max_new_tokens {
-            // 采样下一个 token（在独立作用域中借用 config，避免与后续的 &mut self 冲突）
-            let next_token = {
-                let config = &self.config;
-                sample_token_from_logits(&last_logits, config)?
-            };
-
-            // 检查是否到达结束标记
-            if next_token == eos_token_id {
-                break;
-            }
+    /// 将对话渲染为提示词（末尾带有助手回复的开头）
+    pub fn apply_chat_template(&self, messages: &[ChatMessage]) -> Result<String> {
+        self.chat_template
+            .as_ref()
+            .ok_or_else(|| self.tokenizer_missing("使用对话模板"))?
+            .render(messages, true)
+    }
 
-            generated_tokens.push(next_token);
+    /// 当前使用的对话模板
+    pub fn chat_template(&self) -> Option<&ChatTemplate> {
+        self.chat_template.as_ref()
+    }
+
+    /// 丢弃指定会话的 KV cache，返回会话是否存在
+    pub fn remove_session(&mut self, session_id: &str) -> bool {
+        self.sessions.remove(session_id).is_some()
+    }
 
-            // 准备下一个 token 的输入（只包含单个 token，在独立作用域中借用 device）
-            let next_token_tensor = {
-                let device = &self.device;
-                Tensor::new(&[next_token], device)?.unsqueeze(0)?
-            };
+    /// 丢弃所有会话的 KV cache
+    pub fn clear_sessions(&mut self) {
+        self.sessions.clear();
+    }
 
-            // 前向传播（只处理新生成的 token）
-            let logits = self.forward(&next_token_tensor, index_pos)?;
-            index_pos += 1;
+    /// 当前保留的会话数
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
 
-            // 获取最后一个 token 的 logits
-            let seq_len = logits.dim(1)?;
-            last_logits = logits.narrow(1, seq_len - 1, 1)?.squeeze(1)?;
+    /// 取出会话（不存在时以模型副本新建），超出上限时淘汰最久未使用的会话
+    fn session_mut<'a>(
+        sessions: &'a mut HashMap<String, KvSession>,
+        model: &GGUFModel,
+        max_sessions: usize,
+        session_id: &str,
+    ) -> &'a mut KvSession {
+        if !sessions.contains_key(session_id) {
+            while sessions.len() >= max_sessions.max(1) {
+                let oldest = sessions
+                    .iter()
+                    .min_by_key(|(_, session)| session.last_used)
+                    .map(|(id, _)| id.clone());
+                match oldest {
+                    Some(id) => sessions.remove(&id),
+                    None => break,
+                };
+            }
+            sessions.insert(
+                session_id.to_string(),
+                KvSession {
+                    model: model.try_clone(),
+                    tokens: Vec::new(),
+                    last_used: Instant::now(),
+                },
+            );
         }
+        let session = sessions
+            .get_mut(session_id)
+            .expect("session was just inserted");
+        session.last_used = Instant::now();
+        session
+    }
 
-        // 解码生成的文本
-        let generated_text = {
-            let tokenizer = self
-                .tokenizer
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("Tokenizer 未加载"))?;
-            tokenizer
-                .decode(&generated_tokens, true)
-                .map_err(|e| anyhow::anyhow!("解码失败: {}", e))?
-        };
+    fn generate_from_prompt<F>(
+        &mut self,
+        prompt: &str,
+        add_special_tokens: bool,
+        max_new_tokens: usize,
+        params: &GenerationParams,
+        cancel: &CancellationToken,
+        on_token: F,
+    ) -> Result<GenerationOutput>
+    where
+        F: FnMut(TokenEvent) -> ControlFlow<()>,
+    {
+        let tokenizer = self
+            .tokenizer
+            .as_ref()
+            .ok_or_else(|| self.tokenizer_missing("执行文本生成"))?;
+
+        // 编码输入文本
+        let tokens = tokenizer
+            .encode(prompt, add_special_tokens)
+            .map_err(|e| anyhow::anyhow!("编码失败: {}", e))?;
+        let input_ids = tokens.get_ids().to_vec();
+
+        if input_ids.len() > self.config.max_seq_len {
+            return Err(InferenceError::ContextOverflow {
+                prompt_tokens: input_ids.len(),
+                max_seq_len: self.config.max_seq_len,
+            }
+            .into());
+        }
+
+        // 结束标记：GGUF 元数据声明的 id 加上词表中的常见结束 token
+        let eos_token_ids = collect_eos_token_ids(tokenizer, &self.eos_token_ids);
+        let mut sampler =
+            Sampler::from_params(&params.sampling_params(self.config.sampling_params()));
+
+        let session_id = params.session_id.as_deref().unwrap_or(DEFAULT_SESSION);
+        // 不支持克隆的模型无法为会话保存独立的 KV cache，与默认会话共用会互相覆盖
+        if session_id != DEFAULT_SESSION && !self.model.supports_sessions() {
+            return Err(InferenceError::SessionUnsupported {
+                architecture: self.model.architecture(),
+            }
+            .into());
+        }
+        let session = Self::session_mut(
+            &mut self.sessions,
+            &self.model,
+            self.config.max_sessions,
+            session_id,
+        );
+        let device = &self.device;
 
-        Ok(generated_text)
+        // 共用引擎模型的会话：缓存被其他会话覆盖过时不能复用
+        if session.model.is_none() && self.shared_cache_owner.as_deref() != Some(session_id) {
+            session.tokens.clear();
+            self.shared_cache_owner = Some(session_id.to_string());
+        }
+        let cached_tokens = reusable_prefix_len(&session.tokens, &input_ids);
+        session.tokens.truncate(cached_tokens);
+        let KvSession { model, tokens, .. } = session;
+        let model = model.as_mut().unwrap_or(&mut self.model);
+        let result = decode_loop(
+            tokenizer,
+            &input_ids,
+            cached_tokens,
+            max_new_tokens,
+            self.config.max_seq_len,
+            &eos_token_ids,
+            &params.stop,
+            cancel,
+            |input_tokens, index_pos| {
+                let input = Tensor::new(input_tokens, device)?.unsqueeze(0)?;
+                let logits = model
+                    .forward(&input, index_pos)
+                    .context("模型前向传播失败")?;
+                // 记录 KV cache 中的 token 序列，供下一轮复用
+                tokens.truncate(index_pos);
+                tokens.extend_from_slice(input_tokens);
+                Ok(logits)
+            },
+            |logits, context| sampler.sample(logits, context),
+            on_token,
+        );
+        // 出错时缓存状态未知，下次从头预填充
+        if result.is_err() {
+            tokens.clear();
+        }
+        result
     }
 
     /// 获取设备信息
@@ -318,77 +674,101 @@ impl GGUFInferenceEngine {
     }
 }
 
-/// 采样下一个 token（支持 top-k 和 top-p 采样）
-fn sample_token_from_logits(logits: &Tensor, config: &GGUFConfig) -> Result<u32> {
-    let vocab_size = logits.dim(logits.dims().len() - 1)?;
-
-    // 应用温度
-    let logits = if config.temperature > 0.0 && config.temperature != 1.0 {
-        (logits / config.temperature as f64)?
-    } else {
-        logits.clone()
-    };
+/// GGUF 文件头中的模型描述，用于在不加载权重的情况下展示模型信息
+#[derive(Debug, Clone, Serialize)]
+pub struct GGUFFileInfo {
+    /// `general.architecture`
+    pub architecture: Option<String>,
+    /// 全部张量的元素数之和
+    pub parameter_count: u64,
+    /// 占用字节数最多的张量类型（例如 Q4K）
+    pub quantization: Option<String>,
+    /// `<architecture>.context_length`
+    pub context_length: Option<u64>,
+    /// `tokenizer.chat_template`
+    pub chat_template: Option<String>,
+    /// 标量元数据，词表等数组被省略
+    pub metadata: BTreeMap<String, serde_json::Value>,
+}
 
-    // 应用 softmax 获取概率分布
-    let probs = candle_nn::ops::softmax_last_dim(&logits)?;
-    let mut probs_vec: Vec<(usize, f32)> =
-        probs.to_vec1::<f32>()?.into_iter().enumerate().collect();
-
-    // Top-k 采样：只保留 top-k 个 token
-    if config.top_k > 0 && config.top_k < vocab_size {
-        probs_vec.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
-        probs_vec.truncate(config.top_k);
-    }
-
-    // Top-p (nucleus) 采样：累积概率直到达到 top_p
-    if config.top_p < 1.0 && config.top_p > 0.0 {
-        probs_vec.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
-        let mut cumsum = 0.0;
-        let mut cutoff_idx = probs_vec.len();
-        for (idx, (_, prob)) in probs_vec.iter().enumerate() {
-            cumsum += prob;
-            if cumsum >= config.top_p as f32 {
-                cutoff_idx = idx + 1;
-                break;
-            }
+impl GGUFFileInfo {
+    /// 从已读取的 GGUF 文件头提取模型描述
+    pub fn from_content(ct: &gguf_file::Content) -> Self {
+        let metadata: BTreeMap<String, serde_json::Value> = ct
+            .metadata
+            .iter()
+            .filter_map(|(key, value)| metadata_json(value).map(|value| (key.clone(), value)))
+            .collect();
+        let architecture = ct
+            .metadata
+            .get("general.architecture")
+            .and_then(|value| value.to_string().ok())
+            .cloned();
+        let context_length = architecture.as_ref().and_then(|arch| {
+            metadata
+                .get(&format!("{}.context_length", arch))
+                .and_then(|value| value.as_u64())
+        });
+        let chat_template = ct
+            .metadata
+            .get("tokenizer.chat_template")
+            .and_then(|value| value.to_string().ok())
+            .cloned();
+
+        let mut bytes_by_dtype: HashMap<String, u64> = HashMap::new();
+        for info in ct.tensor_infos.values() {
+            *bytes_by_dtype
+                .entry(format!("{:?}", info.ggml_dtype))
+                .or_default() += crate::memory::tensor_bytes(info);
         }
-        probs_vec.truncate(cutoff_idx);
-    }
+        let quantization = bytes_by_dtype
+            .into_iter()
+            .max_by_key(|(_, bytes)| *bytes)
+            .map(|(dtype, _)| dtype);
 
-    // 归一化概率
-    let total_prob: f32 = probs_vec.iter().map(|(_, p)| p).sum();
-    if total_prob > 0.0 {
-        for (_, prob) in probs_vec.iter_mut() {
-            *prob /= total_prob;
+        Self {
+            architecture,
+            parameter_count: ct
+                .tensor_infos
+                .values()
+                .map(|info| info.shape.elem_count() as u64)
+                .sum(),
+            quantization,
+            context_length,
+            chat_template,
+            metadata,
         }
     }
 
-    // 根据概率分布采样
-    if config.temperature == 0.0 {
-        // 贪婪采样：选择概率最高的
-        Ok(probs_vec
-            .iter()
-            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-            .map(|(idx, _)| *idx)
-            .unwrap_or(0) as u32)
-    } else {
-        // 随机采样：根据概率分布
-        use rand::prelude::*;
-        let mut rng = rand::thread_rng();
-        let rand_val: f32 = rng.gen();
-        let mut cumsum = 0.0;
-        let last_idx = probs_vec.last().map(|(idx, _)| *idx).unwrap_or(0);
-        for (idx, prob) in probs_vec {
-            cumsum += prob;
-            if rand_val <= cumsum {
-                return Ok(idx as u32);
-            }
-        }
-        // 如果由于浮点误差没有匹配到，返回最后一个
-        Ok(last_idx as u32)
+    /// 只读取文件头获取模型描述
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let mut file = File::open(path).with_context(|| format!("无法打开模型文件: {:?}", path))?;
+        let ct = gguf_file::Content::read(&mut file)
+            .with_context(|| format!("无法读取 GGUF 文件头: {:?}", path))?;
+        Ok(Self::from_content(&ct))
     }
 }
 
+/// 把标量元数据转换为 JSON 值，数组返回 None
+fn metadata_json(value: &gguf_file::Value) -> Option<serde_json::Value> {
+    use gguf_file::Value;
+    Some(match value {
+        Value::U8(v) => (*v).into(),
+        Value::I8(v) => (*v).into(),
+        Value::U16(v) => (*v).into(),
+        Value::I16(v) => (*v).into(),
+        Value::U32(v) => (*v).into(),
+        Value::I32(v) => (*v).into(),
+        Value::U64(v) => (*v).into(),
+        Value::I64(v) => (*v).into(),
+        Value::F32(v) => (*v).into(),
+        Value::F64(v) => (*v).into(),
+        Value::Bool(v) => (*v).into(),
+        Value::String(v) => v.clone().into(),
+        Value::Array(_) => return None,
+    })
+}
+
 /// 示例：从 HuggingFace Hub 下载并测试 GGUF 模型
 ///
 /// 这个函数展示了如何从 HuggingFace Hub 下载 GGUF 模型并测试前向传播，
@@ -404,6 +784,7 @@ fn sample_token_from_logits(logits: &Tensor, config: &GGUFConfig) -> Result<u32>
 ///         "HuggingFaceTB/SmolLM2-360M-Instruct-GGUF",
 ///         "smollm2-360m-instruct-q8_0.gguf",
 ///         None, // tokenizer_path
+///         None, // architecture
 ///     )?;
 ///     
 ///     // 测试前向传播（序列长度 128）
@@ -418,7 +799,7 @@ pub fn example_load_and_test_gguf() -> Result<()> {
         "HuggingFaceTB/SmolLM2-360M-Instruct-GGUF",
         "smollm2-360m-instruct-q8_0.gguf",
         None, // tokenizer_path
-        None, // default to llama
+        None, // 从 GGUF 头部识别架构
     )?;
 
     // 测试前向传播（序列长度 128）
@@ -437,4 +818,78 @@ mod tests {
         assert_eq!(config.max_seq_len, 2048);
         assert_eq!(config.temperature, 0.8);
     }
+
+    #[test]
+    fn test_reusable_prefix_len() {
+        // 上一轮的提示词与回复整体是新提示词的前缀
+        assert_eq!(reusable_prefix_len(&[1, 2, 3], &[1, 2, 3, 4, 5]), 3);
+        // 中途分叉：缓存无法截断，只能从头预填充
+        assert_eq!(reusable_prefix_len(&[1, 2, 3], &[1, 2, 4, 5]), 0);
+        // 提示词与缓存完全相同时仍需至少计算一个 token
+        assert_eq!(reusable_prefix_len(&[1, 2, 3], &[1, 2, 3]), 0);
+        assert_eq!(reusable_prefix_len(&[], &[1]), 0);
+    }
+
+    #[test]
+    fn test_resolve_architecture() {
+        let ct = gguf_file::Content {
+            magic: gguf_file::VersionedMagic::GgufV3,
+            metadata: [(
+                "general.architecture".to_string(),
+                gguf_file::Value::String("phi3".to_string()),
+            )]
+            .into_iter()
+            .collect(),
+            tensor_infos: HashMap::new(),
+            tensor_data_offset: 0,
+        };
+        assert_eq!(resolve_architecture(None, &ct).unwrap(), "phi3");
+        assert_eq!(resolve_architecture(Some("Qwen3VL"), &ct).unwrap(), "qwen3");
+
+        let err = resolve_architecture(Some("gemma2"), &ct).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "不支持的模型架构: gemma2，Gemma 系列目前只支持 Gemma 3（gemma3）"
+        );
+        let err = resolve_architecture(Some("mamba"), &ct).unwrap_err();
+        assert!(err.to_string().contains("mamba"));
+        assert!(err.to_string().contains("gemma3"));
+    }
+
+    #[test]
+    fn test_gguf_file_info() {
+        use candle_core::quantized::{GgmlDType, QTensor};
+
+        let tensor = Tensor::ones((4, 64), candle_core::DType::F32, &Device::Cpu).unwrap();
+        let weight = QTensor::quantize(&tensor, GgmlDType::Q8_0).unwrap();
+        let norm = QTensor::quantize(
+            &Tensor::ones(8, candle_core::DType::F32, &Device::Cpu).unwrap(),
+            GgmlDType::F32,
+        )
+        .unwrap();
+        let arch = gguf_file::Value::String("llama".to_string());
+        let context_length = gguf_file::Value::U32(4096);
+        let vocab = gguf_file::Value::Array(vec![gguf_file::Value::String("a".to_string())]);
+        let mut buf = std::io::Cursor::new(Vec::new());
+        gguf_file::write(
+            &mut buf,
+            &[
+                ("general.architecture", &arch),
+                ("llama.context_length", &context_length),
+                ("tokenizer.ggml.tokens", &vocab),
+            ],
+            &[("weight", &weight), ("norm", &norm)],
+        )
+        .unwrap();
+        buf.set_position(0);
+        let ct = gguf_file::Content::read(&mut buf).unwrap();
+
+        let info = GGUFFileInfo::from_content(&ct);
+        assert_eq!(info.architecture.as_deref(), Some("llama"));
+        assert_eq!(info.parameter_count, 4 * 64 + 8);
+        assert_eq!(info.quantization.as_deref(), Some("Q8_0"));
+        assert_eq!(info.context_length, Some(4096));
+        assert_eq!(info.chat_template, None);
+        assert!(!info.metadata.contains_key("tokenizer.ggml.tokens"));
+    }
 }
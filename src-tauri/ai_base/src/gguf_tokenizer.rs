@@ -0,0 +1,309 @@
+//! 从 GGUF 元数据构建 tokenizer
+//!
+//! GGUF 文件在 `tokenizer.ggml.*` 下内嵌了完整词表，没有 `tokenizer.json` 时据此构建
+//! [`Tokenizer`]，单个 GGUF 文件即可完成对话。支持两类词表：
+//!
+//! - `gpt2`：字节级 BPE（Llama-3、Qwen2/3、SmolLM 等），预分词规则取自 `tokenizer.ggml.pre`
+//! - `llama`：SentencePiece（Llama-2、Mistral、Gemma 等），按 `tokenizer.ggml.scores` 分词，
+//!   未登录字符回退到 `<0xXX>` 字节 token
+
+use anyhow::{bail, Context, Result};
+use candle_core::quantized::gguf_file::{self, Value};
+use tokenizers::decoders::byte_fallback::ByteFallback;
+use tokenizers::decoders::fuse::Fuse;
+use tokenizers::decoders::sequence::Sequence as DecoderSequence;
+use tokenizers::decoders::strip::Strip;
+use tokenizers::models::bpe::{Vocab, BPE};
+use tokenizers::models::unigram::Unigram;
+use tokenizers::normalizers::{Prepend, Replace, Sequence as NormalizerSequence};
+use tokenizers::pre_tokenizers::byte_level::ByteLevel;
+use tokenizers::pre_tokenizers::digits::Digits;
+use tokenizers::pre_tokenizers::sequence::Sequence as PreTokenizerSequence;
+use tokenizers::pre_tokenizers::split::{Split, SplitPattern};
+use tokenizers::processors::template::TemplateProcessing;
+use tokenizers::{AddedToken, SplitDelimiterBehavior, Tokenizer};
+
+/// Llama-3 预分词正则（`tokenizer.ggml.pre = llama-bpe`）
+const LLAMA3_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+/// Qwen2 预分词正则（数字逐位切分）
+const QWEN2_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+/// `tokenizer.ggml.token_type` 中的控制 token（`<s>`、`<|im_start|>` 等）
+const TOKEN_TYPE_CONTROL: i32 = 3;
+/// `tokenizer.ggml.token_type` 中的用户自定义 token
+const TOKEN_TYPE_USER_DEFINED: i32 = 4;
+
+fn metadata<'a>(ct: &'a gguf_file::Content, key: &str) -> Option<&'a Value> {
+    ct.metadata.get(key)
+}
+
+fn metadata_u32(ct: &gguf_file::Content, key: &str) -> Option<u32> {
+    metadata(ct, key).and_then(|value| {
+        value
+            .to_u32()
+            .ok()
+            .or_else(|| value.to_i32().ok().and_then(|v| u32::try_from(v).ok()))
+    })
+}
+
+fn metadata_bool(ct: &gguf_file::Content, key: &str) -> Option<bool> {
+    metadata(ct, key).and_then(|value| value.to_bool().ok())
+}
+
+fn metadata_strings(ct: &gguf_file::Content, key: &str) -> Result<Vec<String>> {
+    let values = metadata(ct, key)
+        .with_context(|| format!("GGUF 元数据缺少 {}", key))?
+        .to_vec()
+        .with_context(|| format!("GGUF 元数据 {} 不是数组", key))?;
+    values
+        .iter()
+        .map(|value| {
+            value
+                .to_string()
+                .cloned()
+                .with_context(|| format!("GGUF 元数据 {} 包含非字符串元素", key))
+        })
+        .collect()
+}
+
+/// 从 GGUF 元数据构建 tokenizer
+///
+/// 控制 token 与用户自定义 token 注册为 added token，编码时不会被拆开；
+/// `tokenizer.ggml.add_bos_token` 为 true 时编码结果自动以 BOS 开头。
+pub fn tokenizer_from_gguf(ct: &gguf_file::Content) -> Result<Tokenizer> {
+    let model = metadata(ct, "tokenizer.ggml.model")
+        .and_then(|v| v.to_string().ok())
+        .context("GGUF 元数据缺少 tokenizer.ggml.model，无法构建 tokenizer")?
+        .clone();
+    let tokens = metadata_strings(ct, "tokenizer.ggml.tokens")?;
+
+    let (mut tokenizer, add_bos_default) = match model.as_str() {
+        "gpt2" => (bpe_tokenizer(ct, &tokens)?, false),
+        "llama" => (sentencepiece_tokenizer(ct, &tokens)?, true),
+        other => bail!(
+            "不支持的 GGUF tokenizer 类型: {}（支持 gpt2、llama），请提供 tokenizer.json",
+            other
+        ),
+    };
+
+    // 控制 token 与用户自定义 token
+    if let Some(types) = metadata(ct, "tokenizer.ggml.token_type").and_then(|v| v.to_vec().ok()) {
+        let mut added = Vec::new();
+        for (token, token_type) in tokens.iter().zip(types) {
+            match token_type.to_i32().ok() {
+                Some(TOKEN_TYPE_CONTROL) => added.push(AddedToken::from(token.clone(), true)),
+                Some(TOKEN_TYPE_USER_DEFINED) => added.push(AddedToken::from(token.clone(), false)),
+                _ => {}
+            }
+        }
+        tokenizer.add_special_tokens(&added);
+    }
+
+    let add_bos = metadata_bool(ct, "tokenizer.ggml.add_bos_token").unwrap_or(add_bos_default);
+    let bos = metadata_u32(ct, "tokenizer.ggml.bos_token_id")
+        .and_then(|id| tokens.get(id as usize).map(|token| (token.clone(), id)));
+    if let (true, Some((bos, bos_id))) = (add_bos, bos) {
+        let processor = TemplateProcessing::builder()
+            .try_single(format!("{} $A", bos))
+            .map_err(|e| anyhow::anyhow!("无法构建 BOS 模板: {}", e))?
+            .try_pair(format!("{bos} $A {bos}:1 $B:1"))
+            .map_err(|e| anyhow::anyhow!("无法构建 BOS 模板: {}", e))?
+            .special_tokens(vec![(bos, bos_id)])
+            .build()
+            .map_err(|e| anyhow::anyhow!("无法构建 BOS 模板: {}", e))?;
+        tokenizer.with_post_processor(Some(processor));
+    }
+
+    Ok(tokenizer)
+}
+
+/// 字节级 BPE（`tokenizer.ggml.model = gpt2`）
+fn bpe_tokenizer(ct: &gguf_file::Content, tokens: &[String]) -> Result<Tokenizer> {
+    let vocab: Vocab = tokens
+        .iter()
+        .enumerate()
+        .map(|(id, token)| (token.clone(), id as u32))
+        .collect();
+    let merges = metadata_strings(ct, "tokenizer.ggml.merges")?
+        .into_iter()
+        .map(|merge| {
+            merge
+                .split_once(' ')
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .with_context(|| format!("无效的 BPE 合并规则: {:?}", merge))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let bpe = BPE::builder()
+        .vocab_and_merges(vocab, merges)
+        .build()
+        .map_err(|e| anyhow::anyhow!("无法构建 BPE 模型: {}", e))?;
+    let mut tokenizer = Tokenizer::new(bpe);
+
+    let split = |pattern: &str| -> Result<Split> {
+        Split::new(
+            SplitPattern::Regex(pattern.to_string()),
+            SplitDelimiterBehavior::Isolated,
+            false,
+        )
+        .map_err(|e| anyhow::anyhow!("无效的预分词正则: {}", e))
+    };
+    let pre = metadata(ct, "tokenizer.ggml.pre").and_then(|v| v.to_string().ok());
+    let pre_tokenizer = match pre.map(String::as_str) {
+        Some("llama-bpe" | "llama3") => PreTokenizerSequence::new(vec![
+            split(LLAMA3_PATTERN)?.into(),
+            ByteLevel::new(false, true, false).into(),
+        ]),
+        Some("qwen2") => PreTokenizerSequence::new(vec![
+            split(QWEN2_PATTERN)?.into(),
+            ByteLevel::new(false, true, false).into(),
+        ]),
+        Some("smollm") => PreTokenizerSequence::new(vec![
+            Digits::new(true).into(),
+            ByteLevel::new(false, true, true).into(),
+        ]),
+        _ => PreTokenizerSequence::new(vec![ByteLevel::new(false, true, true).into()]),
+    };
+    tokenizer.with_pre_tokenizer(Some(pre_tokenizer));
+    tokenizer.with_decoder(Some(ByteLevel::default()));
+    Ok(tokenizer)
+}
+
+/// SentencePiece（`tokenizer.ggml.model = llama`）
+fn sentencepiece_tokenizer(ct: &gguf_file::Content, tokens: &[String]) -> Result<Tokenizer> {
+    let scores = metadata(ct, "tokenizer.ggml.scores")
+        .and_then(|v| v.to_vec().ok())
+        .map(|scores| {
+            scores
+                .iter()
+                .map(|score| score.to_f32().unwrap_or_default() as f64)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_else(|| vec![0.0; tokens.len()]);
+    let vocab: Vec<(String, f64)> = tokens
+        .iter()
+        .cloned()
+        .zip(scores.into_iter().chain(std::iter::repeat(0.0)))
+        .collect();
+    let unk_id = metadata_u32(ct, "tokenizer.ggml.unknown_token_id").unwrap_or(0) as usize;
+
+    let unigram = Unigram::from(vocab, Some(unk_id), true)
+        .map_err(|e| anyhow::anyhow!("无法构建 SentencePiece 模型: {}", e))?;
+    let mut tokenizer = Tokenizer::new(unigram);
+
+    // 空格替换为 ▁，并按 add_space_prefix 在开头补一个 ▁
+    let replace_space =
+        Replace::new(" ", "▁").map_err(|e| anyhow::anyhow!("无法构建 normalizer: {}", e))?;
+    let normalizer = if metadata_bool(ct, "tokenizer.ggml.add_space_prefix").unwrap_or(true) {
+        NormalizerSequence::new(vec![
+            Prepend::new("▁".to_string()).into(),
+            replace_space.into(),
+        ])
+    } else {
+        NormalizerSequence::new(vec![replace_space.into()])
+    };
+    tokenizer.with_normalizer(Some(normalizer));
+
+    let replace_marker =
+        Replace::new("▁", " ").map_err(|e| anyhow::anyhow!("无法构建 decoder: {}", e))?;
+    tokenizer.with_decoder(Some(DecoderSequence::new(vec![
+        replace_marker.into(),
+        ByteFallback::new().into(),
+        Fuse::new().into(),
+        Strip::new(' ', 1, 0).into(),
+    ])));
+    Ok(tokenizer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Value {
+        Value::Array(
+            values
+                .iter()
+                .map(|v| Value::String(v.to_string()))
+                .collect(),
+        )
+    }
+
+    fn content(metadata: Vec<(&str, Value)>) -> gguf_file::Content {
+        gguf_file::Content {
+            magic: gguf_file::VersionedMagic::GgufV3,
+            metadata: metadata
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+            tensor_infos: Default::default(),
+            tensor_data_offset: 0,
+        }
+    }
+
+    #[test]
+    fn test_bpe_from_metadata() {
+        // 字节级 BPE：空格映射为 Ġ
+        let ct = content(vec![
+            ("tokenizer.ggml.model", Value::String("gpt2".into())),
+            (
+                "tokenizer.ggml.tokens",
+                strings(&["h", "i", "Ġ", "hi", "Ġhi", "<|im_end|>"]),
+            ),
+            ("tokenizer.ggml.merges", strings(&["h i", "Ġ hi"])),
+            (
+                "tokenizer.ggml.token_type",
+                Value::Array([1, 1, 1, 1, 1, 3].map(Value::I32).to_vec()),
+            ),
+        ]);
+        let tokenizer = tokenizer_from_gguf(&ct).unwrap();
+
+        let encoding = tokenizer.encode("hi hi<|im_end|>", false).unwrap();
+        assert_eq!(encoding.get_ids(), &[3, 4, 5]);
+        assert_eq!(tokenizer.decode(&[3, 4], false).unwrap(), "hi hi");
+        assert_eq!(tokenizer.decode(&[3, 4, 5], true).unwrap(), "hi hi");
+    }
+
+    #[test]
+    fn test_sentencepiece_from_metadata() {
+        let ct = content(vec![
+            ("tokenizer.ggml.model", Value::String("llama".into())),
+            (
+                "tokenizer.ggml.tokens",
+                strings(&["<unk>", "<s>", "</s>", "<0x21>", "▁hello", "▁world", "▁"]),
+            ),
+            (
+                "tokenizer.ggml.scores",
+                Value::Array(
+                    [0.0, 0.0, 0.0, 0.0, -1.0, -1.0, -5.0]
+                        .map(Value::F32)
+                        .to_vec(),
+                ),
+            ),
+            (
+                "tokenizer.ggml.token_type",
+                Value::Array([2, 3, 3, 6, 1, 1, 1].map(Value::I32).to_vec()),
+            ),
+            ("tokenizer.ggml.bos_token_id", Value::U32(1)),
+        ]);
+        let tokenizer = tokenizer_from_gguf(&ct).unwrap();
+
+        // 默认在开头补 BOS，未登录的 "!" 回退为字节 token
+        let encoding = tokenizer.encode("hello world!", true).unwrap();
+        assert_eq!(encoding.get_ids(), &[1, 4, 5, 3]);
+        assert_eq!(
+            tokenizer.decode(encoding.get_ids(), true).unwrap(),
+            "hello world!"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_model() {
+        let ct = content(vec![
+            ("tokenizer.ggml.model", Value::String("bert".into())),
+            ("tokenizer.ggml.tokens", strings(&["a"])),
+        ]);
+        let err = tokenizer_from_gguf(&ct).unwrap_err();
+        assert!(err.to_string().contains("bert"));
+    }
+}
@@ -1,19 +1,45 @@
 use anyhow::{Context, Result};
 use candle_core::{DType, Device, Tensor};
-use candle_nn::VarBuilder;
 use candle_transformers::models::llama as model;
+use std::ops::ControlFlow;
 use std::path::PathBuf;
 use tokenizers::Tokenizer;
 pub mod models;
 
+pub mod generation;
+use generation::{collect_eos_token_ids, decode_loop};
+pub use generation::{
+    CancellationToken, FinishReason, GenerationOutput, GenerationParams, TokenEvent,
+};
+
+pub mod chat;
+pub use chat::{ChatMessage, ChatRole, ChatTemplate};
+
+pub mod sampling;
+pub use sampling::{LogitsProcessor, Sampler, SamplingParams};
+
 pub mod vision;
 pub use vision::{ImagePreprocessConfig, ImagePreprocessor};
 
 pub mod gguf;
-pub use gguf::{GGUFConfig, GGUFInferenceEngine};
+pub use gguf::{GGUFConfig, GGUFFileInfo, GGUFInferenceEngine};
+
+pub mod gguf_tokenizer;
+pub use gguf_tokenizer::tokenizer_from_gguf;
 
 pub mod utils;
 
+pub mod weights;
+pub use weights::SafetensorsFiles;
+
+pub mod memory;
+
+pub mod progress;
+pub use progress::{LoadProgress, ProgressCallback};
+
+pub mod error;
+pub use error::InferenceError;
+
 /// 推理引擎结构体
 pub struct InferenceEngine {
     device: Device,
@@ -21,13 +47,15 @@ pub struct InferenceEngine {
     tokenizer: Tokenizer,
     config: InferenceConfig,
     model_config: model::Config,
+    /// 权重、KV cache 与输入张量统一使用的精度
+    dtype: DType,
     image_preprocessor: Option<ImagePreprocessor>,
 }
 
 /// 推理配置
 #[derive(Debug, Clone)]
 pub struct InferenceConfig {
-    /// 模型路径（safetensors 文件）
+    /// 模型路径（safetensors 文件、分片索引文件或模型目录）
     pub model_path: PathBuf,
     /// Tokenizer 路径
     pub tokenizer_path: PathBuf,
@@ -41,6 +69,10 @@ pub struct InferenceConfig {
     pub top_p: f64,
     /// Top-k 采样参数
     pub top_k: usize,
+    /// 采样随机种子（None 表示随机）
+    pub seed: Option<u64>,
+    /// 计算精度（F32/F16/BF16），None 表示使用模型 config.json 中的 `torch_dtype`，没有时为 F32
+    pub dtype: Option<DType>,
     /// 图像预处理配置（用于多模态模型）
     pub image_preprocess_config: Option<ImagePreprocessConfig>,
 }
@@ -55,11 +87,26 @@ impl Default for InferenceConfig {
             temperature: 0.8,
             top_p: 0.9,
             top_k: 40,
+            seed: None,
+            dtype: None,
             image_preprocess_config: None,
         }
     }
 }
 
+impl InferenceConfig {
+    /// 由配置中的采样参数构建 [`SamplingParams`]
+    pub fn sampling_params(&self) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature,
+            top_k: self.top_k,
+            top_p: self.top_p,
+            seed: self.seed,
+            ..Default::default()
+        }
+    }
+}
+
 impl InferenceEngine {
     /// 创建新的推理引擎
     ///
@@ -75,25 +122,42 @@ impl InferenceEngine {
         config: InferenceConfig,
         model_config: model::Config,
         device: Option<Device>,
+    ) -> Result<Self> {
+        Self::new_with_progress(config, model_config, device, &progress::ignore_progress)
+    }
+
+    /// 创建新的推理引擎，并通过 `on_progress` 报告各加载阶段
+    pub fn new_with_progress(
+        config: InferenceConfig,
+        model_config: model::Config,
+        device: Option<Device>,
+        on_progress: ProgressCallback<'_>,
     ) -> Result<Self> {
         let device = device.unwrap_or_else(|| {
             // 尝试使用 CUDA，如果不可用则使用 CPU
-            Device::cuda_if_available(0).unwrap_or_else(|_| Device::Cpu)
+            Device::cuda_if_available(0).unwrap_or(Device::Cpu)
         });
 
         // 加载 tokenizer
+        on_progress(LoadProgress::Tokenizer);
         let tokenizer = Tokenizer::from_file(&config.tokenizer_path)
             .map_err(|e| anyhow::anyhow!("无法加载 tokenizer: {}", e))?;
 
-        // 加载模型权重
-        // 使用 VarBuilder 直接加载 safetensors 文件
-        let dtype = DType::F32; // 使用 F32 作为默认 dtype，可以根据需要调整
-        let vb = unsafe {
-            VarBuilder::from_mmaped_safetensors(&[config.model_path.clone()], dtype, &device)?
-        };
+        // 计算精度：显式指定 > 模型目录 config.json 的 torch_dtype > F32
+        let dtype = weights::resolve_dtype(config.dtype, &config.model_path)?;
+        weights::ensure_dtype_supported(dtype, &device)?;
+
+        // 加载模型权重（单文件或按索引加载全部分片），按 dtype 转换
+        let weights = SafetensorsFiles::from_path(&config.model_path)?;
+        on_progress(LoadProgress::Open {
+            path: config.model_path.clone(),
+            file_bytes: weights.file_bytes(),
+        });
+        let vb = unsafe { weights.var_builder_with_progress(dtype, &device, on_progress)? };
 
         // 创建模型
         let model = model::Llama::load(vb, &model_config).context("无法加载模型")?;
+        on_progress(LoadProgress::Done);
 
         // 创建图像预处理器（如果配置了图像预处理）
         let image_preprocessor = config
@@ -107,162 +171,81 @@ impl InferenceEngine {
             tokenizer,
             config,
             model_config,
+            dtype,
             image_preprocessor,
         })
     }
 
     /// 执行文本生成推理
     pub fn generate(&self, prompt: &str, max_new_tokens: usize) -> Result<String> {
+        let output = self.generate_stream(
+            prompt,
+            max_new_tokens,
+            &GenerationParams::default(),
+            &CancellationToken::new(),
+            |_| ControlFlow::Continue(()),
+        )?;
+        Ok(output.text)
+    }
+
+    /// 流式文本生成
+    ///
+    /// 每生成一个 token 调用一次 `on_token`，回调返回 `ControlFlow::Break(())` 或 `cancel`
+    /// 被取消时提前结束生成。`params` 中未设置的采样参数使用 [`InferenceConfig`] 中的值。
+    pub fn generate_stream<F>(
+        &self,
+        prompt: &str,
+        max_new_tokens: usize,
+        params: &GenerationParams,
+        cancel: &CancellationToken,
+        on_token: F,
+    ) -> Result<GenerationOutput>
+    where
+        F: FnMut(TokenEvent) -> ControlFlow<()>,
+    {
         // 编码输入文本
         let tokens = self
             .tokenizer
             .encode(prompt, true)
             .map_err(|e| anyhow::anyhow!("编码失败: {}", e))?;
+        let input_ids = tokens.get_ids().to_vec();
 
-        let input_ids: Vec<u32> = tokens.get_ids().iter().map(|&id| id as u32).collect();
-        let input_len = input_ids.len();
-
-        if input_len > self.config.max_seq_len {
-            return Err(anyhow::anyhow!(
-                "输入序列长度 {} 超过最大长度 {}",
-                input_len,
-                self.config.max_seq_len
-            ));
-        }
-
-        // 转换为 Tensor
-        let input_tensor = Tensor::new(input_ids.as_slice(), &self.device)?.unsqueeze(0)?;
-
-        // 执行推理
-        let mut generated_tokens = Vec::new();
-        let dtype = DType::F32; // 使用与模型相同的 dtype
-        let mut cache = model::Cache::new(true, dtype, &self.model_config, &self.device)?;
-
-        // 获取结束标记 ID
-        let eos_token_id = self
-            .tokenizer
-            .token_to_id("<|endoftext|>")
-            .unwrap_or_else(|| {
-                // 如果找不到结束标记，使用 vocab_size 作为默认值
-                let vocab_size = self.tokenizer.get_vocab_size(true);
-                vocab_size.try_into().unwrap_or(u32::MAX)
-            });
-
-        // 初始前向传播处理输入序列
-        let mut index_pos = 0;
-        let logits = self.model.forward(&input_tensor, index_pos, &mut cache)?;
-        index_pos += input_len;
-
-        // 获取最后一个 token 的 logits 并生成第一个 token
-        let seq_len = logits.dim(1)?;
-        let mut last_logits = logits.narrow(1, seq_len - 1, 1)?.squeeze(1)?;
-
-        // 生成循环
-        for _ in 0..max_new_tokens {
-            // 采样下一个 token
-            let next_token = self.sample_token(&last_logits)?;
-
-            // 检查是否到达结束标记
-            if next_token == eos_token_id {
-                break;
+        if input_ids.len() > self.config.max_seq_len {
+            return Err(InferenceError::ContextOverflow {
+                prompt_tokens: input_ids.len(),
+                max_seq_len: self.config.max_seq_len,
             }
-
-            generated_tokens.push(next_token);
-
-            // 准备下一个 token 的输入（只包含单个 token）
-            let next_token_tensor = Tensor::new(&[next_token], &self.device)?.unsqueeze(0)?;
-
-            // 前向传播（只处理新生成的 token，利用 cache）
-            let logits = self
-                .model
-                .forward(&next_token_tensor, index_pos, &mut cache)?;
-            index_pos += 1;
-
-            // 获取最后一个 token 的 logits（应该是新生成的 token 的 logits）
-            let seq_len = logits.dim(1)?;
-            last_logits = logits.narrow(1, seq_len - 1, 1)?.squeeze(1)?;
+            .into());
         }
 
-        // 解码生成的文本
-        let generated_text = self
-            .tokenizer
-            .decode(&generated_tokens, true)
-            .map_err(|e| anyhow::anyhow!("解码失败: {}", e))?;
-
-        Ok(generated_text)
-    }
+        let mut cache = model::Cache::new(true, self.dtype, &self.model_config, &self.device)?;
 
-    /// 采样下一个 token（支持 top-k 和 top-p 采样）
-    fn sample_token(&self, logits: &Tensor) -> Result<u32> {
-        let vocab_size = logits.dim(logits.dims().len() - 1)?;
-
-        // 应用温度
-        let logits = if self.config.temperature > 0.0 && self.config.temperature != 1.0 {
-            (logits / self.config.temperature as f64)?
-        } else {
-            logits.clone()
+        // 结束标记：模型配置声明的 id 加上词表中的常见结束 token
+        let declared = match &self.model_config.eos_token_id {
+            Some(model::LlamaEosToks::Single(id)) => vec![*id],
+            Some(model::LlamaEosToks::Multiple(ids)) => ids.clone(),
+            None => Vec::new(),
         };
-
-        // 应用 softmax 获取概率分布
-        let probs = candle_nn::ops::softmax_last_dim(&logits)?;
-        let mut probs_vec: Vec<(usize, f32)> =
-            probs.to_vec1::<f32>()?.into_iter().enumerate().collect();
-
-        // Top-k 采样：只保留 top-k 个 token
-        if self.config.top_k > 0 && self.config.top_k < vocab_size {
-            probs_vec
-                .sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
-            probs_vec.truncate(self.config.top_k);
-        }
-
-        // Top-p (nucleus) 采样：累积概率直到达到 top_p
-        if self.config.top_p < 1.0 && self.config.top_p > 0.0 {
-            probs_vec
-                .sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
-            let mut cumsum = 0.0;
-            let mut cutoff_idx = probs_vec.len();
-            for (idx, (_, prob)) in probs_vec.iter().enumerate() {
-                cumsum += prob;
-                if cumsum >= self.config.top_p as f32 {
-                    cutoff_idx = idx + 1;
-                    break;
-                }
-            }
-            probs_vec.truncate(cutoff_idx);
-        }
-
-        // 归一化概率
-        let total_prob: f32 = probs_vec.iter().map(|(_, p)| p).sum();
-        if total_prob > 0.0 {
-            for (_, prob) in probs_vec.iter_mut() {
-                *prob /= total_prob;
-            }
-        }
-
-        // 根据概率分布采样
-        if self.config.temperature == 0.0 {
-            // 贪婪采样：选择概率最高的
-            Ok(probs_vec
-                .iter()
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-                .map(|(idx, _)| *idx)
-                .unwrap_or(0) as u32)
-        } else {
-            // 随机采样：根据概率分布
-            use rand::prelude::*;
-            let mut rng = rand::thread_rng();
-            let rand_val: f32 = rng.gen();
-            let mut cumsum = 0.0;
-            let last_idx = probs_vec.last().map(|(idx, _)| *idx).unwrap_or(0);
-            for (idx, prob) in probs_vec {
-                cumsum += prob;
-                if rand_val <= cumsum {
-                    return Ok(idx as u32);
-                }
-            }
-            // 如果由于浮点误差没有匹配到，返回最后一个
-            Ok(last_idx as u32)
-        }
+        let eos_token_ids = collect_eos_token_ids(&self.tokenizer, &declared);
+
+        let mut sampler =
+            Sampler::from_params(&params.sampling_params(self.config.sampling_params()));
+        decode_loop(
+            &self.tokenizer,
+            &input_ids,
+            0,
+            max_new_tokens,
+            self.config.max_seq_len,
+            &eos_token_ids,
+            &params.stop,
+            cancel,
+            |tokens, index_pos| {
+                let input = Tensor::new(tokens, &self.device)?.unsqueeze(0)?;
+                Ok(self.model.forward(&input, index_pos, &mut cache)?)
+            },
+            |logits, context| sampler.sample(logits, context),
+            on_token,
+        )
     }
 
     /// 获取设备信息
@@ -280,6 +263,11 @@ impl InferenceEngine {
         &self.model_config
     }
 
+    /// 获取计算精度
+    pub fn dtype(&self) -> DType {
+        self.dtype
+    }
+
     /// 预处理图像（用于多模态输入）
     ///
     /// 如果图像预处理器未配置，返回错误
@@ -290,7 +278,10 @@ impl InferenceEngine {
             )
         })?;
 
-        preprocessor.load_and_preprocess(image_path, &self.device)
+        preprocessor
+            .load_and_preprocess(image_path, &self.device)?
+            .to_dtype(self.dtype)
+            .context("图像张量精度转换失败")
     }
 
     /// 从字节数据预处理图像
@@ -301,7 +292,10 @@ impl InferenceEngine {
             )
         })?;
 
-        preprocessor.preprocess_from_bytes(image_data, &self.device)
+        preprocessor
+            .preprocess_from_bytes(image_data, &self.device)?
+            .to_dtype(self.dtype)
+            .context("图像张量精度转换失败")
     }
 
     /// 多模态生成（图像 + 文本）
@@ -348,21 +342,75 @@ impl InferenceEngine {
         self.generate(prompt, max_new_tokens)
     }
 
-    /// 从 HuggingFace 模型目录加载配置（如果 Config 支持 serde）
+    /// 从 HuggingFace 模型目录的 `config.json` 加载模型配置
     ///
-    /// 注意：此方法需要 candle-transformers 的 Config 类型支持 serde::Deserialize
-    /// 如果您的版本不支持，请手动构建 Config
-    #[allow(dead_code)]
-    pub fn load_config_from_dir(_model_dir: impl AsRef<std::path::Path>) -> Result<model::Config> {
-        // 注意：candle-transformers 的 Config 可能不支持直接反序列化
-        // 您需要根据模型类型手动构建 Config
-        // 这里提供一个占位符实现
-        Err(anyhow::anyhow!(
-            "Config 反序列化需要手动实现。请根据您的模型类型构建 Config"
-        ))
+    /// 只支持结构与 Llama 相同的模型（见 [`SUPPORTED_MODEL_TYPES`]），其他 `model_type` 返回错误。
+    pub fn load_config_from_dir(model_dir: impl AsRef<std::path::Path>) -> Result<model::Config> {
+        let config_path = model_dir.as_ref().join("config.json");
+        let content = std::fs::read_to_string(&config_path)
+            .with_context(|| format!("无法读取模型配置: {:?}", config_path))?;
+        llama_config_from_json(&content)
+            .with_context(|| format!("无法解析模型配置: {:?}", config_path))
     }
 }
 
+/// 可以用 Llama 实现加载的 `model_type`（Mistral 的滑动窗口注意力不生效，按完整上下文计算）
+pub const SUPPORTED_MODEL_TYPES: &[&str] = &["llama", "mistral"];
+
+/// 将 HF `config.json` 解析为 Llama 配置
+///
+/// 没有 `model_type` 时按 llama 处理；`rope_scaling` 只支持 Llama-3 形式，
+/// linear、dynamic、yarn 等类型在 Llama 实现中无法还原，直接报错。
+fn llama_config_from_json(json: &str) -> Result<model::Config> {
+    let mut value: serde_json::Value = serde_json::from_str(json)?;
+    let object = value
+        .as_object_mut()
+        .context("config.json 顶层不是 JSON 对象")?;
+
+    let model_type = object
+        .get("model_type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("llama");
+    if !SUPPORTED_MODEL_TYPES.contains(&model_type) {
+        anyhow::bail!(
+            "不支持的 model_type: {}，目前支持: {}",
+            model_type,
+            SUPPORTED_MODEL_TYPES.join(", ")
+        );
+    }
+
+    let rope_scaling = match object.remove("rope_scaling") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(mut scaling) => {
+            let rope_type = scaling
+                .get("rope_type")
+                .or_else(|| scaling.get("type"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("default")
+                .to_string();
+            match rope_type.as_str() {
+                "default" => None,
+                "llama3" => {
+                    scaling["rope_type"] = "llama3".into();
+                    Some(
+                        serde_json::from_value::<model::Llama3RopeConfig>(scaling)
+                            .context("rope_scaling 字段不完整")?,
+                    )
+                }
+                other => anyhow::bail!("不支持的 rope_scaling 类型: {}", other),
+            }
+        }
+    };
+    object
+        .entry("max_position_embeddings")
+        .or_insert(model::DEFAULT_MAX_SEQ_LEN.into());
+
+    let llama_config: model::LlamaConfig = serde_json::from_value(value)?;
+    let mut config = llama_config.into_config(false);
+    config.rope_scaling = rope_scaling;
+    Ok(config)
+}
+
 /// 便捷函数：创建推理引擎（需要提供模型配置）
 pub fn create_inference_engine(
     model_path: impl Into<PathBuf>,
@@ -388,4 +436,78 @@ mod tests {
         assert_eq!(config.max_seq_len, 2048);
         assert_eq!(config.temperature, 0.8);
     }
+
+    #[test]
+    fn test_llama3_config_from_json() {
+        let config = llama_config_from_json(
+            r#"{
+                "model_type": "llama",
+                "hidden_size": 2048,
+                "intermediate_size": 8192,
+                "vocab_size": 128256,
+                "num_hidden_layers": 16,
+                "num_attention_heads": 32,
+                "num_key_value_heads": 8,
+                "rms_norm_eps": 1e-5,
+                "rope_theta": 500000.0,
+                "rope_scaling": {
+                    "factor": 32.0,
+                    "low_freq_factor": 1.0,
+                    "high_freq_factor": 4.0,
+                    "original_max_position_embeddings": 8192,
+                    "rope_type": "llama3"
+                },
+                "max_position_embeddings": 131072,
+                "tie_word_embeddings": true,
+                "bos_token_id": 128000,
+                "eos_token_id": [128001, 128008, 128009]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.num_key_value_heads, 8);
+        assert_eq!(config.rope_theta, 500000.0);
+        assert!(config.tie_word_embeddings);
+        assert_eq!(config.rope_scaling.unwrap().factor, 32.0);
+        assert_eq!(config.bos_token_id, Some(128000));
+        assert!(matches!(
+            config.eos_token_id,
+            Some(model::LlamaEosToks::Multiple(ref ids)) if ids.len() == 3
+        ));
+    }
+
+    #[test]
+    fn test_mistral_config_defaults() {
+        // 没有 num_key_value_heads、rope_theta、max_position_embeddings 时使用默认值
+        let config = llama_config_from_json(
+            r#"{
+                "model_type": "mistral",
+                "hidden_size": 64,
+                "intermediate_size": 128,
+                "vocab_size": 100,
+                "num_hidden_layers": 2,
+                "num_attention_heads": 4,
+                "rms_norm_eps": 1e-6,
+                "rope_scaling": null,
+                "eos_token_id": 2
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.num_key_value_heads, 4);
+        assert_eq!(config.rope_theta, 10_000.0);
+        assert_eq!(config.max_position_embeddings, model::DEFAULT_MAX_SEQ_LEN);
+        assert!(!config.tie_word_embeddings);
+        assert!(config.rope_scaling.is_none());
+    }
+
+    #[test]
+    fn test_unsupported_config() {
+        let err = llama_config_from_json(r#"{"model_type": "qwen3_vl"}"#).unwrap_err();
+        assert!(err.to_string().contains("qwen3_vl"));
+
+        let err = llama_config_from_json(
+            r#"{"model_type": "llama", "rope_scaling": {"type": "linear", "factor": 2.0}}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("linear"));
+    }
 }
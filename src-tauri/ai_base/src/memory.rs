@@ -0,0 +1,97 @@
+//! 模型内存估算
+//!
+//! 加载前根据 GGUF 张量信息（元素数与量化类型）或 safetensors 文件头估算权重常驻内存，
+//! 不含 KV cache 与激活值。加载后的实际占用可以用进程常驻内存的变化近似。
+
+use anyhow::{Context, Result};
+use candle_core::quantized::gguf_file;
+use std::path::Path;
+
+/// 按张量信息估算 GGUF 模型权重的常驻字节数
+pub fn estimate_gguf_bytes(ct: &gguf_file::Content) -> u64 {
+    ct.tensor_infos.values().map(tensor_bytes).sum()
+}
+
+/// 单个 GGUF 张量的数据字节数（按量化块计算）
+pub(crate) fn tensor_bytes(info: &gguf_file::TensorInfo) -> u64 {
+    let dtype = info.ggml_dtype;
+    let blocks = info.shape.elem_count().div_ceil(dtype.block_size());
+    (blocks * dtype.type_size()) as u64
+}
+
+/// 只读取 GGUF 文件头估算权重常驻字节数
+pub fn estimate_gguf_file(path: &Path) -> Result<u64> {
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("无法打开模型文件: {:?}", path))?;
+    let ct = gguf_file::Content::read(&mut file)
+        .with_context(|| format!("无法读取 GGUF 文件头: {:?}", path))?;
+    Ok(estimate_gguf_bytes(&ct))
+}
+
+/// 当前进程的常驻内存（RSS）字节数，不支持的平台返回 None
+pub fn resident_memory_bytes() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let kb = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+        Some(kb * 1024)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// 以 KB/MB/GB 格式化字节数，用于日志和错误信息
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candle_core::quantized::{GgmlDType, QTensor};
+    use candle_core::{Device, Tensor};
+
+    #[test]
+    fn test_estimate_gguf_file() {
+        let path =
+            std::env::temp_dir().join(format!("ai_base_estimate_{}.gguf", std::process::id()));
+        let tensor = Tensor::ones((4, 64), candle_core::DType::F32, &Device::Cpu).unwrap();
+        let q8 = QTensor::quantize(&tensor, GgmlDType::Q8_0).unwrap();
+        let f32 = QTensor::quantize(&tensor, GgmlDType::F32).unwrap();
+        let mut file = std::fs::File::create(&path).unwrap();
+        gguf_file::write(&mut file, &[], &[("q8", &q8), ("f32", &f32)]).unwrap();
+        drop(file);
+
+        // Q8_0：每 32 个元素一个 34 字节的块；F32：每个元素 4 字节
+        let expected = (256 / 32 * 34 + 256 * 4) as u64;
+        assert_eq!(estimate_gguf_file(&path).unwrap(), expected);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(4 * 1024 * 1024 * 1024), "4.0 GB");
+    }
+}
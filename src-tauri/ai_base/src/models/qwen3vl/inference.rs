@@ -1,35 +1,45 @@
 use anyhow::Result;
-use candle_core::{Device, Tensor};
-use candle_nn::VarBuilder;
+use candle_core::{DType, Device, Tensor};
 use std::path::Path;
 use tokenizers::Tokenizer;
 
 use crate::models::qwen3vl::{Qwen3VLConfig, Qwen3VLModel, Qwen3VLProcessor};
+use crate::weights::{self, SafetensorsFiles};
 
 pub struct Qwen3VLInferenceEngine {
     model: Qwen3VLModel,
     tokenizer: Tokenizer,
     processor: Qwen3VLProcessor,
     device: Device,
+    dtype: DType,
 }
 
 impl Qwen3VLInferenceEngine {
+    /// 按 `text_config.dtype` 指定的精度加载模型
     pub fn new(
         model_path: impl AsRef<Path>,
         tokenizer_path: impl AsRef<Path>,
         config: Qwen3VLConfig,
         device: Device,
     ) -> Result<Self> {
+        let dtype = weights::parse_dtype(&config.text_config.dtype)?;
+        Self::new_with_dtype(model_path, tokenizer_path, config, device, dtype)
+    }
+
+    /// 以指定精度加载模型
+    pub fn new_with_dtype(
+        model_path: impl AsRef<Path>,
+        tokenizer_path: impl AsRef<Path>,
+        config: Qwen3VLConfig,
+        device: Device,
+        dtype: DType,
+    ) -> Result<Self> {
+        weights::ensure_dtype_supported(dtype, &device)?;
         let tokenizer = Tokenizer::from_file(tokenizer_path)
             .map_err(|e| anyhow::anyhow!("Tokenizer load failed: {}", e))?;
 
-        let vb = unsafe {
-            VarBuilder::from_mmaped_safetensors(
-                &[model_path.as_ref().to_path_buf()],
-                candle_core::DType::F32,
-                &device,
-            )?
-        };
+        let weights = SafetensorsFiles::from_path(model_path)?;
+        let vb = unsafe { weights.var_builder(dtype, &device)? };
 
         let model = Qwen3VLModel::new(&config, vb)?;
         let processor = Qwen3VLProcessor::new(&config, &device)?;
@@ -39,6 +49,7 @@ impl Qwen3VLInferenceEngine {
             tokenizer,
             processor,
             device,
+            dtype,
         })
     }
 
@@ -49,7 +60,7 @@ impl Qwen3VLInferenceEngine {
 
         if let Some(img) = image {
             let (pv, gthw) = self.processor.process_image(&img, &self.device)?;
-            pixel_values = Some(pv);
+            pixel_values = Some(pv.to_dtype(self.dtype)?);
             grid_thw = Some(gthw);
         }
 
@@ -5,8 +5,7 @@ use candle_nn::{
 };
 
 use crate::models::qwen3vl::config::{Qwen3VLConfig, Qwen3VLVisionConfig};
-use crate::utils::rope::{apply_rotary_pos_emb, Qwen3VLTextRotaryEmbedding};
-use crate::utils::tensor_utils::split_tensor;
+use crate::utils::rope::Qwen3VLTextRotaryEmbedding;
 
 pub struct Qwen3VLVisionPatchEmbed {
     conv3d_weight: Tensor,
@@ -218,6 +217,7 @@ impl Qwen3VLVisionModel {
     }
 }
 
+#[allow(dead_code)] // rope/config 留给尚未实现的语言模型部分
 pub struct Qwen3VLModel {
     vision_model: Qwen3VLVisionModel,
     // language_model: Qwen2Model, // Placeholder for actual LM
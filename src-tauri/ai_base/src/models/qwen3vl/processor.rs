@@ -15,7 +15,7 @@ impl Qwen3VLProcessor {
         let mean =
             Tensor::new(&[0.48145466f32, 0.4578275, 0.40821073], device)?.reshape((3, 1, 1))?;
         let std =
-            Tensor::new(&[0.26862954f32, 0.26130258, 0.2757771], device)?.reshape((3, 1, 1))?;
+            Tensor::new(&[0.26862954f32, 0.2613026, 0.2757771], device)?.reshape((3, 1, 1))?;
 
         Ok(Self {
             config: config.clone(),
@@ -33,8 +33,8 @@ impl Qwen3VLProcessor {
             h,
             w,
             factor as u32,
-            28 as u32,          // placeholder min pixels
-            1024 * 1024 as u32, // placeholder max pixels
+            28,          // placeholder min pixels
+            1024 * 1024, // placeholder max pixels
         )?;
 
         let resized_img =
@@ -0,0 +1,157 @@
+//! 模型加载进度
+//!
+//! 加载分为打开文件、解析文件头、逐个加载张量和加载 tokenizer 几个阶段，
+//! 每个阶段通过 [`ProgressCallback`] 报告，调用方可以据此显示进度条。
+
+use crate::memory::tensor_bytes;
+use candle_core::quantized::gguf_file;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+/// 加载进度事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "phase", rename_all = "snake_case")]
+pub enum LoadProgress {
+    /// 打开模型文件，`file_bytes` 为全部权重文件的大小
+    Open { path: PathBuf, file_bytes: u64 },
+    /// 文件头解析完成，`total_bytes` 为全部张量加载后的字节数
+    Header {
+        tensor_count: usize,
+        total_bytes: u64,
+    },
+    /// 一个张量加载完成
+    Tensor {
+        name: String,
+        bytes: u64,
+        loaded_bytes: u64,
+        total_bytes: u64,
+    },
+    /// 开始加载 tokenizer
+    Tokenizer,
+    /// 加载完成
+    Done,
+}
+
+/// 加载进度回调，可能在加载线程以外的线程上调用
+pub type ProgressCallback<'a> = &'a (dyn Fn(LoadProgress) + Send + Sync);
+
+/// 忽略进度的回调
+pub fn ignore_progress(_: LoadProgress) {}
+
+/// 包装 GGUF 文件读取，按张量数据的偏移识别正在读取的张量并报告进度
+pub(crate) struct ProgressReader<'a, R> {
+    inner: R,
+    /// 张量数据的绝对偏移 -> (张量名, 字节数)
+    tensors: HashMap<u64, (String, u64)>,
+    /// 正在读取的张量及其剩余字节数
+    current: Option<(String, u64, u64)>,
+    loaded_bytes: u64,
+    total_bytes: u64,
+    on_progress: ProgressCallback<'a>,
+}
+
+impl<'a, R> ProgressReader<'a, R> {
+    pub(crate) fn new(
+        inner: R,
+        ct: &gguf_file::Content,
+        on_progress: ProgressCallback<'a>,
+    ) -> Self {
+        let tensors: HashMap<u64, (String, u64)> = ct
+            .tensor_infos
+            .iter()
+            .map(|(name, info)| {
+                let offset = ct.tensor_data_offset + info.offset;
+                (offset, (name.clone(), tensor_bytes(info)))
+            })
+            .collect();
+        let total_bytes = tensors.values().map(|(_, bytes)| bytes).sum();
+        Self {
+            inner,
+            tensors,
+            current: None,
+            loaded_bytes: 0,
+            total_bytes,
+            on_progress,
+        }
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let offset = self.inner.seek(pos)?;
+        self.current = self
+            .tensors
+            .get(&offset)
+            .map(|(name, bytes)| (name.clone(), *bytes, *bytes));
+        Ok(offset)
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some((_, _, remaining)) = self.current.as_mut() {
+            *remaining = remaining.saturating_sub(n as u64);
+            if *remaining == 0 {
+                let (name, bytes, _) = self.current.take().unwrap();
+                self.loaded_bytes += bytes;
+                (self.on_progress)(LoadProgress::Tensor {
+                    name,
+                    bytes,
+                    loaded_bytes: self.loaded_bytes,
+                    total_bytes: self.total_bytes,
+                });
+            }
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candle_core::quantized::{GgmlDType, QTensor};
+    use candle_core::{DType, Device, Tensor};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_progress_reader_reports_each_tensor() {
+        let tensor = Tensor::ones((2, 32), DType::F32, &Device::Cpu).unwrap();
+        let a = QTensor::quantize(&tensor, GgmlDType::Q8_0).unwrap();
+        let b = QTensor::quantize(&tensor, GgmlDType::F32).unwrap();
+        let mut buf = std::io::Cursor::new(Vec::new());
+        gguf_file::write(&mut buf, &[], &[("a", &a), ("b", &b)]).unwrap();
+        buf.set_position(0);
+        let ct = gguf_file::Content::read(&mut buf).unwrap();
+
+        let events = Mutex::new(Vec::new());
+        let on_progress = |event: LoadProgress| events.lock().unwrap().push(event);
+        let mut reader = ProgressReader::new(&mut buf, &ct, &on_progress);
+        ct.tensor(&mut reader, "b", &Device::Cpu).unwrap();
+        ct.tensor(&mut reader, "a", &Device::Cpu).unwrap();
+
+        let events = events.into_inner().unwrap();
+        let loaded: Vec<(String, u64, u64)> = events
+            .into_iter()
+            .map(|event| match event {
+                LoadProgress::Tensor {
+                    name,
+                    loaded_bytes,
+                    total_bytes,
+                    ..
+                } => (name, loaded_bytes, total_bytes),
+                other => panic!("unexpected event: {:?}", other),
+            })
+            .collect();
+        let total = 64 * 4 + 2 * 34;
+        assert_eq!(
+            loaded,
+            vec![
+                ("b".to_string(), 64 * 4, total),
+                ("a".to_string(), total, total),
+            ]
+        );
+    }
+}
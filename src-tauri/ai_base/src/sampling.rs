@@ -0,0 +1,601 @@
+//! 采样模块
+//!
+//! 采样流程由若干 [`LogitsProcessor`] 组成的处理链完成：每个处理器依次修改或裁剪
+//! 候选 token 集合，最后由 [`Sampler`] 贪婪选择或按概率随机抽取。
+//!
+//! 为了在大词表（15 万量级）上保持低开销，处理链避免对完整词表排序：top-k 使用
+//! `select_nth_unstable` 先行裁剪，top-p 与 typical-p 只对达到累计概率所需的前缀分块排序，
+//! min-p 只需线性扫描。
+
+use anyhow::Result;
+use candle_core::{DType, Tensor};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 采样参数
+///
+/// 各项取“关闭”值时对应的处理器不会加入处理链。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplingParams {
+    /// 温度，小于等于 0 时使用贪婪采样
+    pub temperature: f64,
+    /// 只保留概率最高的 k 个 token，0 表示关闭
+    pub top_k: usize,
+    /// 核采样累计概率阈值，大于等于 1 表示关闭
+    pub top_p: f64,
+    /// 相对最高概率的最小概率比例，0 表示关闭
+    pub min_p: f64,
+    /// 典型采样（locally typical sampling）阈值，大于等于 1 表示关闭
+    pub typical_p: f64,
+    /// 重复惩罚（正 logit 除以该值，负 logit 乘以该值），1 表示关闭
+    pub repetition_penalty: f32,
+    /// 频率惩罚，按 token 出现次数线性扣减 logit
+    pub frequency_penalty: f32,
+    /// 存在惩罚，出现过的 token 扣减固定值
+    pub presence_penalty: f32,
+    /// 惩罚只考虑最近的 n 个 token，0 表示考虑全部上下文
+    pub penalty_last_n: usize,
+    /// 按 token id 加到 logit 上的偏置
+    pub logit_bias: HashMap<u32, f32>,
+    /// 随机种子，相同种子与相同输入得到相同输出
+    pub seed: Option<u64>,
+}
+
+impl Default for SamplingParams {
+    fn default() -> Self {
+        Self {
+            temperature: 0.8,
+            top_k: 40,
+            top_p: 0.9,
+            min_p: 0.0,
+            typical_p: 1.0,
+            repetition_penalty: 1.0,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            penalty_last_n: 64,
+            logit_bias: HashMap::new(),
+            seed: None,
+        }
+    }
+}
+
+/// 候选 token 集合：`(token_id, logit)` 列表
+#[derive(Debug, Clone)]
+pub struct Candidates {
+    tokens: Vec<(u32, f32)>,
+    /// 是否已按 logit 降序排列
+    sorted: bool,
+}
+
+impl Candidates {
+    /// 由完整词表的 logits 创建，下标即 token id
+    pub fn from_logits(logits: &[f32]) -> Self {
+        Self {
+            tokens: logits
+                .iter()
+                .enumerate()
+                .map(|(id, &logit)| (id as u32, logit))
+                .collect(),
+            sorted: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// 当前候选（若已排序则按 logit 降序）
+    pub fn as_slice(&self) -> &[(u32, f32)] {
+        &self.tokens
+    }
+
+    /// 查找指定 token 的 logit
+    ///
+    /// 未裁剪、未排序时下标即 token id，可直接定位；否则退化为线性查找。
+    pub fn logit_mut(&mut self, token: u32) -> Option<&mut f32> {
+        let direct = self
+            .tokens
+            .get(token as usize)
+            .is_some_and(|&(id, _)| id == token);
+        let index = if direct {
+            Some(token as usize)
+        } else {
+            self.tokens.iter().position(|&(id, _)| id == token)
+        };
+        let index = index?;
+        // 修改单个 logit 可能破坏排序
+        self.sorted = false;
+        Some(&mut self.tokens[index].1)
+    }
+
+    /// 所有 logit 乘以正数因子（不改变顺序）
+    pub fn scale(&mut self, factor: f32) {
+        for (_, logit) in self.tokens.iter_mut() {
+            *logit *= factor;
+        }
+    }
+
+    /// 按 logit 降序排列（已排序时不做任何事）
+    pub fn sort_desc(&mut self) {
+        if !self.sorted {
+            self.tokens.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+            self.sorted = true;
+        }
+    }
+
+    /// 只保留 logit 最高的 k 个，结果按降序排列
+    pub fn keep_top_k(&mut self, k: usize) {
+        let k = k.max(1);
+        if k < self.tokens.len() {
+            if !self.sorted {
+                self.tokens
+                    .select_nth_unstable_by(k - 1, |a, b| b.1.total_cmp(&a.1));
+            }
+            self.tokens.truncate(k);
+        }
+        self.sort_desc();
+    }
+
+    /// 保留满足条件的候选，保持原有顺序
+    pub fn retain(&mut self, f: impl FnMut(&(u32, f32)) -> bool) {
+        self.tokens.retain(f);
+    }
+
+    /// 最大 logit
+    pub fn max_logit(&self) -> f32 {
+        if self.sorted {
+            return self.tokens.first().map_or(f32::NEG_INFINITY, |t| t.1);
+        }
+        self.tokens
+            .iter()
+            .map(|t| t.1)
+            .fold(f32::NEG_INFINITY, f32::max)
+    }
+
+    /// 对当前候选做 softmax，返回与 [`Self::as_slice`] 顺序一致的概率
+    pub fn probs(&self) -> Vec<f32> {
+        let max = self.max_logit();
+        let mut probs: Vec<f32> = self.tokens.iter().map(|t| (t.1 - max).exp()).collect();
+        let sum: f32 = probs.iter().sum();
+        if sum > 0.0 {
+            for p in probs.iter_mut() {
+                *p /= sum;
+            }
+        }
+        probs
+    }
+
+    /// logit 最高的 token
+    pub fn argmax(&self) -> Option<u32> {
+        if self.sorted {
+            return self.tokens.first().map(|t| t.0);
+        }
+        self.tokens
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|t| t.0)
+    }
+}
+
+/// 采样处理器：修改或裁剪候选集合
+///
+/// `context` 为提示词与已生成 token 组成的完整上下文。
+pub trait LogitsProcessor: Send {
+    fn process(&self, candidates: &mut Candidates, context: &[u32]);
+}
+
+/// 按 token id 加偏置
+pub struct LogitBias(pub HashMap<u32, f32>);
+
+impl LogitsProcessor for LogitBias {
+    fn process(&self, candidates: &mut Candidates, _context: &[u32]) {
+        for (&token, &bias) in &self.0 {
+            if let Some(logit) = candidates.logit_mut(token) {
+                *logit += bias;
+            }
+        }
+    }
+}
+
+/// 重复、频率与存在惩罚
+pub struct Penalties {
+    pub repetition: f32,
+    pub frequency: f32,
+    pub presence: f32,
+    /// 只统计最近的 n 个 token，0 表示全部
+    pub last_n: usize,
+}
+
+impl LogitsProcessor for Penalties {
+    fn process(&self, candidates: &mut Candidates, context: &[u32]) {
+        let window = if self.last_n == 0 {
+            context
+        } else {
+            &context[context.len().saturating_sub(self.last_n)..]
+        };
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        for &token in window {
+            *counts.entry(token).or_default() += 1;
+        }
+
+        for (token, count) in counts {
+            if let Some(logit) = candidates.logit_mut(token) {
+                if *logit > 0.0 {
+                    *logit /= self.repetition;
+                } else {
+                    *logit *= self.repetition;
+                }
+                *logit -= count as f32 * self.frequency + self.presence;
+            }
+        }
+    }
+}
+
+/// 温度缩放
+pub struct Temperature(pub f64);
+
+impl LogitsProcessor for Temperature {
+    fn process(&self, candidates: &mut Candidates, _context: &[u32]) {
+        candidates.scale(1.0 / self.0 as f32);
+    }
+}
+
+/// Top-k 裁剪
+pub struct TopK(pub usize);
+
+impl LogitsProcessor for TopK {
+    fn process(&self, candidates: &mut Candidates, _context: &[u32]) {
+        candidates.keep_top_k(self.0);
+    }
+}
+
+/// Top-p（核）采样：保留累计概率达到 p 的最小前缀
+pub struct TopP(pub f64);
+
+impl LogitsProcessor for TopP {
+    fn process(&self, candidates: &mut Candidates, _context: &[u32]) {
+        let max = candidates.max_logit();
+        let total: f32 = candidates.tokens.iter().map(|t| (t.1 - max).exp()).sum();
+        // 在未归一化的概率上累加，省去一次除法
+        let target = self.0 as f32 * total;
+
+        let mut cumsum = 0.0;
+        let keep = sort_prefix_until(
+            &mut candidates.tokens,
+            |a, b| b.1.total_cmp(&a.1),
+            |t| {
+                cumsum += (t.1 - max).exp();
+                cumsum >= target
+            },
+        );
+        candidates.tokens.truncate(keep.max(1));
+        candidates.sorted = true;
+    }
+}
+
+/// Min-p：丢弃概率低于最高概率 p 倍的 token
+pub struct MinP(pub f64);
+
+impl LogitsProcessor for MinP {
+    fn process(&self, candidates: &mut Candidates, _context: &[u32]) {
+        // p_i >= min_p * p_max 等价于 logit_i >= logit_max + ln(min_p)，无需 softmax
+        let threshold = candidates.max_logit() + (self.0 as f32).ln();
+        candidates.retain(|&(_, logit)| logit >= threshold);
+    }
+}
+
+/// 典型采样：保留信息量最接近分布熵的 token，直到累计概率达到 p
+pub struct TypicalP(pub f64);
+
+impl LogitsProcessor for TypicalP {
+    fn process(&self, candidates: &mut Candidates, _context: &[u32]) {
+        let probs = candidates.probs();
+        let entropy: f32 = probs
+            .iter()
+            .filter(|&&p| p > 0.0)
+            .map(|&p| -p * p.ln())
+            .sum();
+
+        let mut scored: Vec<(f32, f32, (u32, f32))> = probs
+            .iter()
+            .zip(candidates.tokens.iter())
+            .map(|(&p, &token)| ((-p.ln() - entropy).abs(), p, token))
+            .collect();
+
+        let mut cumsum = 0.0;
+        let keep = sort_prefix_until(
+            &mut scored,
+            |a, b| a.0.total_cmp(&b.0),
+            |(_, p, _)| {
+                cumsum += p;
+                cumsum >= self.0 as f32
+            },
+        );
+        candidates.tokens = scored
+            .into_iter()
+            .take(keep.max(1))
+            .map(|(_, _, token)| token)
+            .collect();
+        candidates.sorted = false;
+    }
+}
+
+/// 按 `compare` 分块排序 `items` 的前缀，并依次把元素交给 `take`，直到其返回 true
+///
+/// 返回已交出的元素个数，前这么多个元素保证有序。每块先用 `select_nth_unstable`
+/// 选出，块大小逐次翻倍；累计概率通常在很短的前缀内就能达到阈值，
+/// 因此大多数情况下不需要对整个词表排序。
+fn sort_prefix_until<T>(
+    items: &mut [T],
+    compare: impl Fn(&T, &T) -> std::cmp::Ordering + Copy,
+    mut take: impl FnMut(&T) -> bool,
+) -> usize {
+    let mut sorted_len = 0;
+    let mut taken = 0;
+    let mut chunk = 256;
+    while taken < items.len() {
+        if taken == sorted_len {
+            let end = (sorted_len + chunk).min(items.len());
+            let tail = &mut items[sorted_len..];
+            let chunk_len = end - sorted_len;
+            if chunk_len < tail.len() {
+                tail.select_nth_unstable_by(chunk_len - 1, compare);
+            }
+            tail[..chunk_len].sort_unstable_by(compare);
+            sorted_len = end;
+            chunk *= 2;
+        }
+        taken += 1;
+        if take(&items[taken - 1]) {
+            break;
+        }
+    }
+    taken
+}
+
+/// 采样器：依次执行处理链，然后选出下一个 token
+pub struct Sampler {
+    processors: Vec<Box<dyn LogitsProcessor>>,
+    greedy: bool,
+    rng: StdRng,
+}
+
+impl Sampler {
+    /// 创建随机采样器（处理链为空），`seed` 为 `None` 时使用系统随机种子
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        Self {
+            processors: Vec::new(),
+            greedy: false,
+            rng,
+        }
+    }
+
+    /// 创建贪婪采样器：处理链执行完后选择 logit 最高的 token
+    pub fn greedy() -> Self {
+        Self {
+            greedy: true,
+            ..Self::new(Some(0))
+        }
+    }
+
+    /// 在处理链末尾追加一个处理器
+    pub fn with_processor(mut self, processor: impl LogitsProcessor + 'static) -> Self {
+        self.processors.push(Box::new(processor));
+        self
+    }
+
+    /// 按参数构建标准处理链：
+    /// logit 偏置 → 惩罚 → 温度 → top-k → typical-p → top-p → min-p
+    pub fn from_params(params: &SamplingParams) -> Self {
+        let greedy = params.temperature <= 0.0;
+        let mut sampler = if greedy {
+            Self::greedy()
+        } else {
+            Self::new(params.seed)
+        };
+
+        if !params.logit_bias.is_empty() {
+            sampler = sampler.with_processor(LogitBias(params.logit_bias.clone()));
+        }
+        if params.repetition_penalty != 1.0
+            || params.frequency_penalty != 0.0
+            || params.presence_penalty != 0.0
+        {
+            sampler = sampler.with_processor(Penalties {
+                repetition: params.repetition_penalty,
+                frequency: params.frequency_penalty,
+                presence: params.presence_penalty,
+                last_n: params.penalty_last_n,
+            });
+        }
+
+        // 贪婪采样只取最大值，裁剪类处理器不影响结果
+        if greedy {
+            return sampler;
+        }
+
+        if params.temperature != 1.0 {
+            sampler = sampler.with_processor(Temperature(params.temperature));
+        }
+        if params.top_k > 0 {
+            sampler = sampler.with_processor(TopK(params.top_k));
+        }
+        if params.typical_p > 0.0 && params.typical_p < 1.0 {
+            sampler = sampler.with_processor(TypicalP(params.typical_p));
+        }
+        if params.top_p > 0.0 && params.top_p < 1.0 {
+            sampler = sampler.with_processor(TopP(params.top_p));
+        }
+        if params.min_p > 0.0 && params.min_p < 1.0 {
+            sampler = sampler.with_processor(MinP(params.min_p));
+        }
+        sampler
+    }
+
+    /// 从一维 logits 张量中采样
+    pub fn sample(&mut self, logits: &Tensor, context: &[u32]) -> Result<u32> {
+        let logits = logits.to_dtype(DType::F32)?.to_vec1::<f32>()?;
+        self.sample_logits(&logits, context)
+    }
+
+    /// 从 logits 切片中采样（下标即 token id）
+    pub fn sample_logits(&mut self, logits: &[f32], context: &[u32]) -> Result<u32> {
+        let mut candidates = Candidates::from_logits(logits);
+        for processor in &self.processors {
+            processor.process(&mut candidates, context);
+        }
+
+        if self.greedy {
+            return candidates
+                .argmax()
+                .ok_or_else(|| anyhow::anyhow!("没有可采样的候选 token"));
+        }
+
+        let probs = candidates.probs();
+        let rand_val: f32 = self.rng.random();
+        let mut cumsum = 0.0;
+        for (&(token, _), p) in candidates.as_slice().iter().zip(probs) {
+            cumsum += p;
+            if rand_val < cumsum {
+                return Ok(token);
+            }
+        }
+        // 浮点误差导致未命中时返回最后一个候选
+        candidates
+            .as_slice()
+            .last()
+            .map(|t| t.0)
+            .ok_or_else(|| anyhow::anyhow!("没有可采样的候选 token"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(candidates: &Candidates) -> Vec<u32> {
+        candidates.as_slice().iter().map(|t| t.0).collect()
+    }
+
+    #[test]
+    fn test_top_k_and_top_p() {
+        let logits = [1.0, 4.0, 3.0, 2.0, 0.0];
+        let mut candidates = Candidates::from_logits(&logits);
+        TopK(3).process(&mut candidates, &[]);
+        assert_eq!(tokens(&candidates), vec![1, 2, 3]);
+
+        // 概率约为 [0.665, 0.245, 0.090]
+        TopP(0.8).process(&mut candidates, &[]);
+        assert_eq!(tokens(&candidates), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_sort_prefix_until_crosses_chunks() {
+        let mut items: Vec<u32> = (0..2000).map(|i| (i * 7919) % 2000).collect();
+        let mut seen = 0;
+        let taken = sort_prefix_until(
+            &mut items,
+            |a, b| b.cmp(a),
+            |_| {
+                seen += 1;
+                seen == 700
+            },
+        );
+        assert_eq!(taken, 700);
+        let expected: Vec<u32> = (1300..2000).rev().collect();
+        assert_eq!(&items[..700], expected.as_slice());
+    }
+
+    #[test]
+    fn test_min_p_keeps_order() {
+        let logits = [0.0, 2.0, 1.0, 3.0];
+        let mut candidates = Candidates::from_logits(&logits);
+        // 阈值 logit 约为 3 + ln(0.3) ≈ 1.8
+        MinP(0.3).process(&mut candidates, &[]);
+        assert_eq!(tokens(&candidates), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_typical_p_keeps_at_least_one() {
+        let logits = [0.0, 10.0, 0.0];
+        let mut candidates = Candidates::from_logits(&logits);
+        TypicalP(0.01).process(&mut candidates, &[]);
+        assert_eq!(candidates.len(), 1);
+    }
+
+    #[test]
+    fn test_penalties_and_bias() {
+        let logits = [2.0, -2.0, 1.0];
+        let mut candidates = Candidates::from_logits(&logits);
+        Penalties {
+            repetition: 2.0,
+            frequency: 0.5,
+            presence: 0.25,
+            last_n: 0,
+        }
+        .process(&mut candidates, &[0, 1, 1]);
+        let logits: Vec<f32> = candidates.as_slice().iter().map(|t| t.1).collect();
+        assert_eq!(logits, vec![2.0 / 2.0 - 0.75, -2.0 * 2.0 - 1.25, 1.0]);
+
+        let mut candidates = Candidates::from_logits(&[0.0, 0.0, 0.0]);
+        LogitBias(HashMap::from([(2, 5.0)])).process(&mut candidates, &[]);
+        assert_eq!(candidates.argmax(), Some(2));
+    }
+
+    #[test]
+    fn test_penalty_window() {
+        let mut candidates = Candidates::from_logits(&[1.0, 1.0]);
+        Penalties {
+            repetition: 1.0,
+            frequency: 0.0,
+            presence: 1.0,
+            last_n: 1,
+        }
+        .process(&mut candidates, &[0, 1]);
+        let logits: Vec<f32> = candidates.as_slice().iter().map(|t| t.1).collect();
+        assert_eq!(logits, vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        let logits: Vec<f32> = (0..1000).map(|i| ((i * 37) % 101) as f32 / 20.0).collect();
+        let params = SamplingParams {
+            temperature: 1.0,
+            top_k: 0,
+            top_p: 1.0,
+            seed: Some(42),
+            ..Default::default()
+        };
+        let run = || {
+            let mut sampler = Sampler::from_params(&params);
+            (0..20)
+                .map(|_| sampler.sample_logits(&logits, &[]).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_greedy() {
+        let params = SamplingParams {
+            temperature: 0.0,
+            repetition_penalty: 10.0,
+            ..Default::default()
+        };
+        let mut sampler = Sampler::from_params(&params);
+        assert_eq!(sampler.sample_logits(&[1.0, 3.0, 2.0], &[]).unwrap(), 1);
+        // token 1 被惩罚后 token 2 最高
+        assert_eq!(sampler.sample_logits(&[1.0, 3.0, 2.0], &[1]).unwrap(), 2);
+    }
+}
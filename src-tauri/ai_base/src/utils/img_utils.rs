@@ -1,4 +1,4 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use candle_core::{DType, Device, Tensor};
 use image::{DynamicImage, GenericImageView};
 
@@ -25,8 +25,8 @@ pub fn img_smart_resize(
         w = (w as f32 * ratio) as u32;
     }
 
-    h = (h + factor - 1) / factor * factor;
-    w = (w + factor - 1) / factor * factor;
+    h = h.div_ceil(factor) * factor;
+    w = w.div_ceil(factor) * factor;
 
     Ok((h, w))
 }
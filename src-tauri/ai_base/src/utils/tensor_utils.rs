@@ -45,7 +45,7 @@ pub fn prepare_causal_attention_mask(
     Ok(mask)
 }
 
-pub fn split_tensor<D_DIM: Dim>(t: &Tensor, splits: &[usize], dim: D_DIM) -> Result<Vec<Tensor>> {
+pub fn split_tensor<D: Dim>(t: &Tensor, splits: &[usize], dim: D) -> Result<Vec<Tensor>> {
     let dim = dim.to_index(t.shape(), "split").map_err(|e| anyhow!(e))?;
     let mut split_res = Vec::new();
     let mut index = 0;
@@ -82,10 +82,10 @@ pub fn nonzero_index(mask: &Tensor) -> Result<Tensor> {
 
 pub fn linspace(start: f32, stop: f32, num: usize, device: &Device) -> Result<Tensor> {
     if num == 0 {
-        return Ok(Tensor::from_vec(Vec::<f32>::new(), (0,), device).map_err(|e| anyhow!(e))?);
+        return Tensor::from_vec(Vec::<f32>::new(), (0,), device).map_err(|e| anyhow!(e));
     }
     if num == 1 {
-        return Ok(Tensor::new(&[start], device).map_err(|e| anyhow!(e))?);
+        return Tensor::new(&[start], device).map_err(|e| anyhow!(e));
     }
     let step = (stop - start) / (num - 1) as f32;
     let v: Vec<f32> = (0..num).map(|i| start + i as f32 * step).collect();
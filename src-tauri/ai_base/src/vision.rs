@@ -30,17 +30,19 @@ pub struct ImagePreprocessor {
     config: ImagePreprocessConfig,
 }
 
+impl Default for ImagePreprocessor {
+    /// 从默认配置创建
+    fn default() -> Self {
+        Self::new(ImagePreprocessConfig::default())
+    }
+}
+
 impl ImagePreprocessor {
     /// 创建新的图像预处理器
     pub fn new(config: ImagePreprocessConfig) -> Self {
         Self { config }
     }
 
-    /// 从默认配置创建
-    pub fn default() -> Self {
-        Self::new(ImagePreprocessConfig::default())
-    }
-
     /// 从文件路径加载并预处理图像
     pub fn load_and_preprocess<P: AsRef<Path>>(
         &self,
@@ -0,0 +1,543 @@
+//! safetensors 权重定位与加载
+//!
+//! 支持单个 safetensors 文件，以及由 `model.safetensors.index.json` 描述的分片权重
+//! （7B 以上的 HF 模型通常拆分为多个分片）。权重按 `config.json` 中的 `torch_dtype`
+//! 或调用方指定的精度加载。
+
+use crate::progress::{ignore_progress, LoadProgress, ProgressCallback};
+use anyhow::{Context, Result};
+use candle_core::safetensors::MmapedSafetensors;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::var_builder::SimpleBackend;
+use candle_nn::VarBuilder;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 分片索引文件名
+pub const SAFETENSORS_INDEX_FILE: &str = "model.safetensors.index.json";
+
+/// 缺失张量过多时，错误信息中最多列出的名称数
+const MAX_REPORTED_TENSORS: usize = 10;
+
+/// 解析 HF 配置中的精度名称（`float32`、`float16`、`bfloat16` 及其简写）
+pub fn parse_dtype(name: &str) -> Result<DType> {
+    match name.trim().to_lowercase().as_str() {
+        "float32" | "f32" | "fp32" => Ok(DType::F32),
+        "float16" | "f16" | "fp16" | "half" => Ok(DType::F16),
+        "bfloat16" | "bf16" => Ok(DType::BF16),
+        other => anyhow::bail!(
+            "不支持的计算精度: {}（支持 float32、float16、bfloat16）",
+            other
+        ),
+    }
+}
+
+/// 读取 `config.json` 声明的权重精度
+///
+/// 依次查找 `torch_dtype`、`dtype`，以及多模态模型 `text_config` 中的同名字段；
+/// 都没有或值为 `auto` 时返回 `None`。
+pub fn dtype_from_config(config_path: &Path) -> Result<Option<DType>> {
+    let content = std::fs::read_to_string(config_path)
+        .with_context(|| format!("无法读取模型配置: {:?}", config_path))?;
+    let config: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("无法解析模型配置: {:?}", config_path))?;
+
+    let name = [&config, &config["text_config"]]
+        .into_iter()
+        .flat_map(|c| [&c["torch_dtype"], &c["dtype"]])
+        .find_map(|v| v.as_str());
+    match name {
+        None | Some("auto") => Ok(None),
+        Some(name) => parse_dtype(name).map(Some),
+    }
+}
+
+/// 模型所在目录：`model_path` 本身是目录时返回它，否则返回其所在目录
+///
+/// `model_path` 可以是模型目录，也可以是目录中的权重文件或索引文件。
+pub fn model_dir(model_path: &Path) -> &Path {
+    if model_path.is_dir() {
+        model_path
+    } else {
+        model_path.parent().unwrap_or(Path::new("."))
+    }
+}
+
+/// 确定 safetensors 模型的计算精度：显式指定 > 模型目录 `config.json` 的 `torch_dtype` > F32
+///
+/// `model_path` 可以是模型目录，也可以是目录中的权重文件或索引文件。
+pub fn resolve_dtype(explicit: Option<DType>, model_path: &Path) -> Result<DType> {
+    if let Some(dtype) = explicit {
+        return Ok(dtype);
+    }
+    let config_path = model_dir(model_path).join("config.json");
+    if !config_path.exists() {
+        return Ok(DType::F32);
+    }
+    Ok(dtype_from_config(&config_path)?.unwrap_or(DType::F32))
+}
+
+/// 检查设备能否以指定精度计算，不支持时返回明确的错误
+///
+/// 用一次小矩阵乘法探测，覆盖 CPU 与 Metal 等后端对半精度支持不一致的情况。
+pub fn ensure_dtype_supported(dtype: DType, device: &Device) -> Result<()> {
+    if !matches!(dtype, DType::F32 | DType::F16 | DType::BF16) {
+        anyhow::bail!("不支持的计算精度: {:?}（支持 F32、F16、BF16）", dtype);
+    }
+    let probe = Tensor::ones((2, 2), dtype, device).and_then(|t| t.matmul(&t));
+    probe.map(|_| ()).map_err(|e| {
+        anyhow::anyhow!(
+            "设备 {:?} 不支持 {:?} 精度计算: {}，请改用 F32",
+            device.location(),
+            dtype,
+            e
+        )
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct SafetensorsIndex {
+    /// 张量名 -> 分片文件名
+    weight_map: HashMap<String, String>,
+}
+
+/// 一个模型的全部 safetensors 权重文件
+#[derive(Debug, Clone)]
+pub struct SafetensorsFiles {
+    files: Vec<PathBuf>,
+    /// 索引文件中声明的张量名（没有索引文件时为空，不做检查）
+    expected_tensors: Vec<String>,
+}
+
+impl SafetensorsFiles {
+    /// 从模型目录、索引文件或单个 safetensors 文件定位权重
+    ///
+    /// 目录中优先使用 `model.safetensors.index.json`，其次是 `model.safetensors`，
+    /// 都没有时加载目录下全部 `.safetensors` 文件。
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let index = path.join(SAFETENSORS_INDEX_FILE);
+            if index.exists() {
+                return Self::from_index(&index);
+            }
+            let single = path.join("model.safetensors");
+            if single.exists() {
+                return Ok(Self::single(single));
+            }
+
+            let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+                .with_context(|| format!("无法读取模型目录: {:?}", path))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().and_then(|s| s.to_str()) == Some("safetensors"))
+                .collect();
+            if files.is_empty() {
+                anyhow::bail!("模型目录中没有 safetensors 权重文件: {:?}", path);
+            }
+            files.sort();
+            return Ok(Self {
+                files,
+                expected_tensors: Vec::new(),
+            });
+        }
+
+        if path.to_string_lossy().ends_with(".index.json") {
+            return Self::from_index(path);
+        }
+        if !path.exists() {
+            anyhow::bail!("模型文件不存在: {:?}", path);
+        }
+        Ok(Self::single(path.to_path_buf()))
+    }
+
+    /// 读取分片索引，所有分片都必须存在，缺失的分片按文件名报告
+    pub fn from_index(index_path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(index_path)
+            .with_context(|| format!("无法读取分片索引: {:?}", index_path))?;
+        let index: SafetensorsIndex = serde_json::from_str(&content)
+            .with_context(|| format!("无法解析分片索引: {:?}", index_path))?;
+        let dir = index_path.parent().unwrap_or(Path::new("."));
+
+        let shards: BTreeSet<String> = index.weight_map.values().cloned().collect();
+        let missing: Vec<&str> = shards
+            .iter()
+            .filter(|shard| !dir.join(shard).exists())
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "缺少 {} 个权重分片: {}（目录: {:?}）",
+                missing.len(),
+                missing.join(", "),
+                dir
+            );
+        }
+
+        let mut expected_tensors: Vec<String> = index.weight_map.into_keys().collect();
+        expected_tensors.sort();
+        Ok(Self {
+            files: shards.into_iter().map(|shard| dir.join(shard)).collect(),
+            expected_tensors,
+        })
+    }
+
+    fn single(file: PathBuf) -> Self {
+        Self {
+            files: vec![file],
+            expected_tensors: Vec::new(),
+        }
+    }
+
+    /// 权重文件列表
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// 按文件头估算以 `dtype` 加载后权重常驻内存的字节数
+    ///
+    /// 只读取每个文件开头的 JSON 头部；浮点张量按 `dtype` 换算，其他张量保持原大小。
+    pub fn estimated_bytes(&self, dtype: DType) -> Result<u64> {
+        let mut total = 0u64;
+        for file in &self.files {
+            for (dtype_name, numel) in read_header_tensors(file)? {
+                let elem_size = match dtype_name.as_str() {
+                    "F64" | "F32" | "F16" | "BF16" => dtype.size_in_bytes(),
+                    "I64" | "U64" => 8,
+                    "I32" | "U32" => 4,
+                    "I16" | "U16" => 2,
+                    _ => 1,
+                };
+                total += numel * elem_size as u64;
+            }
+        }
+        Ok(total)
+    }
+
+    /// 全部权重文件的大小
+    pub fn file_bytes(&self) -> u64 {
+        self.files
+            .iter()
+            .filter_map(|file| std::fs::metadata(file).ok())
+            .map(|m| m.len())
+            .sum()
+    }
+
+    /// 内存映射全部权重文件，并检查索引中声明的张量都存在
+    ///
+    /// # Safety
+    ///
+    /// 与 [`VarBuilder::from_mmaped_safetensors`] 相同：映射期间权重文件不能被修改。
+    pub unsafe fn var_builder(&self, dtype: DType, device: &Device) -> Result<VarBuilder<'static>> {
+        self.var_builder_with_progress(dtype, device, &ignore_progress)
+    }
+
+    /// 同 [`Self::var_builder`]，映射后报告文件头信息，模型每取出一个张量报告一次进度
+    ///
+    /// # Safety
+    ///
+    /// 与 [`VarBuilder::from_mmaped_safetensors`] 相同：映射期间权重文件不能被修改。
+    pub unsafe fn var_builder_with_progress<'a>(
+        &self,
+        dtype: DType,
+        device: &Device,
+        on_progress: ProgressCallback<'a>,
+    ) -> Result<VarBuilder<'a>> {
+        let safetensors = MmapedSafetensors::multi(&self.files)
+            .with_context(|| format!("无法映射权重文件: {:?}", self.files))?;
+
+        if !self.expected_tensors.is_empty() {
+            let available: HashSet<String> = safetensors
+                .tensors()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+            let missing: Vec<&str> = self
+                .expected_tensors
+                .iter()
+                .filter(|name| !available.contains(*name))
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                let more = missing.len().saturating_sub(MAX_REPORTED_TENSORS);
+                anyhow::bail!(
+                    "权重分片中缺少 {} 个索引声明的张量: {}{}",
+                    missing.len(),
+                    missing[..missing.len().min(MAX_REPORTED_TENSORS)].join(", "),
+                    if more > 0 {
+                        format!(" 等（另有 {} 个）", more)
+                    } else {
+                        String::new()
+                    }
+                );
+            }
+        }
+
+        let tensors = safetensors.tensors();
+        let total_bytes = tensors
+            .iter()
+            .map(|(_, view)| {
+                let numel: usize = view.shape().iter().product();
+                let elem_size = match DType::try_from(view.dtype()) {
+                    Ok(stored) if stored.is_float() => dtype.size_in_bytes(),
+                    Ok(stored) => stored.size_in_bytes(),
+                    Err(_) => 1,
+                };
+                (numel * elem_size) as u64
+            })
+            .sum();
+        on_progress(LoadProgress::Header {
+            tensor_count: tensors.len(),
+            total_bytes,
+        });
+
+        let backend = ProgressBackend {
+            inner: safetensors,
+            loaded_bytes: AtomicU64::new(0),
+            total_bytes,
+            on_progress,
+        };
+        Ok(VarBuilder::from_backend(
+            Box::new(backend),
+            dtype,
+            device.clone(),
+        ))
+    }
+}
+
+/// 在模型取出张量时报告加载进度的 safetensors 后端
+struct ProgressBackend<'a> {
+    inner: MmapedSafetensors,
+    loaded_bytes: AtomicU64,
+    total_bytes: u64,
+    on_progress: ProgressCallback<'a>,
+}
+
+impl ProgressBackend<'_> {
+    fn report(&self, name: &str, tensor: &Tensor) {
+        let bytes = (tensor.elem_count() * tensor.dtype().size_in_bytes()) as u64;
+        let loaded_bytes = self.loaded_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        (self.on_progress)(LoadProgress::Tensor {
+            name: name.to_string(),
+            bytes,
+            loaded_bytes,
+            total_bytes: self.total_bytes,
+        });
+    }
+}
+
+impl SimpleBackend for ProgressBackend<'_> {
+    fn get(
+        &self,
+        s: candle_core::Shape,
+        name: &str,
+        h: candle_nn::Init,
+        dtype: DType,
+        dev: &Device,
+    ) -> candle_core::Result<Tensor> {
+        let tensor = SimpleBackend::get(&self.inner, s, name, h, dtype, dev)?;
+        self.report(name, &tensor);
+        Ok(tensor)
+    }
+
+    fn get_unchecked(&self, name: &str, dtype: DType, dev: &Device) -> candle_core::Result<Tensor> {
+        let tensor = self.inner.get_unchecked(name, dtype, dev)?;
+        self.report(name, &tensor);
+        Ok(tensor)
+    }
+
+    fn contains_tensor(&self, name: &str) -> bool {
+        self.inner.contains_tensor(name)
+    }
+}
+
+/// 读取 safetensors 文件头中每个张量的精度名与元素数
+fn read_header_tensors(path: &Path) -> Result<Vec<(String, u64)>> {
+    use std::io::Read;
+
+    #[derive(Deserialize)]
+    struct HeaderTensor {
+        dtype: String,
+        shape: Vec<u64>,
+    }
+
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("无法打开权重文件: {:?}", path))?;
+    let mut len = [0u8; 8];
+    file.read_exact(&mut len)
+        .with_context(|| format!("无法读取 safetensors 文件头: {:?}", path))?;
+    let mut header = vec![0u8; u64::from_le_bytes(len) as usize];
+    file.read_exact(&mut header)
+        .with_context(|| format!("无法读取 safetensors 文件头: {:?}", path))?;
+
+    let header: HashMap<String, serde_json::Value> = serde_json::from_slice(&header)
+        .with_context(|| format!("无法解析 safetensors 文件头: {:?}", path))?;
+    header
+        .into_iter()
+        .filter(|(name, _)| name != "__metadata__")
+        .map(|(name, value)| {
+            let tensor: HeaderTensor = serde_json::from_value(value)
+                .with_context(|| format!("张量 {} 的文件头格式错误: {:?}", name, path))?;
+            Ok((tensor.dtype, tensor.shape.iter().product()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ai_base_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_shard(path: &Path, names: &[&str]) {
+        let tensors: HashMap<String, Tensor> = names
+            .iter()
+            .map(|name| {
+                let tensor = Tensor::ones(2, DType::F32, &Device::Cpu).unwrap();
+                (name.to_string(), tensor)
+            })
+            .collect();
+        candle_core::safetensors::save(&tensors, path).unwrap();
+    }
+
+    fn write_index(dir: &Path, weight_map: &[(&str, &str)]) {
+        let weight_map: HashMap<&str, &str> = weight_map.iter().copied().collect();
+        let index = serde_json::json!({ "metadata": {}, "weight_map": weight_map });
+        std::fs::write(dir.join(SAFETENSORS_INDEX_FILE), index.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_sharded_loading() {
+        let dir = test_dir("sharded");
+        write_shard(&dir.join("model-00001-of-00002.safetensors"), &["a"]);
+        write_shard(&dir.join("model-00002-of-00002.safetensors"), &["b"]);
+        write_index(
+            &dir,
+            &[
+                ("a", "model-00001-of-00002.safetensors"),
+                ("b", "model-00002-of-00002.safetensors"),
+            ],
+        );
+
+        let files = SafetensorsFiles::from_path(&dir).unwrap();
+        assert_eq!(files.files().len(), 2);
+        let vb = unsafe { files.var_builder(DType::F32, &Device::Cpu).unwrap() };
+        assert!(vb.get(2, "a").is_ok());
+        assert!(vb.get(2, "b").is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_var_builder_reports_progress() {
+        let dir = test_dir("progress");
+        write_shard(&dir.join("model.safetensors"), &["a", "b"]);
+
+        let events = std::sync::Mutex::new(Vec::new());
+        let on_progress = |event: LoadProgress| events.lock().unwrap().push(event);
+        let files = SafetensorsFiles::from_path(&dir).unwrap();
+        let vb = unsafe {
+            files
+                .var_builder_with_progress(DType::F16, &Device::Cpu, &on_progress)
+                .unwrap()
+        };
+        vb.get(2, "a").unwrap();
+        drop(vb);
+
+        let events = events.into_inner().unwrap();
+        assert!(matches!(
+            events[0],
+            LoadProgress::Header {
+                tensor_count: 2,
+                total_bytes: 8
+            }
+        ));
+        assert!(matches!(
+            &events[1],
+            LoadProgress::Tensor { name, bytes: 4, loaded_bytes: 4, total_bytes: 8 } if name == "a"
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_shard_is_named() {
+        let dir = test_dir("missing_shard");
+        write_shard(&dir.join("model-00001-of-00002.safetensors"), &["a"]);
+        write_index(
+            &dir,
+            &[
+                ("a", "model-00001-of-00002.safetensors"),
+                ("b", "model-00002-of-00002.safetensors"),
+            ],
+        );
+
+        let err = SafetensorsFiles::from_path(&dir).unwrap_err();
+        assert!(err.to_string().contains("model-00002-of-00002.safetensors"));
+        assert!(!err.to_string().contains("model-00001-of-00002"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_tensor_is_named() {
+        let dir = test_dir("missing_tensor");
+        write_shard(&dir.join("model.safetensors"), &["a"]);
+        write_index(
+            &dir,
+            &[("a", "model.safetensors"), ("b", "model.safetensors")],
+        );
+
+        let files = SafetensorsFiles::from_path(&dir).unwrap();
+        let err = match unsafe { files.var_builder(DType::F32, &Device::Cpu) } {
+            Ok(_) => panic!("缺少张量时应当报错"),
+            Err(err) => err,
+        };
+        assert!(err.to_string().contains(": b"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dtype_from_config() {
+        assert_eq!(parse_dtype("bfloat16").unwrap(), DType::BF16);
+        assert_eq!(parse_dtype("FP16").unwrap(), DType::F16);
+        assert!(parse_dtype("int8").is_err());
+
+        let dir = test_dir("dtype");
+        let path = dir.join("config.json");
+        std::fs::write(&path, r#"{"torch_dtype": "bfloat16"}"#).unwrap();
+        assert_eq!(dtype_from_config(&path).unwrap(), Some(DType::BF16));
+        std::fs::write(&path, r#"{"text_config": {"dtype": "float16"}}"#).unwrap();
+        assert_eq!(dtype_from_config(&path).unwrap(), Some(DType::F16));
+        std::fs::write(&path, r#"{"torch_dtype": "auto"}"#).unwrap();
+        assert_eq!(dtype_from_config(&path).unwrap(), None);
+
+        // 权重文件与目录解析到同一个 config.json
+        let weights = dir.join("model.safetensors");
+        assert_eq!(model_dir(&dir), dir.as_path());
+        assert_eq!(model_dir(&weights), dir.as_path());
+        std::fs::write(&path, r#"{"torch_dtype": "float16"}"#).unwrap();
+        assert_eq!(resolve_dtype(None, &weights).unwrap(), DType::F16);
+        assert_eq!(resolve_dtype(None, &dir).unwrap(), DType::F16);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(ensure_dtype_supported(DType::F32, &Device::Cpu).is_ok());
+        assert!(ensure_dtype_supported(DType::U32, &Device::Cpu).is_err());
+    }
+
+    #[test]
+    fn test_estimated_bytes() {
+        let dir = test_dir("estimate");
+        write_shard(&dir.join("model-00001-of-00002.safetensors"), &["a", "b"]);
+        write_shard(&dir.join("model-00002-of-00002.safetensors"), &["c"]);
+
+        let files = SafetensorsFiles::from_path(&dir).unwrap();
+        // 3 个张量，每个 2 个元素
+        assert_eq!(files.estimated_bytes(DType::F32).unwrap(), 3 * 2 * 4);
+        assert_eq!(files.estimated_bytes(DType::BF16).unwrap(), 3 * 2 * 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+//! 模型管理 HTTP 接口
+//!
+//! 脚本可以通过 `POST /admin/models/load` 加载 GGUF 模型、执行一批推理后再用
+//! `POST /admin/models/unload` 卸载。加载的请求体和响应与 `init_gguf_model_from_file` 命令相同。
+//! `GET /admin/queue` 查看各模型正在执行和排队中的推理请求。
+//!
+//! 加载接口可以读取任意路径的文件，服务器未配置 API key 时这些接口全部返回 401。
+
+use crate::commands::api::{ApiResponse, ApiState};
+use crate::commands::common::{InitGGUFFileRequest, InitModelResponse};
+use crate::commands::gguf::load_gguf_from_file;
+use crate::error::{ApiError, ApiJson};
+use crate::queue::QueueStatus;
+use crate::registry::LoadedModelInfo;
+use ai_base::progress::ignore_progress;
+use axum::{
+    extract::{Path, State},
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+/// 模型管理接口的路由
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/admin/models/load", post(load_model))
+        .route("/admin/models/unload", post(unload_model))
+        .route("/admin/models/{id}", get(get_model))
+        .route("/admin/queue", get(queue_status))
+}
+
+/// 卸载模型请求
+#[derive(Debug, Deserialize)]
+pub struct UnloadModelRequest {
+    pub model_id: String,
+}
+
+/// 从本地文件加载 GGUF 模型，响应与 `init_gguf_model_from_file` 命令一致
+///
+/// 模型文件不存在时返回 404（`model_not_found`），加载失败时返回 500。
+async fn load_model(
+    State(state): State<ApiState>,
+    ApiJson(request): ApiJson<InitGGUFFileRequest>,
+) -> Result<Json<InitModelResponse>, ApiError> {
+    info!("收到 API 加载模型请求，路径: {}", request.model_path);
+    load_gguf_from_file(state.gguf.clone(), &state.worker, request, ignore_progress)
+        .await
+        .map(Json)
+}
+
+/// 卸载模型，正在进行的生成会继续完成
+async fn unload_model(
+    State(state): State<ApiState>,
+    ApiJson(request): ApiJson<UnloadModelRequest>,
+) -> Result<Json<ApiResponse>, ApiError> {
+    match state.registry.remove(&request.model_id) {
+        Some(_) => {
+            info!("已通过 API 卸载模型: {}", request.model_id);
+            Ok(Json(ApiResponse {
+                message: format!("模型 {} 已卸载", request.model_id),
+                status: "success".to_string(),
+            }))
+        }
+        None => {
+            warn!("未找到要卸载的模型: {}", request.model_id);
+            Err(ApiError::model_not_loaded(&request.model_id))
+        }
+    }
+}
+
+/// 查询已加载模型的信息
+async fn get_model(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+) -> Result<Json<LoadedModelInfo>, ApiError> {
+    state
+        .registry
+        .get(&id)
+        .map(|model| Json(model.info()))
+        .ok_or_else(|| ApiError::model_not_loaded(&id))
+}
+
+/// 查看请求队列：配置、各模型正在执行和排队中的请求
+async fn queue_status(State(state): State<ApiState>) -> Json<QueueStatus> {
+    Json(state.queue.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use candle_core::quantized::{gguf_file, GgmlDType, QTensor};
+    use candle_core::{DType, Device, Tensor};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    /// 写入一个没有 Transformer 层、权重全为零的 llama GGUF 模型，只用于测试加载与卸载
+    fn write_empty_llama(path: &std::path::Path) {
+        let zeros = |shape: &[usize]| {
+            let tensor = Tensor::zeros(shape, DType::F32, &Device::Cpu).unwrap();
+            QTensor::quantize(&tensor, GgmlDType::F32).unwrap()
+        };
+        let token_embd = zeros(&[4, 8]);
+        let output_norm = zeros(&[8]);
+        let arch = gguf_file::Value::String("llama".to_string());
+        let heads = gguf_file::Value::U32(2);
+        let blocks = gguf_file::Value::U32(0);
+        let embedding = gguf_file::Value::U32(8);
+        let rope_dim = gguf_file::Value::U32(4);
+        let eps = gguf_file::Value::F32(1e-5);
+        let mut file = std::fs::File::create(path).unwrap();
+        gguf_file::write(
+            &mut file,
+            &[
+                ("general.architecture", &arch),
+                ("llama.attention.head_count", &heads),
+                ("llama.attention.head_count_kv", &heads),
+                ("llama.block_count", &blocks),
+                ("llama.embedding_length", &embedding),
+                ("llama.rope.dimension_count", &rope_dim),
+                ("llama.attention.layer_norm_rms_epsilon", &eps),
+            ],
+            &[
+                ("token_embd.weight", &token_embd),
+                ("output_norm.weight", &output_norm),
+            ],
+        )
+        .unwrap();
+    }
+
+    /// 发送请求，返回状态码和 JSON 响应体
+    async fn call(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_unload_unknown_model() {
+        let app = routes().with_state(ApiState::for_test());
+
+        let (status, body) = call(
+            &app,
+            "POST",
+            "/admin/models/unload",
+            Some(json!({ "model_id": "missing" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "model_not_loaded");
+
+        let (status, body) = call(&app, "GET", "/admin/models/missing", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "model_not_loaded");
+    }
+
+    #[tokio::test]
+    async fn test_list_models_after_unload() {
+        let path = std::env::temp_dir().join(format!("admin-test-{}.gguf", uuid::Uuid::new_v4()));
+        write_empty_llama(&path);
+        let state = ApiState::for_test();
+        let app = routes().with_state(state.clone());
+
+        for id in ["a", "b"] {
+            let (status, body) = call(
+                &app,
+                "POST",
+                "/admin/models/load",
+                Some(json!({ "model_id": id, "model_path": path })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            assert_eq!(body["model_id"], id);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/admin/models/unload",
+            Some(json!({ "model_id": "a" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let listed: Vec<String> = state
+            .registry
+            .list()
+            .into_iter()
+            .map(|model| model.model_id)
+            .collect();
+        assert_eq!(listed, ["b"]);
+        let (status, _) = call(&app, "GET", "/admin/models/a", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, body) = call(&app, "GET", "/admin/models/b", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["model_id"], "b");
+        assert_eq!(body["kind"], "gguf");
+
+        // 再次卸载已卸载的模型
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/admin/models/unload",
+            Some(json!({ "model_id": "a" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -1,6 +1,18 @@
+use crate::commands::common::{InferenceRequest, InferenceResponse};
+use crate::commands::gguf::run_gguf_inference;
+use crate::commands::storage::{read_setting, write_setting};
+use crate::commands::{admin, metrics, ollama, openai, openapi, websocket};
+use crate::error::{ApiError, ApiJson, ErrorCode};
+use crate::inference::GGUFInferenceService;
+use crate::metrics::InferenceMetrics;
+use crate::queue::{QueueConfig, RequestQueue};
+use crate::registry::{ModelRegistry, DEFAULT_GGUF_MODEL_ID};
+use crate::worker::InferenceWorker;
 use axum::{
-    http::StatusCode,
-    response::Json,
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
     routing::{get, post},
     Router,
 };
@@ -8,8 +20,8 @@ use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use tokio::net::TcpListener;
-use tokio::sync::oneshot;
-use tower_http::cors::CorsLayer;
+use tokio::sync::{oneshot, watch};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use tracing::{debug, error, info, warn};
 
 #[derive(Serialize, Deserialize)]
@@ -26,37 +38,167 @@ pub struct GreetRequest {
 #[derive(Serialize, Deserialize)]
 pub struct ServerStatus {
     pub is_running: bool,
+    /// 实际监听的地址（端口为 0 时是系统分配的端口）
     pub address: Option<String>,
 }
 
+/// 服务器配置在设置文件中的键
+const SERVER_CONFIG_KEY: &str = "server_config";
+
+/// HTTP 服务器配置，启动时保存到设置文件，下次未指定配置时沿用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: String,
+    /// 0 表示由系统分配端口
+    pub port: u16,
+    /// 非空时除 `/health` 与 `/openapi.json` 外的请求都需要携带 `Authorization: Bearer <key>`；
+    /// 为空时 `/admin/*` 接口不可用
+    pub api_keys: Vec<String>,
+    /// 允许跨域访问的来源；为空时不允许任何跨域访问，包含 "*" 时允许所有来源
+    pub allowed_origins: Vec<String>,
+    /// 推理请求的并发数与排队上限
+    pub queue: QueueConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            api_keys: Vec::new(),
+            allowed_origins: Vec::new(),
+            queue: QueueConfig::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    fn cors_layer(&self) -> Result<CorsLayer, ApiError> {
+        if self.allowed_origins.iter().any(|o| o == "*") {
+            return Ok(CorsLayer::permissive());
+        }
+        if self.allowed_origins.is_empty() {
+            // 不返回任何 Access-Control-Allow-* 头，浏览器只允许同源访问
+            return Ok(CorsLayer::new());
+        }
+        let origins = self
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin)
+                    .map_err(|_| ApiError::invalid_request(format!("无效的跨域来源: {}", origin)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods(Any)
+            .allow_headers(Any))
+    }
+}
+
+/// 读取保存的服务器配置，没有保存过或解析失败时使用默认配置
+fn load_server_config(app: &tauri::AppHandle) -> ServerConfig {
+    match read_setting(app, SERVER_CONFIG_KEY) {
+        Ok(Some(value)) => serde_json::from_str(&value).unwrap_or_else(|e| {
+            warn!("解析服务器配置失败，使用默认配置: {}", e);
+            ServerConfig::default()
+        }),
+        Ok(None) => ServerConfig::default(),
+        Err(e) => {
+            warn!("读取服务器配置失败，使用默认配置: {}", e);
+            ServerConfig::default()
+        }
+    }
+}
+
+fn save_server_config(app: &tauri::AppHandle, config: &ServerConfig) -> Result<(), ApiError> {
+    let value = serde_json::to_string(config)
+        .map_err(|e| ApiError::internal(format!("序列化服务器配置失败: {}", e)))?;
+    write_setting(app, SERVER_CONFIG_KEY.to_string(), value)
+}
+
+/// HTTP 处理函数共享的状态
+#[derive(Clone)]
+pub struct ApiState {
+    pub gguf: Arc<GGUFInferenceService>,
+    pub worker: Arc<InferenceWorker>,
+    pub registry: Arc<ModelRegistry>,
+    pub queue: Arc<RequestQueue>,
+    pub metrics: Arc<InferenceMetrics>,
+}
+
+#[cfg(test)]
+impl ApiState {
+    /// 使用空注册表和默认队列配置创建状态，供路由测试使用
+    pub(crate) fn for_test() -> Self {
+        let registry = Arc::new(ModelRegistry::new());
+        let metrics = Arc::new(InferenceMetrics::new());
+        Self {
+            gguf: Arc::new(GGUFInferenceService::new(registry.clone(), metrics.clone())),
+            worker: Arc::new(InferenceWorker::new()),
+            registry,
+            queue: Arc::new(RequestQueue::new(QueueConfig::default())),
+            metrics,
+        }
+    }
+}
+
 // 服务器句柄，用于停止服务器
 pub struct ServerHandle {
+    address: SocketAddr,
     shutdown_tx: Option<oneshot::Sender<()>>,
     thread_handle: Option<std::thread::JoinHandle<()>>,
 }
 
 impl ServerHandle {
     pub fn new(
+        address: SocketAddr,
         shutdown_tx: oneshot::Sender<()>,
         thread_handle: std::thread::JoinHandle<()>,
     ) -> Self {
         Self {
+            address,
             shutdown_tx: Some(shutdown_tx),
             thread_handle: Some(thread_handle),
         }
     }
 
-    pub fn stop(&mut self) -> Result<(), String> {
+    /// 实际监听的地址
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// 服务器线程是否已经退出（例如运行中出错）
+    pub fn is_finished(&self) -> bool {
+        self.thread_handle
+            .as_ref()
+            .is_none_or(|handle| handle.is_finished())
+    }
+
+    fn status(&self) -> ServerStatus {
+        ServerStatus {
+            is_running: true,
+            address: Some(format!("http://{}", self.address)),
+        }
+    }
+
+    /// 发送停止信号，不等待服务器线程结束
+    pub fn shutdown(&mut self) -> Result<(), ApiError> {
         if let Some(tx) = self.shutdown_tx.take() {
             tx.send(())
-                .map_err(|_| "发送停止信号失败：接收端已关闭".to_string())?;
+                .map_err(|_| ApiError::internal("发送停止信号失败：接收端已关闭"))?;
             info!("已发送服务器停止信号");
         }
+        Ok(())
+    }
 
+    /// 阻塞等待服务器线程结束，在异步上下文中需要放到 `spawn_blocking` 中调用
+    pub fn join(mut self) -> Result<(), ApiError> {
         if let Some(handle) = self.thread_handle.take() {
             handle
                 .join()
-                .map_err(|e| format!("等待服务器线程结束失败: {:?}", e))?;
+                .map_err(|e| ApiError::internal(format!("等待服务器线程结束失败: {:?}", e)))?;
             info!("服务器线程已结束");
         }
 
@@ -84,29 +226,141 @@ async fn greet_api(Json(payload): Json<GreetRequest>) -> Result<Json<ApiResponse
     }))
 }
 
-// 创建并启动 Axum 服务器（带停止信号）
+/// GGUF 文本推理，请求体与 `generate_gguf_text` 命令相同（可携带采样参数和停止序列）
+///
+/// 与其他推理接口共用按模型的请求队列，队列已满时返回 429。
+async fn inference_api(
+    State(state): State<ApiState>,
+    ApiJson(mut request): ApiJson<InferenceRequest>,
+) -> Result<Json<InferenceResponse>, ApiError> {
+    info!("收到 API 推理请求，prompt 长度: {}", request.prompt.len());
+    // 队列与推理任务使用同一个请求 ID
+    let request_id = request
+        .request_id
+        .get_or_insert_with(|| uuid::Uuid::new_v4().to_string())
+        .clone();
+    let queue_key = request
+        .model_id
+        .clone()
+        .unwrap_or_else(|| DEFAULT_GGUF_MODEL_ID.to_string());
+    let _permit = state
+        .queue
+        .acquire(&queue_key, &request_id, "/api/inference")
+        .await?;
+    run_gguf_inference(state.gguf.clone(), &state.worker, request)
+        .await
+        .map(Json)
+}
+
+/// 校验 `Authorization: Bearer <key>`，`/health` 与 `/openapi.json` 始终开放
+///
+/// 未配置 API key 时不校验，但 `/admin/*` 可以加载任意路径的模型文件，一律拒绝。
+///
+/// 浏览器无法为 WebSocket 设置请求头，WebSocket 接口也接受 `?api_key=<key>`。
+async fn require_api_key(
+    State(api_keys): State<Arc<Vec<String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if matches!(request.uri().path(), "/health" | "/openapi.json") {
+        return next.run(request).await;
+    }
+    if api_keys.is_empty() {
+        if request.uri().path().starts_with("/admin/") {
+            warn!("未配置 API key，拒绝模型管理请求: {}", request.uri().path());
+            return ApiError::new(
+                ErrorCode::Unauthorized,
+                "未配置 API key，模型管理接口不可用",
+            )
+            .into_response();
+        }
+        return next.run(request).await;
+    }
+
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|key| key.trim().to_string());
+    let query = (request.uri().path() == websocket::CHAT_ROUTE)
+        .then(|| request.uri().query())
+        .flatten()
+        .and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("api_key="))
+        })
+        .and_then(|key| urlencoding::decode(key).ok())
+        .map(|key| key.into_owned());
+    let authorized = bearer
+        .into_iter()
+        .chain(query)
+        .any(|key| api_keys.contains(&key));
+    if authorized {
+        return next.run(request).await;
+    }
+
+    warn!("拒绝未授权的 API 请求: {}", request.uri().path());
+    ApiError::new(ErrorCode::Unauthorized, "缺少或无效的 API key").into_response()
+}
+
+/// 按配置创建全部路由及鉴权、跨域和请求统计中间件
+///
+/// `sockets_closed` 变为 true 时关闭所有 WebSocket 连接。
+pub(crate) fn build_router(
+    state: ApiState,
+    config: &ServerConfig,
+    sockets_closed: watch::Receiver<bool>,
+) -> Result<Router, ApiError> {
+    let cors = config.cors_layer()?;
+    let api_keys = Arc::new(config.api_keys.clone());
+    let request_metrics = state.metrics.clone();
+
+    Ok(Router::new()
+        .route("/health", get(health_check))
+        .route("/api/greet", post(greet_api))
+        .route("/api/inference", post(inference_api))
+        .merge(openai::routes())
+        .merge(ollama::routes())
+        .merge(admin::routes())
+        .merge(metrics::routes())
+        .merge(openapi::routes())
+        .merge(websocket::routes(
+            sockets_closed,
+            config.allowed_origins.clone(),
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            request_metrics,
+            metrics::record_request,
+        ))
+        .with_state(state)
+        .layer(middleware::from_fn_with_state(api_keys, require_api_key))
+        .layer(cors)) // 跨域预检请求在鉴权之前处理
+}
+
+// 在已绑定的端口上运行 Axum 服务器（带停止信号）
 async fn start_axum_server_with_shutdown(
+    state: ApiState,
+    config: ServerConfig,
+    listener: std::net::TcpListener,
     mut shutdown_rx: oneshot::Receiver<()>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     info!("正在启动 Axum 服务器...");
 
-    // 创建路由
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .route("/api/greet", post(greet_api))
-        .layer(CorsLayer::permissive()); // 允许所有跨域请求
+    // 停止服务器时通知 WebSocket 连接关闭
+    let (close_sockets, sockets_closed) = watch::channel(false);
+    let app = build_router(state, &config, sockets_closed)?;
 
-    // 绑定到本地地址，默认端口 8080
-    let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
-    let listener = TcpListener::bind(addr).await?;
-
-    info!("Axum 服务器运行在 http://{}", addr);
+    let listener = TcpListener::from_std(listener)?;
+    info!("Axum 服务器运行在 http://{}", listener.local_addr()?);
 
     // 启动服务器，支持优雅关闭
     axum::serve(listener, app)
-        .with_graceful_shutdown(async {
+        .with_graceful_shutdown(async move {
             let _ = shutdown_rx.await;
             info!("收到停止信号，正在关闭服务器...");
+            let _ = close_sockets.send(true);
         })
         .await?;
 
@@ -114,10 +368,26 @@ async fn start_axum_server_with_shutdown(
     Ok(())
 }
 
-// 在后台启动 Axum 服务器，返回句柄用于停止
-pub fn spawn_axum_server() -> Result<ServerHandle, String> {
+/// 按配置绑定端口并在后台启动 Axum 服务器，返回句柄用于停止
+///
+/// 端口在调用线程上同步绑定，端口被占用等错误直接返回给调用方。
+pub fn spawn_axum_server(state: ApiState, config: ServerConfig) -> Result<ServerHandle, ApiError> {
     info!("在后台线程中启动 Axum 服务器");
 
+    // 提前检查配置，避免在后台线程中才失败
+    let _ = config.cors_layer()?;
+
+    let listener =
+        std::net::TcpListener::bind((config.host.as_str(), config.port)).map_err(|e| {
+            ApiError::internal(format!("绑定 {}:{} 失败: {}", config.host, config.port, e))
+        })?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| ApiError::internal(format!("设置监听端口为非阻塞失败: {}", e)))?;
+    let address = listener
+        .local_addr()
+        .map_err(|e| ApiError::internal(format!("获取监听地址失败: {}", e)))?;
+
     let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
 
     let thread_handle = std::thread::spawn(move || {
@@ -130,68 +400,103 @@ pub fn spawn_axum_server() -> Result<ServerHandle, String> {
         };
 
         rt.block_on(async {
-            if let Err(e) = start_axum_server_with_shutdown(shutdown_rx).await {
+            if let Err(e) =
+                start_axum_server_with_shutdown(state, config, listener, shutdown_rx).await
+            {
                 error!("Axum 服务器错误: {}", e);
             }
         });
     });
 
-    Ok(ServerHandle::new(shutdown_tx, thread_handle))
+    Ok(ServerHandle::new(address, shutdown_tx, thread_handle))
 }
 
 // 获取服务器状态
 #[tauri::command]
 pub async fn get_server_status(
     state: tauri::State<'_, Arc<Mutex<Option<ServerHandle>>>>,
-) -> Result<ServerStatus, String> {
-    let guard = state
+) -> Result<ServerStatus, ApiError> {
+    let mut guard = state
         .lock()
-        .map_err(|e| format!("获取服务器状态锁失败: {}", e))?;
+        .map_err(|e| ApiError::internal(format!("获取服务器状态锁失败: {}", e)))?;
 
-    if guard.is_some() {
-        Ok(ServerStatus {
-            is_running: true,
-            address: Some("http://127.0.0.1:8080".to_string()),
-        })
-    } else {
-        Ok(ServerStatus {
+    // 服务器线程因错误退出时不再报告为运行中
+    if guard.as_ref().is_some_and(|handle| handle.is_finished()) {
+        warn!("服务器线程已退出");
+        *guard = None;
+    }
+
+    match guard.as_ref() {
+        Some(handle) => Ok(handle.status()),
+        None => Ok(ServerStatus {
             is_running: false,
             address: None,
-        })
+        }),
     }
 }
 
-// 启动服务器
+/// 获取保存的服务器配置
+#[tauri::command]
+pub async fn get_server_config(app: tauri::AppHandle) -> Result<ServerConfig, ApiError> {
+    Ok(load_server_config(&app))
+}
+
+/// 启动服务器
+///
+/// 传入的配置在端口绑定成功后保存到设置文件；未传入时使用上次保存的配置。
 #[tauri::command]
 pub async fn start_server(
+    app: tauri::AppHandle,
     state: tauri::State<'_, Arc<Mutex<Option<ServerHandle>>>>,
-) -> Result<ServerStatus, String> {
+    gguf_state: tauri::State<'_, Arc<GGUFInferenceService>>,
+    worker: tauri::State<'_, Arc<InferenceWorker>>,
+    registry: tauri::State<'_, Arc<ModelRegistry>>,
+    metrics: tauri::State<'_, Arc<InferenceMetrics>>,
+    config: Option<ServerConfig>,
+) -> Result<ServerStatus, ApiError> {
     let mut guard = state
         .lock()
-        .map_err(|e| format!("获取服务器状态锁失败: {}", e))?;
+        .map_err(|e| ApiError::internal(format!("获取服务器状态锁失败: {}", e)))?;
+
+    if guard.as_ref().is_some_and(|handle| handle.is_finished()) {
+        *guard = None;
+    }
 
     // 如果服务器已经在运行，返回当前状态
-    if guard.is_some() {
+    if let Some(handle) = guard.as_ref() {
         warn!("服务器已经在运行中");
-        return Ok(ServerStatus {
-            is_running: true,
-            address: Some("http://127.0.0.1:8080".to_string()),
-        });
+        return Ok(handle.status());
     }
 
+    let (config, save_config) = match config {
+        Some(config) => (config, true),
+        None => (load_server_config(&app), false),
+    };
+
     // 启动服务器
-    match spawn_axum_server() {
+    let api_state = ApiState {
+        gguf: gguf_state.inner().clone(),
+        worker: worker.inner().clone(),
+        registry: registry.inner().clone(),
+        queue: Arc::new(RequestQueue::new(config.queue.clone())),
+        metrics: metrics.inner().clone(),
+    };
+    match spawn_axum_server(api_state, config.clone()) {
         Ok(handle) => {
+            info!("服务器启动成功: http://{}", handle.address());
+            // 启动失败的配置不保存，下次仍沿用上次可用的配置
+            if save_config {
+                if let Err(e) = save_server_config(&app, &config) {
+                    warn!("服务器已启动，但保存配置失败: {}", e);
+                }
+            }
+            let status = handle.status();
             *guard = Some(handle);
-            info!("服务器启动成功");
-            Ok(ServerStatus {
-                is_running: true,
-                address: Some("http://127.0.0.1:8080".to_string()),
-            })
+            Ok(status)
         }
         Err(e) => {
             error!("启动服务器失败: {}", e);
-            Err(format!("启动服务器失败: {}", e))
+            Err(e.context("启动服务器失败"))
         }
     }
 }
@@ -200,30 +505,166 @@ pub async fn start_server(
 #[tauri::command]
 pub async fn stop_server(
     state: tauri::State<'_, Arc<Mutex<Option<ServerHandle>>>>,
-) -> Result<ServerStatus, String> {
-    let mut guard = state
+) -> Result<ServerStatus, ApiError> {
+    // 取出句柄后立即释放锁，等待服务器线程结束期间不阻塞其他命令
+    let handle = state
         .lock()
-        .map_err(|e| format!("获取服务器状态锁失败: {}", e))?;
-
-    if let Some(mut handle) = guard.take() {
-        match handle.stop() {
-            Ok(_) => {
-                info!("服务器已停止");
-                Ok(ServerStatus {
-                    is_running: false,
-                    address: None,
-                })
-            }
-            Err(e) => {
-                error!("停止服务器失败: {}", e);
-                Err(format!("停止服务器失败: {}", e))
-            }
-        }
-    } else {
+        .map_err(|e| ApiError::internal(format!("获取服务器状态锁失败: {}", e)))?
+        .take();
+    let stopped = ServerStatus {
+        is_running: false,
+        address: None,
+    };
+
+    let Some(mut handle) = handle else {
         warn!("服务器未运行");
-        Ok(ServerStatus {
-            is_running: false,
-            address: None,
-        })
+        return Ok(stopped);
+    };
+
+    let result = match handle.shutdown() {
+        Ok(()) => tokio::task::spawn_blocking(move || handle.join())
+            .await
+            .unwrap_or_else(|e| Err(ApiError::internal(format!("等待服务器线程结束失败: {}", e)))),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => {
+            info!("服务器已停止");
+            Ok(stopped)
+        }
+        Err(e) => {
+            error!("停止服务器失败: {}", e);
+            Err(e.context("停止服务器失败"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    fn router(config: &ServerConfig) -> Router {
+        let (_close_sockets, sockets_closed) = watch::channel(false);
+        build_router(ApiState::for_test(), config, sockets_closed).unwrap()
+    }
+
+    fn config_with_keys(api_keys: &[&str]) -> ServerConfig {
+        ServerConfig {
+            api_keys: api_keys.iter().map(|key| key.to_string()).collect(),
+            ..ServerConfig::default()
+        }
+    }
+
+    /// 发送 GET 请求，`headers` 为附加的请求头
+    async fn get(app: &Router, uri: &str, headers: &[(header::HeaderName, &str)]) -> Response {
+        let mut request = Request::get(uri);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        app.clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_require_api_key() {
+        let app = router(&config_with_keys(&["secret"]));
+
+        let missing = get(&app, "/v1/models", &[]).await;
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        let wrong = get(
+            &app,
+            "/v1/models",
+            &[(header::AUTHORIZATION, "Bearer other")],
+        )
+        .await;
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+        let correct = get(
+            &app,
+            "/v1/models",
+            &[(header::AUTHORIZATION, "Bearer secret")],
+        )
+        .await;
+        assert_eq!(correct.status(), StatusCode::OK);
+
+        // /health 与 /openapi.json 不需要 API key
+        assert_eq!(get(&app, "/health", &[]).await.status(), StatusCode::OK);
+        assert_eq!(
+            get(&app, "/openapi.json", &[]).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_api_key_only_for_websocket() {
+        let app = router(&config_with_keys(&["secret"]));
+
+        let http = get(&app, "/v1/models?api_key=secret", &[]).await;
+        assert_eq!(http.status(), StatusCode::UNAUTHORIZED);
+
+        // 通过鉴权后因为不是真正的 WebSocket 握手被拒绝，但不再是 401
+        let ws = get(&app, "/ws/chat?api_key=secret", &[]).await;
+        assert_ne!(ws.status(), StatusCode::UNAUTHORIZED);
+        let ws = get(&app, "/ws/chat?api_key=other", &[]).await;
+        assert_eq!(ws.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_admin_refused_without_api_keys() {
+        let app = router(&config_with_keys(&[]));
+
+        assert_eq!(
+            get(&app, "/admin/queue", &[]).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(get(&app, "/v1/models", &[]).await.status(), StatusCode::OK);
+    }
+
+    /// 以 `origin` 为来源请求 /health，返回 Access-Control-Allow-Origin 头
+    async fn allowed_origin(config: &ServerConfig, origin: &str) -> Option<String> {
+        let response = get(&router(config), "/health", &[(header::ORIGIN, origin)]).await;
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_cors_layer() {
+        let listed = ServerConfig {
+            allowed_origins: vec!["http://allowed.example".to_string()],
+            ..ServerConfig::default()
+        };
+        assert_eq!(
+            allowed_origin(&listed, "http://allowed.example")
+                .await
+                .as_deref(),
+            Some("http://allowed.example")
+        );
+        assert_eq!(allowed_origin(&listed, "http://other.example").await, None);
+
+        let any = ServerConfig {
+            allowed_origins: vec!["*".to_string()],
+            ..ServerConfig::default()
+        };
+        assert_eq!(
+            allowed_origin(&any, "http://other.example")
+                .await
+                .as_deref(),
+            Some("*")
+        );
+
+        // 未配置时不允许任何跨域访问
+        let none = ServerConfig::default();
+        assert_eq!(allowed_origin(&none, "http://other.example").await, None);
+
+        let invalid = ServerConfig {
+            allowed_origins: vec!["bad\norigin".to_string()],
+            ..ServerConfig::default()
+        };
+        assert!(invalid.cors_layer().is_err());
     }
 }
@@ -1,15 +1,40 @@
+use crate::error::ApiError;
+use ai_base::{ChatMessage, GenerationOutput, GenerationParams, LoadProgress};
 use serde::{Deserialize, Serialize};
 
 /// 推理请求
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InferenceRequest {
+    /// 目标模型 id，未提供时使用默认模型
+    pub model_id: Option<String>,
+    /// 请求 ID，可用于取消生成；未提供时由后端生成
+    pub request_id: Option<String>,
     pub prompt: String,
     pub max_tokens: Option<usize>,
+    /// 采样参数与停止序列，与其他字段位于同一层级
+    #[serde(flatten)]
+    pub params: GenerationParams,
+}
+
+/// 对话补全请求
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatCompletionRequest {
+    /// 目标模型 id，未提供时使用默认模型
+    pub model_id: Option<String>,
+    /// 请求 ID，可用于取消生成；未提供时由后端生成
+    pub request_id: Option<String>,
+    pub messages: Vec<ChatMessage>,
+    pub max_tokens: Option<usize>,
+    /// 采样参数与停止序列，与其他字段位于同一层级
+    #[serde(flatten)]
+    pub params: GenerationParams,
 }
 
 /// 多模态推理请求（图像 + 文本）
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MultimodalInferenceRequest {
+    /// 目标模型 id，未提供时使用默认模型
+    pub model_id: Option<String>,
     pub image_path: String,
     pub prompt: String,
     pub max_tokens: Option<usize>,
@@ -18,57 +43,162 @@ pub struct MultimodalInferenceRequest {
 /// 多模态推理请求（从图像字节数据）
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MultimodalInferenceFromBytesRequest {
+    /// 目标模型 id，未提供时使用默认模型
+    pub model_id: Option<String>,
     pub image_data: Vec<u8>, // base64 编码的图像数据
     pub prompt: String,
     pub max_tokens: Option<usize>,
 }
 
+/// 流式推理请求
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamInferenceRequest {
+    /// 目标模型 id，未提供时使用默认模型
+    pub model_id: Option<String>,
+    /// 请求 ID，用于匹配事件；未提供时由后端生成
+    pub request_id: Option<String>,
+    pub prompt: String,
+    pub max_tokens: Option<usize>,
+    /// 采样参数与停止序列，与其他字段位于同一层级
+    #[serde(flatten)]
+    pub params: GenerationParams,
+}
+
+/// 流式推理 token 事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenStreamEvent {
+    pub request_id: String,
+    pub token_id: u32,
+    /// 本次新增的文本
+    pub text: String,
+    /// 已生成的 token 数
+    pub index: usize,
+}
+
+/// 流式推理结束事件（同时作为命令返回值）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamDoneEvent {
+    pub request_id: String,
+    pub text: String,
+    /// "eos"、"stop"、"length" 或 "cancelled"
+    pub finish_reason: Option<String>,
+    pub prompt_tokens: usize,
+    /// 提示词中复用 KV cache 的 token 数
+    pub cached_tokens: usize,
+    pub completion_tokens: usize,
+    /// 生成失败时的错误，此时命令本身返回同一个错误
+    pub error: Option<ApiError>,
+}
+
+/// 流式推理请求进入等待队列的事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedEvent {
+    pub request_id: String,
+    /// 前面还有多少个任务（1 表示下一个执行）
+    pub position: usize,
+}
+
+/// token 用量
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: usize,
+    /// 提示词中复用 KV cache、未重新计算的 token 数
+    pub cached_tokens: usize,
+    pub completion_tokens: usize,
+}
+
 /// 推理响应
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InferenceResponse {
     pub text: String,
-    pub success: bool,
-    pub error: Option<String>,
+    /// "eos"、"stop"、"length" 或 "cancelled"
+    pub finish_reason: Option<String>,
+    /// token 用量（仅 GGUF 生成成功时提供）
+    pub usage: Option<TokenUsage>,
+}
+
+impl From<GenerationOutput> for InferenceResponse {
+    fn from(output: GenerationOutput) -> Self {
+        Self {
+            finish_reason: Some(output.finish_reason.as_str().to_string()),
+            usage: Some(TokenUsage {
+                prompt_tokens: output.prompt_tokens,
+                cached_tokens: output.cached_tokens,
+                completion_tokens: output.completion_tokens,
+            }),
+            text: output.text,
+        }
+    }
 }
 
 /// 初始化模型请求
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitModelRequest {
+    /// 加载后登记的模型 id，未提供时使用默认 id（再次加载会替换默认模型）
+    pub model_id: Option<String>,
     pub model_path: String,
     pub tokenizer_path: String,
+    /// 计算精度（float32、float16、bfloat16），未提供时使用 config.json 中的 torch_dtype
+    pub dtype: Option<String>,
+}
+
+/// 模型加载进度事件
+///
+/// `phase` 依次为 `open`、`header`、`tensor`（每个张量一次，带字节数）、`tokenizer`、`done`。
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadProgressEvent {
+    pub model_id: String,
+    #[serde(flatten)]
+    pub progress: LoadProgress,
 }
 
 /// 初始化模型响应
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitModelResponse {
-    pub success: bool,
     pub message: String,
+    /// 模型在注册表中的 id
+    pub model_id: String,
 }
 
 /// GGUF 初始化模型请求（从本地文件）
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitGGUFFileRequest {
+    /// 加载后登记的模型 id，未提供时使用默认 id（再次加载会替换默认模型）
+    pub model_id: Option<String>,
     pub model_path: String,
     pub tokenizer_path: Option<String>,
+    /// 模型架构（llama、mistral、qwen2、qwen3、phi3、gemma3；Gemma 系列只支持 Gemma 3），未提供时从 GGUF 头部识别
     pub architecture: Option<String>,
 }
 
 /// GGUF 初始化模型请求（从 HuggingFace Hub）
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitGGUFHubRequest {
+    /// 加载后登记的模型 id，未提供时使用默认 id（再次加载会替换默认模型）
+    pub model_id: Option<String>,
     pub hf_repo: String,
     pub hf_filename: String,
     pub tokenizer_path: Option<String>,
+    /// 模型架构（llama、mistral、qwen2、qwen3、phi3、gemma3；Gemma 系列只支持 Gemma 3），未提供时从 GGUF 头部识别
     pub architecture: Option<String>,
 }
 
 /// 统一推理请求（初始化模型并执行推理）
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UnifiedInferenceRequest {
+    /// 请求 ID，可用于取消生成；未提供时由后端生成
+    pub request_id: Option<String>,
+    /// 加载后登记的模型 id，未提供时使用默认 id（再次加载会替换默认模型）
+    pub model_id: Option<String>,
     pub model_path: String,
     pub model_type: String, // "gguf" 或 "safetensors"
     pub architecture: Option<String>,
     pub tokenizer_path: Option<String>,
+    /// Safetensors 模型的计算精度（float32、float16、bfloat16），未提供时使用 config.json 中的 torch_dtype
+    pub dtype: Option<String>,
     pub prompt: String,
     pub max_tokens: Option<usize>,
+    /// 采样参数与停止序列，与其他字段位于同一层级
+    #[serde(flatten)]
+    pub params: GenerationParams,
 }
@@ -1,17 +1,30 @@
 use crate::commands::common::*;
+use crate::commands::registry::load_progress_emitter;
+use crate::error::{ApiError, ErrorCode};
 use crate::inference::{GGUFInferenceService, InferenceService};
+use crate::registry::{ModelKind, DEFAULT_GGUF_MODEL_ID, DEFAULT_SAFETENSORS_MODEL_ID};
+use crate::worker::{InferenceWorker, JobError, JobHandle, WorkerStatus};
+use ai_base::{FinishReason, GenerationOutput, LoadProgress};
+use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tauri::State;
-use tracing::{debug, error, info};
+use tauri::{AppHandle, Emitter, State};
+use tracing::{debug, error, info, warn};
+
+/// 流式推理 token 事件名
+pub const GGUF_TOKEN_EVENT: &str = "gguf-token";
+/// 流式推理结束事件名
+pub const GGUF_DONE_EVENT: &str = "gguf-done";
+/// 流式推理请求进入等待队列时的事件名
+pub const GGUF_QUEUED_EVENT: &str = "gguf-queued";
 
 /// 将路径转换为绝对路径
-fn to_absolute_path(path: &Path) -> Result<PathBuf, String> {
+fn to_absolute_path(path: &Path) -> Result<PathBuf, ApiError> {
     let abs_path = if path.is_absolute() {
         path.to_path_buf()
     } else {
-        let current_dir =
-            std::env::current_dir().map_err(|e| format!("无法获取当前工作目录: {}", e))?;
+        let current_dir = std::env::current_dir()
+            .map_err(|e| ApiError::internal(format!("无法获取当前工作目录: {}", e)))?;
         current_dir.join(path)
     };
 
@@ -20,15 +33,54 @@ fn to_absolute_path(path: &Path) -> Result<PathBuf, String> {
     abs_path
         .canonicalize()
         .or_else(|_| Ok::<PathBuf, std::io::Error>(abs_path))
-        .map_err(|_| "无法规范化路径".to_string())
+        .map_err(|_| ApiError::invalid_request("无法规范化路径"))
+}
+
+/// 等待生成任务完成
+///
+/// 在排队期间被取消的任务视为以 `cancelled` 结束的空生成。
+async fn wait_generation(
+    job: JobHandle<anyhow::Result<GenerationOutput>>,
+) -> anyhow::Result<GenerationOutput> {
+    match job.wait().await {
+        Ok(result) => result,
+        Err(JobError::Cancelled) => Ok(GenerationOutput {
+            text: String::new(),
+            finish_reason: FinishReason::Cancelled,
+            prompt_tokens: 0,
+            cached_tokens: 0,
+            completion_tokens: 0,
+        }),
+        Err(e) => Err(e.into()),
+    }
 }
 
 /// 从本地文件初始化 GGUF 模型
+///
+/// 加载过程中发送 `model-load-progress` 事件。
 #[tauri::command]
 pub async fn init_gguf_model_from_file(
+    app: AppHandle,
     state: State<'_, Arc<GGUFInferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
     request: InitGGUFFileRequest,
-) -> Result<InitModelResponse, String> {
+) -> Result<InitModelResponse, ApiError> {
+    let model_id = request
+        .model_id
+        .clone()
+        .unwrap_or_else(|| DEFAULT_GGUF_MODEL_ID.to_string());
+    let on_progress = load_progress_emitter(app, model_id);
+    load_gguf_from_file(state.inner().clone(), &worker, request, on_progress).await
+}
+
+/// 在推理队列上从本地文件加载 GGUF 模型，供 `init_gguf_model_from_file` 命令和 HTTP 管理接口共用
+///
+pub(crate) async fn load_gguf_from_file(
+    service: Arc<GGUFInferenceService>,
+    worker: &InferenceWorker,
+    request: InitGGUFFileRequest,
+    on_progress: impl Fn(LoadProgress) + Send + Sync + 'static,
+) -> Result<InitModelResponse, ApiError> {
     info!("开始从本地文件初始化 GGUF 模型");
     info!(
         "原始模型路径: {}, 原始 Tokenizer 路径: {:?}",
@@ -36,48 +88,62 @@ pub async fn init_gguf_model_from_file(
     );
 
     let model_path = to_absolute_path(&PathBuf::from(&request.model_path))
-        .map_err(|e| format!("无法转换模型路径为绝对路径: {}", e))?;
+        .map_err(|e| e.context("无法转换模型路径为绝对路径"))?;
     info!("转换后的模型绝对路径: {}", model_path.display());
 
     let tokenizer_path = request
         .tokenizer_path
         .map(|p| to_absolute_path(&PathBuf::from(&p)))
         .transpose()
-        .map_err(|e| format!("无法转换 Tokenizer 路径为绝对路径: {}", e))?;
+        .map_err(|e| e.context("无法转换 Tokenizer 路径为绝对路径"))?;
     if let Some(ref tp) = tokenizer_path {
         info!("转换后的 Tokenizer 绝对路径: {}", tp.display());
     }
 
-    match state.init_model_from_file(
-        model_path.clone(),
-        tokenizer_path.clone(),
-        request.architecture.clone(),
-    ) {
-        Ok(_) => {
-            info!("GGUF 模型初始化成功");
+    let model_id = request
+        .model_id
+        .unwrap_or_else(|| DEFAULT_GGUF_MODEL_ID.to_string());
+    let queue_key = model_id.clone();
+    let job = worker.submit(
+        &queue_key,
+        uuid::Uuid::new_v4().to_string(),
+        "load_model",
+        move || {
+            service.init_model_from_file(
+                Some(model_id),
+                model_path,
+                tokenizer_path,
+                request.architecture,
+                &on_progress,
+            )
+        },
+    );
+
+    match job.join().await {
+        Ok(model_id) => {
+            info!("GGUF 模型 {} 初始化成功", model_id);
             Ok(InitModelResponse {
-                success: true,
                 message: "GGUF 模型初始化成功".to_string(),
+                model_id,
             })
         }
         Err(e) => {
-            error!("GGUF 模型初始化失败: {}", e);
-            // 使用 format!("{:?}", e) 来显示完整的错误链
-            let error_msg = format!("GGUF 模型初始化失败: {}", e);
-            Ok(InitModelResponse {
-                success: false,
-                message: error_msg,
-            })
+            error!("GGUF 模型初始化失败: {:#}", e);
+            Err(ApiError::from(e).context("GGUF 模型初始化失败"))
         }
     }
 }
 
 /// 从 HuggingFace Hub 下载并初始化 GGUF 模型
+///
+/// 下载完成后的加载过程中发送 `model-load-progress` 事件。
 #[tauri::command]
 pub async fn init_gguf_model_from_hub(
+    app: AppHandle,
     state: State<'_, Arc<GGUFInferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
     request: InitGGUFHubRequest,
-) -> Result<InitModelResponse, String> {
+) -> Result<InitModelResponse, ApiError> {
     info!("开始从 HuggingFace Hub 下载并初始化 GGUF 模型");
     info!(
         "仓库: {}, 文件名: {}, Tokenizer 路径: {:?}",
@@ -86,25 +152,39 @@ pub async fn init_gguf_model_from_hub(
 
     let tokenizer_path = request.tokenizer_path.map(PathBuf::from);
 
-    match state.init_model_from_hf_hub(
-        request.hf_repo.clone(),
-        request.hf_filename.clone(),
-        tokenizer_path.clone(),
-        request.architecture.clone(),
-    ) {
-        Ok(_) => {
-            info!("GGUF 模型从 HuggingFace Hub 下载并初始化成功");
+    let model_id = request
+        .model_id
+        .unwrap_or_else(|| DEFAULT_GGUF_MODEL_ID.to_string());
+    let on_progress = load_progress_emitter(app, model_id.clone());
+    let service = state.inner().clone();
+    let queue_key = model_id.clone();
+    let job = worker.submit(
+        &queue_key,
+        uuid::Uuid::new_v4().to_string(),
+        "load_model",
+        move || {
+            service.init_model_from_hf_hub(
+                Some(model_id),
+                request.hf_repo,
+                request.hf_filename,
+                tokenizer_path,
+                request.architecture,
+                &on_progress,
+            )
+        },
+    );
+
+    match job.join().await {
+        Ok(model_id) => {
+            info!("GGUF 模型 {} 从 HuggingFace Hub 下载并初始化成功", model_id);
             Ok(InitModelResponse {
-                success: true,
                 message: "GGUF 模型从 HuggingFace Hub 下载并初始化成功".to_string(),
+                model_id,
             })
         }
         Err(e) => {
-            error!("GGUF 模型从 HuggingFace Hub 初始化失败: {}", e);
-            Ok(InitModelResponse {
-                success: false,
-                message: format!("GGUF 模型初始化失败: {}", e),
-            })
+            error!("GGUF 模型从 HuggingFace Hub 初始化失败: {:#}", e);
+            Err(ApiError::from(e).context("GGUF 模型初始化失败"))
         }
     }
 }
@@ -113,42 +193,267 @@ pub async fn init_gguf_model_from_hub(
 #[tauri::command]
 pub async fn generate_gguf_text(
     state: State<'_, Arc<GGUFInferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
+    request: InferenceRequest,
+) -> Result<InferenceResponse, ApiError> {
+    run_gguf_inference(state.inner().clone(), &worker, request).await
+}
+
+/// 在推理队列上执行一次 GGUF 文本推理（Tauri 命令与 HTTP API 共用）
+pub(crate) async fn run_gguf_inference(
+    service: Arc<GGUFInferenceService>,
+    worker: &InferenceWorker,
     request: InferenceRequest,
-) -> Result<InferenceResponse, String> {
+) -> Result<InferenceResponse, ApiError> {
     let max_tokens = request.max_tokens.unwrap_or(512);
+    let request_id = request
+        .request_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
     debug!(
-        "收到 GGUF 文本推理请求，prompt 长度: {}, max_tokens: {}",
+        "收到 GGUF 文本推理请求 {}，prompt 长度: {}, max_tokens: {}, 参数: {:?}",
+        request_id,
         request.prompt.len(),
+        max_tokens,
+        request.params
+    );
+
+    let id = request_id.clone();
+    let queue_key = request
+        .model_id
+        .clone()
+        .unwrap_or_else(|| DEFAULT_GGUF_MODEL_ID.to_string());
+    let job = worker.submit(&queue_key, request_id, "generate", move || {
+        service.generate(
+            request.model_id.as_deref(),
+            &id,
+            &request.prompt,
+            max_tokens,
+            &request.params,
+        )
+    });
+    if job.position > 0 {
+        info!(
+            "GGUF 文本推理请求 {} 排队中，位置: {}",
+            job.job_id, job.position
+        );
+    }
+
+    match wait_generation(job).await {
+        Ok(output) => {
+            info!(
+                "GGUF 文本推理成功，生成长度: {}，结束原因: {}",
+                output.text.len(),
+                output.finish_reason.as_str()
+            );
+            Ok(InferenceResponse::from(output))
+        }
+        Err(e) => {
+            error!("GGUF 文本推理失败: {:#}", e);
+            Err(ApiError::from(e).context("GGUF 推理失败"))
+        }
+    }
+}
+
+/// 对话补全：按模型的对话模板渲染消息后生成助手回复
+///
+/// 模板依次取自 GGUF 头部、tokenizer 同目录的 `tokenizer_config.json`，
+/// 都没有时按词表选择内置的 ChatML、Llama-3 或 Gemma 模板。
+#[tauri::command]
+pub async fn chat_completion(
+    state: State<'_, Arc<GGUFInferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
+    request: ChatCompletionRequest,
+) -> Result<InferenceResponse, ApiError> {
+    let max_tokens = request.max_tokens.unwrap_or(512);
+    let request_id = request
+        .request_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    debug!(
+        "收到对话补全请求 {}，消息数: {}, max_tokens: {}",
+        request_id,
+        request.messages.len(),
         max_tokens
     );
 
-    match state.generate(&request.prompt, max_tokens) {
-        Ok(text) => {
-            info!("GGUF 文本推理成功，生成长度: {}", text.len());
-            Ok(InferenceResponse {
-                text,
-                success: true,
+    let service = state.inner().clone();
+    let id = request_id.clone();
+    let queue_key = request
+        .model_id
+        .clone()
+        .unwrap_or_else(|| DEFAULT_GGUF_MODEL_ID.to_string());
+    let job = worker.submit(&queue_key, request_id, "chat", move || {
+        service.chat_stream(
+            request.model_id.as_deref(),
+            &id,
+            &request.messages,
+            max_tokens,
+            &request.params,
+            |_| ControlFlow::Continue(()),
+        )
+    });
+    if job.position > 0 {
+        info!("对话补全请求 {} 排队中，位置: {}", job.job_id, job.position);
+    }
+
+    match wait_generation(job).await {
+        Ok(output) => {
+            info!(
+                "对话补全成功，生成长度: {}，结束原因: {}",
+                output.text.len(),
+                output.finish_reason.as_str()
+            );
+            Ok(InferenceResponse::from(output))
+        }
+        Err(e) => {
+            error!("对话补全失败: {:#}", e);
+            Err(ApiError::from(e).context("对话补全失败"))
+        }
+    }
+}
+
+/// 流式执行 GGUF 模型推理
+///
+/// 每生成一个 token 发送一次 `gguf-token` 事件，结束时发送 `gguf-done` 事件，
+/// 事件均带有 `request_id`。需要排队等待时先发送 `gguf-queued` 事件。
+/// 命令在生成结束后返回与 `gguf-done` 相同的内容，生成失败时返回事件中的错误。
+#[tauri::command]
+pub async fn generate_gguf_text_stream(
+    app: AppHandle,
+    state: State<'_, Arc<GGUFInferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
+    request: StreamInferenceRequest,
+) -> Result<StreamDoneEvent, ApiError> {
+    let max_tokens = request.max_tokens.unwrap_or(512);
+    let request_id = request
+        .request_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    debug!(
+        "收到 GGUF 流式推理请求 {}，prompt 长度: {}, max_tokens: {}",
+        request_id,
+        request.prompt.len(),
+        max_tokens
+    );
+
+    let service = state.inner().clone();
+    let token_app = app.clone();
+    let id = request_id.clone();
+    let queue_key = request
+        .model_id
+        .clone()
+        .unwrap_or_else(|| DEFAULT_GGUF_MODEL_ID.to_string());
+    let job = worker.submit(&queue_key, request_id.clone(), "generate", move || {
+        let model_id = request.model_id.as_deref();
+        service.generate_stream(
+            model_id,
+            &id,
+            &request.prompt,
+            max_tokens,
+            &request.params,
+            |event| {
+                let payload = TokenStreamEvent {
+                    request_id: id.clone(),
+                    token_id: event.token_id,
+                    text: event.text,
+                    index: event.index,
+                };
+                if let Err(e) = token_app.emit(GGUF_TOKEN_EVENT, payload) {
+                    warn!("发送 token 事件失败: {}", e);
+                }
+                ControlFlow::Continue(())
+            },
+        )
+    });
+
+    if job.position > 0 {
+        info!(
+            "GGUF 流式推理请求 {} 排队中，位置: {}",
+            request_id, job.position
+        );
+        let queued = QueuedEvent {
+            request_id: request_id.clone(),
+            position: job.position,
+        };
+        if let Err(e) = app.emit(GGUF_QUEUED_EVENT, queued) {
+            warn!("发送排队事件失败: {}", e);
+        }
+    }
+
+    let done = match wait_generation(job).await {
+        Ok(output) => {
+            info!(
+                "GGUF 流式推理 {} 完成，生成 {} 个 token，结束原因: {}",
+                request_id,
+                output.completion_tokens,
+                output.finish_reason.as_str()
+            );
+            StreamDoneEvent {
+                request_id,
+                text: output.text,
+                finish_reason: Some(output.finish_reason.as_str().to_string()),
+                prompt_tokens: output.prompt_tokens,
+                cached_tokens: output.cached_tokens,
+                completion_tokens: output.completion_tokens,
                 error: None,
-            })
+            }
         }
         Err(e) => {
-            error!("GGUF 文本推理失败: {}", e);
-            Ok(InferenceResponse {
+            error!("GGUF 流式推理 {} 失败: {:#}", request_id, e);
+            StreamDoneEvent {
+                request_id,
                 text: String::new(),
-                success: false,
-                error: Some(format!("GGUF 推理失败: {}", e)),
-            })
+                finish_reason: None,
+                prompt_tokens: 0,
+                cached_tokens: 0,
+                completion_tokens: 0,
+                error: Some(ApiError::from(e).context("GGUF 推理失败")),
+            }
         }
+    };
+
+    if let Err(e) = app.emit(GGUF_DONE_EVENT, done.clone()) {
+        warn!("发送结束事件失败: {}", e);
+    }
+    match done.error {
+        Some(error) => Err(error),
+        None => Ok(done),
+    }
+}
+
+/// 取消正在进行或排队中的 GGUF 生成请求
+///
+/// 被取消的请求以 `finish_reason: "cancelled"` 结束并返回已生成的部分文本。
+/// 返回值表示是否找到了对应的请求。
+#[tauri::command]
+pub async fn cancel_generation(
+    state: State<'_, Arc<GGUFInferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
+    request_id: String,
+) -> Result<bool, ApiError> {
+    let found = state.cancel(&request_id) || worker.cancel_queued(&request_id);
+    if found {
+        info!("已请求取消生成: {}", request_id);
+    } else {
+        warn!("未找到要取消的生成请求: {}", request_id);
     }
+    Ok(found)
+}
+
+/// 获取推理队列状态（执行中和排队中的任务）
+#[tauri::command]
+pub async fn get_inference_queue(
+    worker: State<'_, Arc<InferenceWorker>>,
+) -> Result<WorkerStatus, ApiError> {
+    Ok(worker.status())
 }
 
 /// 检查 GGUF 模型是否已加载
 #[tauri::command]
 pub async fn is_gguf_model_loaded(
     state: State<'_, Arc<GGUFInferenceService>>,
-) -> Result<bool, String> {
-    let loaded = state.is_loaded();
-    debug!("检查 GGUF 模型加载状态: {}", loaded);
+    model_id: Option<String>,
+) -> Result<bool, ApiError> {
+    let loaded = state.is_loaded(model_id.as_deref());
+    debug!("检查 GGUF 模型 {:?} 加载状态: {}", model_id, loaded);
     Ok(loaded)
 }
 
@@ -156,19 +461,32 @@ pub async fn is_gguf_model_loaded(
 #[tauri::command]
 pub async fn test_gguf_forward(
     state: State<'_, Arc<GGUFInferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
+    model_id: Option<String>,
     seq_len: Option<usize>,
-) -> Result<String, String> {
+) -> Result<String, ApiError> {
     let seq_len = seq_len.unwrap_or(128);
     info!("开始测试 GGUF 模型前向传播，序列长度: {}", seq_len);
 
-    match state.test_forward(seq_len) {
+    let service = state.inner().clone();
+    let queue_key = model_id
+        .clone()
+        .unwrap_or_else(|| DEFAULT_GGUF_MODEL_ID.to_string());
+    let job = worker.submit(
+        &queue_key,
+        uuid::Uuid::new_v4().to_string(),
+        "test_forward",
+        move || service.test_forward(model_id.as_deref(), seq_len),
+    );
+
+    match job.join().await {
         Ok(_) => {
             info!("前向传播测试成功 (序列长度: {})", seq_len);
             Ok(format!("前向传播测试成功 (序列长度: {})", seq_len))
         }
         Err(e) => {
-            error!("前向传播测试失败: {}", e);
-            Err(format!("前向传播测试失败: {}", e))
+            error!("前向传播测试失败: {:#}", e);
+            Err(ApiError::from(e).context("前向传播测试失败"))
         }
     }
 }
@@ -176,10 +494,12 @@ pub async fn test_gguf_forward(
 /// 统一推理命令：初始化模型并执行推理
 #[tauri::command]
 pub async fn unified_inference(
+    app: AppHandle,
     gguf_state: State<'_, Arc<GGUFInferenceService>>,
     safetensors_state: State<'_, Arc<crate::inference::InferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
     request: UnifiedInferenceRequest,
-) -> Result<InferenceResponse, String> {
+) -> Result<InferenceResponse, ApiError> {
     info!(
         "开始统一推理，模型类型: {}, 模型路径: {}",
         request.model_type, request.model_path
@@ -192,13 +512,13 @@ pub async fn unified_inference(
         "gguf" => {
             // 初始化 GGUF 模型
             let model_path = to_absolute_path(&PathBuf::from(&request.model_path))
-                .map_err(|e| format!("无法转换模型路径为绝对路径: {}", e))?;
+                .map_err(|e| e.context("无法转换模型路径为绝对路径"))?;
 
             let tokenizer_path = request
                 .tokenizer_path
                 .map(|p| to_absolute_path(&PathBuf::from(&p)))
                 .transpose()
-                .map_err(|e| format!("无法转换 Tokenizer 路径为绝对路径: {}", e))?;
+                .map_err(|e| e.context("无法转换 Tokenizer 路径为绝对路径"))?;
 
             // let init_request = InitGGUFFileRequest {
             //     model_path: request.model_path.clone(),
@@ -206,54 +526,79 @@ pub async fn unified_inference(
             // };
 
             // 初始化模型
-            match gguf_state.init_model_from_file(
-                model_path.clone(),
-                tokenizer_path.clone(),
-                request.architecture.clone(),
-            ) {
-                Ok(_) => {
-                    info!("GGUF 模型初始化成功，开始推理");
+            let service = gguf_state.inner().clone();
+            let architecture = request.architecture.clone();
+            let model_id = request
+                .model_id
+                .clone()
+                .unwrap_or_else(|| DEFAULT_GGUF_MODEL_ID.to_string());
+            let on_progress = load_progress_emitter(app, model_id.clone());
+            let queue_key = model_id.clone();
+            let job = worker.submit(
+                &queue_key,
+                uuid::Uuid::new_v4().to_string(),
+                "load_model",
+                move || {
+                    service.init_model_from_file(
+                        Some(model_id),
+                        model_path,
+                        tokenizer_path,
+                        architecture,
+                        &on_progress,
+                    )
+                },
+            );
+            let model_id = match job.join().await {
+                Ok(model_id) => {
+                    info!("GGUF 模型 {} 初始化成功，开始推理", model_id);
+                    model_id
                 }
                 Err(e) => {
-                    error!("GGUF 模型初始化失败: {}", e);
-                    return Ok(InferenceResponse {
-                        text: String::new(),
-                        success: false,
-                        error: Some(format!("GGUF 模型初始化失败: {}", e)),
-                    });
+                    error!("GGUF 模型初始化失败: {:#}", e);
+                    return Err(ApiError::from(e).context("GGUF 模型初始化失败"));
                 }
-            }
+            };
 
             // 执行推理
-            match gguf_state.generate(&request.prompt, max_tokens) {
-                Ok(text) => {
-                    info!("统一推理成功，生成长度: {}", text.len());
-                    Ok(InferenceResponse {
-                        text,
-                        success: true,
-                        error: None,
-                    })
+            let request_id = request
+                .request_id
+                .clone()
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+            let service = gguf_state.inner().clone();
+            let id = request_id.clone();
+            let prompt = request.prompt.clone();
+            let params = request.params.clone();
+            let queue_key = model_id.clone();
+            let job = worker.submit(&queue_key, request_id, "generate", move || {
+                service.generate(Some(&model_id), &id, &prompt, max_tokens, &params)
+            });
+            match wait_generation(job).await {
+                Ok(output) => {
+                    info!("统一推理成功，生成长度: {}", output.text.len());
+                    Ok(InferenceResponse::from(output))
                 }
                 Err(e) => {
-                    error!("统一推理失败: {}", e);
-                    Ok(InferenceResponse {
-                        text: String::new(),
-                        success: false,
-                        error: Some(format!("推理失败: {}", e)),
-                    })
+                    error!("统一推理失败: {:#}", e);
+                    Err(ApiError::from(e).context("推理失败"))
                 }
             }
         }
         "safetensors" => {
             // 初始化 Safetensors 模型
             if request.tokenizer_path.is_none() {
-                return Ok(InferenceResponse {
-                    text: String::new(),
-                    success: false,
-                    error: Some("Safetensors 模型需要提供 tokenizer_path".to_string()),
-                });
+                return Err(ApiError::new(
+                    ErrorCode::TokenizerMissing,
+                    "Safetensors 模型需要提供 tokenizer_path",
+                ));
             }
 
+            let dtype = request
+                .dtype
+                .as_deref()
+                .map(ai_base::weights::parse_dtype)
+                .transpose()
+                .map_err(|e| ApiError::invalid_request(e.to_string()))?;
+
             let tokenizer_path = request.tokenizer_path.as_ref().unwrap();
             let model_path = PathBuf::from(&request.model_path);
             let config_path = model_path.join("../config.json");
@@ -265,63 +610,79 @@ pub async fn unified_inference(
                 model_path.clone()
             };
 
-            let model_config = match crate::inference::create_qwen3vl_config_from_dir(&model_dir) {
-                Ok(config) => config,
-                Err(e) => {
-                    return Ok(InferenceResponse {
-                        text: String::new(),
-                        success: false,
-                        error: Some(format!(
-                            "无法加载模型配置: {}. 请确保模型目录包含有效的 config.json 文件。",
-                            e
-                        )),
-                    });
-                }
-            };
+            let model_config =
+                ai_base::InferenceEngine::load_config_from_dir(&model_dir).map_err(|e| {
+                    ApiError::invalid_request(format!(
+                        "无法加载模型配置: {}. 请确保模型目录包含有效的 config.json 文件。",
+                        e
+                    ))
+                })?;
 
             // 初始化模型
-            match safetensors_state.init_model(
-                model_path.clone(),
-                PathBuf::from(tokenizer_path),
-                model_config,
-            ) {
-                Ok(_) => {
-                    info!("Safetensors 模型初始化成功，开始推理");
+            let service = safetensors_state.inner().clone();
+            let tokenizer_path = PathBuf::from(tokenizer_path);
+            let model_id = request
+                .model_id
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SAFETENSORS_MODEL_ID.to_string());
+            let on_progress = load_progress_emitter(app, model_id.clone());
+            let queue_key = model_id.clone();
+            let job = worker.submit(
+                &queue_key,
+                uuid::Uuid::new_v4().to_string(),
+                "load_model",
+                move || {
+                    service.init_model(
+                        Some(model_id),
+                        ModelKind::Safetensors,
+                        model_path,
+                        tokenizer_path,
+                        model_config,
+                        dtype,
+                        &on_progress,
+                    )
+                },
+            );
+            let model_id = match job.join().await {
+                Ok(model_id) => {
+                    info!("Safetensors 模型 {} 初始化成功，开始推理", model_id);
+                    model_id
                 }
                 Err(e) => {
-                    error!("Safetensors 模型初始化失败: {}", e);
-                    return Ok(InferenceResponse {
-                        text: String::new(),
-                        success: false,
-                        error: Some(format!("Safetensors 模型初始化失败: {}", e)),
-                    });
+                    error!("Safetensors 模型初始化失败: {:#}", e);
+                    return Err(ApiError::from(e).context("Safetensors 模型初始化失败"));
                 }
-            }
+            };
 
             // 执行推理
-            match safetensors_state.generate(&request.prompt, max_tokens) {
+            let service = safetensors_state.inner().clone();
+            let prompt = request.prompt.clone();
+            let params = request.params.clone();
+            let queue_key = model_id.clone();
+            let job = worker.submit(
+                &queue_key,
+                uuid::Uuid::new_v4().to_string(),
+                "generate",
+                move || service.generate(Some(&model_id), &prompt, max_tokens, &params),
+            );
+            match job.join().await {
                 Ok(text) => {
                     info!("统一推理成功，生成长度: {}", text.len());
                     Ok(InferenceResponse {
                         text,
-                        success: true,
-                        error: None,
+                        finish_reason: None,
+                        usage: None,
                     })
                 }
                 Err(e) => {
-                    error!("统一推理失败: {}", e);
-                    Ok(InferenceResponse {
-                        text: String::new(),
-                        success: false,
-                        error: Some(format!("推理失败: {}", e)),
-                    })
+                    error!("统一推理失败: {:#}", e);
+                    Err(ApiError::from(e).context("推理失败"))
                 }
             }
         }
-        _ => Ok(InferenceResponse {
-            text: String::new(),
-            success: false,
-            error: Some(format!("不支持的模型类型: {}", request.model_type)),
-        }),
+        _ => Err(ApiError::invalid_request(format!(
+            "不支持的模型类型: {}",
+            request.model_type
+        ))),
     }
 }
@@ -1,3 +1,4 @@
+use crate::error::ApiError;
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
 use tracing::{error, info};
@@ -40,7 +41,6 @@ impl LogLevel {
 /// 日志级别设置响应
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LogLevelResponse {
-    pub success: bool,
     pub message: String,
     pub current_level: LogLevel,
 }
@@ -52,7 +52,7 @@ pub type LogHandle = reload::Handle<EnvFilter, Registry>;
 #[tauri::command]
 pub async fn get_log_level(
     _state: tauri::State<'_, Arc<Mutex<Option<LogHandle>>>>,
-) -> Result<LogLevel, String> {
+) -> Result<LogLevel, ApiError> {
     // 默认返回 info 级别
     Ok(LogLevel::Info)
 }
@@ -62,7 +62,7 @@ pub async fn get_log_level(
 pub async fn set_log_level(
     level: LogLevel,
     state: tauri::State<'_, Arc<Mutex<Option<LogHandle>>>>,
-) -> Result<LogLevelResponse, String> {
+) -> Result<LogLevelResponse, ApiError> {
     info!("收到设置日志级别请求: {:?}", level);
 
     let level_str = level.as_str();
@@ -70,21 +70,20 @@ pub async fn set_log_level(
 
     let handle_guard = state
         .lock()
-        .map_err(|e| format!("获取日志句柄锁失败: {}", e))?;
+        .map_err(|e| ApiError::internal(format!("获取日志句柄锁失败: {}", e)))?;
 
     if let Some(handle) = handle_guard.as_ref() {
         handle
             .reload(filter)
-            .map_err(|e| format!("更新日志级别失败: {}", e))?;
+            .map_err(|e| ApiError::internal(format!("更新日志级别失败: {}", e)))?;
 
         info!("日志级别已更新为: {}", level_str);
         Ok(LogLevelResponse {
-            success: true,
             message: format!("日志级别已设置为: {}", level_str),
             current_level: level,
         })
     } else {
         error!("日志句柄未初始化");
-        Err("日志系统未正确初始化".to_string())
+        Err(ApiError::internal("日志系统未正确初始化"))
     }
 }
@@ -0,0 +1,59 @@
+//! 推理统计
+//!
+//! `GET /metrics` 以 Prometheus 文本格式导出统计，桌面端通过 `get_inference_stats` 命令获取相同的数据。
+//! 启用 API key 时抓取 `/metrics` 同样需要 `Authorization: Bearer <key>`。
+
+use crate::commands::api::ApiState;
+use crate::error::ApiError;
+use crate::metrics::{InferenceMetrics, InferenceStats};
+use crate::registry::ModelRegistry;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::sync::Arc;
+
+/// 推理统计接口的路由
+pub fn routes() -> Router<ApiState> {
+    Router::new().route("/metrics", get(prometheus_metrics))
+}
+
+/// 按路由模板和状态码记录请求数，作为 `route_layer` 使用，未匹配任何路由的请求不计入
+pub async fn record_request(
+    State(metrics): State<Arc<InferenceMetrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let response = next.run(request).await;
+    metrics.record_request(&route, response.status().as_u16());
+    response
+}
+
+/// 以 Prometheus 文本格式导出推理统计
+async fn prometheus_metrics(State(state): State<ApiState>) -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        state.metrics.render_prometheus(&state.registry),
+    )
+}
+
+/// 获取推理统计：请求数、各模型的 token 数、首 token 延迟与生成速度、加载耗时和内存占用
+#[tauri::command]
+pub async fn get_inference_stats(
+    metrics: tauri::State<'_, Arc<InferenceMetrics>>,
+    registry: tauri::State<'_, Arc<ModelRegistry>>,
+) -> Result<InferenceStats, ApiError> {
+    Ok(metrics.snapshot(&registry))
+}
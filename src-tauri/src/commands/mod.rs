@@ -1,7 +1,14 @@
+pub mod admin;
 pub mod api;
 pub mod common;
 pub mod gguf;
 pub mod logging;
+pub mod metrics;
 pub mod models;
+pub mod ollama;
+pub mod openai;
+pub mod openapi;
 pub mod qwen3vl;
+pub mod registry;
 pub mod storage;
+pub mod websocket;
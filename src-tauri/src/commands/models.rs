@@ -1,3 +1,4 @@
+use crate::error::ApiError;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -55,8 +56,6 @@ pub struct SearchRemoteModelsRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchRemoteModelsResponse {
     pub models: Vec<RemoteModelInfo>,
-    pub success: bool,
-    pub error: Option<String>,
 }
 
 /// 下载模型请求
@@ -70,15 +69,13 @@ pub struct DownloadModelRequest {
 /// 下载模型响应
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DownloadModelResponse {
-    pub success: bool,
     pub message: String,
-    pub local_path: Option<String>,
-    pub error: Option<String>,
+    pub local_path: String,
 }
 
 /// 获取本地模型列表
 #[tauri::command]
-pub async fn get_local_models() -> Result<Vec<LocalModelInfo>, String> {
+pub async fn get_local_models() -> Result<Vec<LocalModelInfo>, ApiError> {
     info!("开始获取本地模型列表");
 
     let models_dir = get_models_directory();
@@ -88,7 +85,7 @@ pub async fn get_local_models() -> Result<Vec<LocalModelInfo>, String> {
         info!("模型目录不存在，创建目录: {}", models_dir.display());
         if let Err(e) = fs::create_dir_all(&models_dir) {
             error!("创建模型目录失败: {}", e);
-            return Err(format!("创建模型目录失败: {}", e));
+            return Err(ApiError::internal(format!("创建模型目录失败: {}", e)));
         }
         return Ok(models);
     }
@@ -99,7 +96,7 @@ pub async fn get_local_models() -> Result<Vec<LocalModelInfo>, String> {
         }
         Err(e) => {
             error!("扫描模型目录失败: {}", e);
-            return Err(format!("扫描模型目录失败: {}", e));
+            return Err(ApiError::internal(format!("扫描模型目录失败: {}", e)));
         }
     }
 
@@ -114,7 +111,7 @@ pub async fn get_local_models() -> Result<Vec<LocalModelInfo>, String> {
 /// - models/gguf/模型文件.gguf （旧结构，兼容）
 /// - models/safetensors/模型文件.safetensors （旧结构，兼容）
 /// - models/模型文件.gguf 或 models/模型文件.safetensors （根目录，通过扩展名判断）
-fn scan_directory_for_models(dir: &Path) -> Result<Vec<LocalModelInfo>> {
+pub(crate) fn scan_directory_for_models(dir: &Path) -> Result<Vec<LocalModelInfo>> {
     let mut models = Vec::new();
 
     if !dir.is_dir() {
@@ -275,7 +272,7 @@ fn find_tokenizer_in_directory(model_path: &Path) -> Option<String> {
 }
 
 /// 获取模型目录路径
-fn get_models_directory() -> PathBuf {
+pub(crate) fn get_models_directory() -> PathBuf {
     // 优先使用项目根目录下的 models 文件夹
     if let Ok(current_dir) = std::env::current_dir() {
         let project_models = current_dir.join("models");
@@ -301,7 +298,7 @@ fn get_tokenizers_directory() -> PathBuf {
 
 /// 获取本地 tokenizer 列表
 #[tauri::command]
-pub async fn get_local_tokenizers() -> Result<Vec<TokenizerInfo>, String> {
+pub async fn get_local_tokenizers() -> Result<Vec<TokenizerInfo>, ApiError> {
     info!("开始获取本地 tokenizer 列表");
 
     let tokenizers_dir = get_tokenizers_directory();
@@ -314,7 +311,10 @@ pub async fn get_local_tokenizers() -> Result<Vec<TokenizerInfo>, String> {
         );
         if let Err(e) = fs::create_dir_all(&tokenizers_dir) {
             error!("创建 tokenizer 目录失败: {}", e);
-            return Err(format!("创建 tokenizer 目录失败: {}", e));
+            return Err(ApiError::internal(format!(
+                "创建 tokenizer 目录失败: {}",
+                e
+            )));
         }
         return Ok(tokenizers);
     }
@@ -325,7 +325,10 @@ pub async fn get_local_tokenizers() -> Result<Vec<TokenizerInfo>, String> {
         }
         Err(e) => {
             error!("扫描 tokenizer 目录失败: {}", e);
-            return Err(format!("扫描 tokenizer 目录失败: {}", e));
+            return Err(ApiError::internal(format!(
+                "扫描 tokenizer 目录失败: {}",
+                e
+            )));
         }
     }
 
@@ -424,18 +427,14 @@ fn scan_directory_for_tokenizers(dir: &Path) -> Result<Vec<TokenizerInfo>> {
 #[tauri::command]
 pub async fn search_remote_models(
     request: SearchRemoteModelsRequest,
-) -> Result<SearchRemoteModelsResponse, String> {
+) -> Result<SearchRemoteModelsResponse, ApiError> {
     info!("开始搜索远程模型，查询: {}", request.query);
 
     let limit = request.limit.unwrap_or(20);
     let query = request.query.trim();
 
     if query.is_empty() {
-        return Ok(SearchRemoteModelsResponse {
-            models: Vec::new(),
-            success: false,
-            error: Some("搜索查询不能为空".to_string()),
-        });
+        return Err(ApiError::invalid_request("搜索查询不能为空"));
     }
 
     // 构建 HuggingFace API 搜索 URL
@@ -467,33 +466,24 @@ pub async fn search_remote_models(
         Ok(resp) => resp,
         Err(e) => {
             error!("HTTP 请求失败: {}", e);
-            return Ok(SearchRemoteModelsResponse {
-                models: Vec::new(),
-                success: false,
-                error: Some(format!("网络请求失败: {}", e)),
-            });
+            return Err(ApiError::internal(format!("网络请求失败: {}", e)));
         }
     };
 
     if !response.status().is_success() {
         let status = response.status();
         error!("HTTP 请求失败，状态码: {}", status);
-        return Ok(SearchRemoteModelsResponse {
-            models: Vec::new(),
-            success: false,
-            error: Some(format!("HTTP 请求失败，状态码: {}", status)),
-        });
+        return Err(ApiError::internal(format!(
+            "HTTP 请求失败，状态码: {}",
+            status
+        )));
     }
 
     let json: Vec<serde_json::Value> = match response.json().await {
         Ok(data) => data,
         Err(e) => {
             error!("解析 JSON 响应失败: {}", e);
-            return Ok(SearchRemoteModelsResponse {
-                models: Vec::new(),
-                success: false,
-                error: Some(format!("解析响应失败: {}", e)),
-            });
+            return Err(ApiError::internal(format!("解析响应失败: {}", e)));
         }
     };
 
@@ -532,11 +522,7 @@ pub async fn search_remote_models(
 
     info!("找到 {} 个远程模型", models.len());
 
-    Ok(SearchRemoteModelsResponse {
-        models,
-        success: true,
-        error: None,
-    })
+    Ok(SearchRemoteModelsResponse { models })
 }
 
 /// 获取模型文件列表
@@ -587,7 +573,7 @@ async fn get_model_files(repo_id: &str) -> Result<Vec<ModelFileInfo>> {
 #[tauri::command]
 pub async fn download_model(
     request: DownloadModelRequest,
-) -> Result<DownloadModelResponse, String> {
+) -> Result<DownloadModelResponse, ApiError> {
     info!("开始下载模型: {} / {}", request.repo_id, request.filename);
 
     let models_dir = get_models_directory();
@@ -595,12 +581,7 @@ pub async fn download_model(
     // 确保模型目录存在
     if let Err(e) = fs::create_dir_all(&models_dir) {
         error!("创建模型目录失败: {}", e);
-        return Ok(DownloadModelResponse {
-            success: false,
-            message: format!("创建模型目录失败: {}", e),
-            local_path: None,
-            error: Some(format!("创建模型目录失败: {}", e)),
-        });
+        return Err(ApiError::internal(format!("创建模型目录失败: {}", e)));
     }
 
     // 确定保存路径
@@ -648,12 +629,7 @@ pub async fn download_model(
             let model_folder = models_dir.join(model_type).join(&model_name);
             if let Err(e) = fs::create_dir_all(&model_folder) {
                 error!("创建模型文件夹失败: {}", e);
-                return Ok(DownloadModelResponse {
-                    success: false,
-                    message: format!("创建模型文件夹失败: {}", e),
-                    local_path: None,
-                    error: Some(format!("创建模型文件夹失败: {}", e)),
-                });
+                return Err(ApiError::internal(format!("创建模型文件夹失败: {}", e)));
             }
 
             // 保存路径：models/{model_type}/{model_name}/{filename}
@@ -683,12 +659,7 @@ pub async fn download_model(
                     let model_folder = models_dir.join("safetensors").join(&model_name);
                     if let Err(e) = fs::create_dir_all(&model_folder) {
                         error!("创建模型文件夹失败: {}", e);
-                        return Ok(DownloadModelResponse {
-                            success: false,
-                            message: format!("创建模型文件夹失败: {}", e),
-                            local_path: None,
-                            error: Some(format!("创建模型文件夹失败: {}", e)),
-                        });
+                        return Err(ApiError::internal(format!("创建模型文件夹失败: {}", e)));
                     }
                     model_folder.join(&request.filename)
                 }
@@ -719,24 +690,17 @@ pub async fn download_model(
         Ok(resp) => resp,
         Err(e) => {
             error!("下载请求失败: {}", e);
-            return Ok(DownloadModelResponse {
-                success: false,
-                message: format!("下载请求失败: {}", e),
-                local_path: None,
-                error: Some(format!("下载请求失败: {}", e)),
-            });
+            return Err(ApiError::internal(format!("下载请求失败: {}", e)));
         }
     };
 
     if !response.status().is_success() {
         let status = response.status();
         error!("下载失败，状态码: {}", status);
-        return Ok(DownloadModelResponse {
-            success: false,
-            message: format!("下载失败，HTTP 状态码: {}", status),
-            local_path: None,
-            error: Some(format!("下载失败，HTTP 状态码: {}", status)),
-        });
+        return Err(ApiError::internal(format!(
+            "下载失败，HTTP 状态码: {}",
+            status
+        )));
     }
 
     // 获取文件大小（用于显示进度）
@@ -748,12 +712,7 @@ pub async fn download_model(
         Ok(f) => f,
         Err(e) => {
             error!("创建文件失败: {}", e);
-            return Ok(DownloadModelResponse {
-                success: false,
-                message: format!("创建文件失败: {}", e),
-                local_path: None,
-                error: Some(format!("创建文件失败: {}", e)),
-            });
+            return Err(ApiError::internal(format!("创建文件失败: {}", e)));
         }
     };
 
@@ -769,23 +728,13 @@ pub async fn download_model(
             Ok(data) => data,
             Err(e) => {
                 error!("读取数据流失败: {}", e);
-                return Ok(DownloadModelResponse {
-                    success: false,
-                    message: format!("读取数据流失败: {}", e),
-                    local_path: None,
-                    error: Some(format!("读取数据流失败: {}", e)),
-                });
+                return Err(ApiError::internal(format!("读取数据流失败: {}", e)));
             }
         };
 
         if let Err(e) = file.write_all(&chunk) {
             error!("写入文件失败: {}", e);
-            return Ok(DownloadModelResponse {
-                success: false,
-                message: format!("写入文件失败: {}", e),
-                local_path: None,
-                error: Some(format!("写入文件失败: {}", e)),
-            });
+            return Err(ApiError::internal(format!("写入文件失败: {}", e)));
         }
 
         downloaded += chunk.len() as u64;
@@ -804,9 +753,7 @@ pub async fn download_model(
     info!("模型下载完成: {}", save_path.display());
 
     Ok(DownloadModelResponse {
-        success: true,
         message: format!("模型下载成功: {}", save_path.display()),
-        local_path: Some(save_path.to_string_lossy().to_string()),
-        error: None,
+        local_path: save_path.to_string_lossy().to_string(),
     })
 }
@@ -0,0 +1,910 @@
+//! Ollama 兼容的 HTTP 接口
+//!
+//! 提供 `/api/generate`、`/api/chat`（NDJSON 流式输出）、`/api/tags`、`/api/show` 和 `/api/version`，
+//! Open WebUI 等只支持 Ollama 协议的工具可以直接连接本应用。
+//!
+//! 模型名称对应模型目录中扫描到的 GGUF 文件：目录中同名的文件只有一个时标签为 `latest`，
+//! 否则以文件名（不含扩展名）作为标签。请求尚未加载的模型时按需加载，与 Ollama 的行为一致。
+
+use crate::commands::api::ApiState;
+use crate::commands::common::InitGGUFFileRequest;
+use crate::commands::gguf::load_gguf_from_file;
+use crate::commands::models::{get_models_directory, scan_directory_for_models, LocalModelInfo};
+use crate::commands::openai::{spawn_generation, GenerationInput, GenerationMessage};
+use crate::error::{ApiError, ErrorCode};
+use crate::queue::QueueFull;
+use crate::registry::{LoadedModelInfo, ModelKind};
+use ai_base::progress::ignore_progress;
+use ai_base::{ChatMessage, FinishReason, GGUFFileInfo, GenerationOutput, GenerationParams};
+use axum::{
+    body::Body,
+    extract::{rejection::JsonRejection, FromRequest, State},
+    http::{header, HeaderValue},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+    Router,
+};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info};
+
+/// 未指定 `num_predict`（或为负数）时的最大生成长度
+const DEFAULT_NUM_PREDICT: usize = 2048;
+
+/// Ollama 兼容接口的路由
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/api/generate", post(generate))
+        .route("/api/chat", post(chat))
+        .route("/api/tags", get(tags))
+        .route("/api/show", post(show))
+        .route("/api/version", get(version))
+}
+
+/// Ollama 的 `options` 字段
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ModelOptions {
+    num_predict: Option<i64>,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    top_k: Option<usize>,
+    min_p: Option<f64>,
+    typical_p: Option<f64>,
+    repeat_penalty: Option<f32>,
+    frequency_penalty: Option<f32>,
+    presence_penalty: Option<f32>,
+    seed: Option<u64>,
+    stop: Vec<String>,
+}
+
+impl ModelOptions {
+    fn max_tokens(&self) -> usize {
+        self.num_predict
+            .and_then(|n| usize::try_from(n).ok())
+            .unwrap_or(DEFAULT_NUM_PREDICT)
+    }
+
+    fn generation_params(&self) -> GenerationParams {
+        GenerationParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            min_p: self.min_p,
+            typical_p: self.typical_p,
+            repetition_penalty: self.repeat_penalty,
+            frequency_penalty: self.frequency_penalty,
+            presence_penalty: self.presence_penalty,
+            seed: self.seed,
+            stop: self.stop.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+fn default_stream() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct GenerateRequest {
+    model: String,
+    #[serde(default)]
+    prompt: String,
+    #[serde(default)]
+    system: Option<String>,
+    /// 为 true 时不套用对话模板，直接补全提示词
+    #[serde(default)]
+    raw: bool,
+    #[serde(default = "default_stream")]
+    stream: bool,
+    #[serde(default)]
+    options: ModelOptions,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatRequest {
+    model: String,
+    #[serde(default)]
+    messages: Vec<ChatMessage>,
+    #[serde(default = "default_stream")]
+    stream: bool,
+    #[serde(default)]
+    options: ModelOptions,
+}
+
+/// `/api/show` 请求，旧版客户端使用 `name` 字段
+#[derive(Debug, Deserialize)]
+struct ShowRequest {
+    #[serde(alias = "name")]
+    model: String,
+}
+
+/// 最后一条响应中的统计信息（时长单位为纳秒）
+#[derive(Debug, Serialize)]
+struct DoneStats {
+    done_reason: &'static str,
+    total_duration: u64,
+    load_duration: u64,
+    prompt_eval_count: usize,
+    prompt_eval_duration: u64,
+    eval_count: usize,
+    eval_duration: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct AssistantMessage {
+    role: &'static str,
+    content: String,
+}
+
+/// 一行响应：`/api/generate` 输出 `response`，`/api/chat` 输出 `message`
+#[derive(Debug, Serialize)]
+struct ResponseLine {
+    model: String,
+    created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<AssistantMessage>,
+    done: bool,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    stats: Option<DoneStats>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ModelDetails {
+    format: &'static str,
+    family: String,
+    families: Vec<String>,
+    parameter_size: String,
+    quantization_level: String,
+}
+
+impl ModelDetails {
+    fn from_info(info: Option<&GGUFFileInfo>) -> Self {
+        let Some(info) = info else {
+            return Self {
+                format: "gguf",
+                ..Default::default()
+            };
+        };
+        let family = info.architecture.clone().unwrap_or_default();
+        Self {
+            format: "gguf",
+            families: vec![family.clone()],
+            family,
+            parameter_size: format_parameter_count(info.parameter_count),
+            quantization_level: info.quantization.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TagModel {
+    name: String,
+    model: String,
+    modified_at: String,
+    size: u64,
+    digest: String,
+    details: ModelDetails,
+}
+
+#[derive(Debug, Serialize)]
+struct TagsResponse {
+    models: Vec<TagModel>,
+}
+
+#[derive(Debug, Serialize)]
+struct ShowResponse {
+    modelfile: String,
+    parameters: String,
+    template: String,
+    details: ModelDetails,
+    model_info: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified_at: Option<String>,
+}
+
+/// Ollama 格式的错误响应：`{"error": "..."}`，状态码由 [`ApiError`] 的错误码决定
+struct OllamaError(ApiError);
+
+impl OllamaError {
+    fn model_not_found(model: &str) -> Self {
+        Self(ApiError::new(
+            ErrorCode::ModelNotFound,
+            format!("model \"{}\" not found", model),
+        ))
+    }
+}
+
+impl From<ApiError> for OllamaError {
+    fn from(error: ApiError) -> Self {
+        Self(error)
+    }
+}
+
+impl From<QueueFull> for OllamaError {
+    fn from(full: QueueFull) -> Self {
+        Self(full.into())
+    }
+}
+
+impl From<JsonRejection> for OllamaError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self(rejection.into())
+    }
+}
+
+/// 解析失败时返回 Ollama 格式错误的 JSON 请求体
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(OllamaError))]
+struct OllamaJson<T>(T);
+
+impl IntoResponse for OllamaError {
+    fn into_response(self) -> Response {
+        let mut response = (
+            self.0.status(),
+            Json(serde_json::json!({ "error": self.0.message })),
+        )
+            .into_response();
+        if let Some(secs) = self.0.retry_after_secs {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+/// 参数量的简写，例如 360M、7.2B
+fn format_parameter_count(count: u64) -> String {
+    match count {
+        0 => String::new(),
+        c if c >= 1_000_000_000 => format!("{:.1}B", c as f64 / 1e9),
+        c if c >= 1_000_000 => format!("{}M", c / 1_000_000),
+        c => format!("{}K", c / 1_000),
+    }
+}
+
+fn rfc3339_now() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+fn modified_at(path: &str) -> Option<String> {
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339())
+}
+
+fn canonical_path(path: &str) -> String {
+    std::fs::canonicalize(path)
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// 补全 Ollama 名称中省略的 `latest` 标签
+fn with_tag(name: &str) -> String {
+    if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("{}:latest", name)
+    }
+}
+
+/// 模型目录中的 GGUF 模型及其 Ollama 名称
+fn local_gguf_models() -> Result<Vec<(String, LocalModelInfo)>, OllamaError> {
+    let models_dir = get_models_directory();
+    if !models_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let models: Vec<LocalModelInfo> = scan_directory_for_models(&models_dir)
+        .map_err(|e| {
+            error!("扫描模型目录失败: {}", e);
+            ApiError::internal(format!("扫描模型目录失败: {}", e))
+        })?
+        .into_iter()
+        .filter(|model| model.model_type == "gguf")
+        .collect();
+
+    let base_name = |model: &LocalModelInfo| model.name.trim_end_matches(".gguf").to_string();
+    Ok(models
+        .iter()
+        .map(|model| {
+            let base = base_name(model);
+            let shared = models.iter().filter(|m| base_name(m) == base).count() > 1;
+            let tag = if shared {
+                Path::new(&model.path)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "latest".to_string())
+            } else {
+                "latest".to_string()
+            };
+            (format!("{}:{}", base, tag), model.clone())
+        })
+        .collect())
+}
+
+/// 已解析的模型：注册表中的 id，以及按需加载所用的时间
+struct ResolvedModel {
+    model_id: String,
+    load_duration: Duration,
+}
+
+/// 把 Ollama 模型名称解析为已加载的 GGUF 模型，模型目录中的模型尚未加载时先加载它
+async fn resolve_model(state: &ApiState, name: &str) -> Result<ResolvedModel, OllamaError> {
+    let loaded = |id: &str| {
+        state
+            .registry
+            .get(id)
+            .filter(|model| model.kind == ModelKind::Gguf)
+            .map(|model| ResolvedModel {
+                model_id: model.id.clone(),
+                load_duration: Duration::ZERO,
+            })
+    };
+    if let Some(model) = loaded(name).or_else(|| loaded(name.trim_end_matches(":latest"))) {
+        return Ok(model);
+    }
+
+    let tagged = with_tag(name);
+    let Some((_, local)) = local_gguf_models()?
+        .into_iter()
+        .find(|(local_name, _)| *local_name == tagged)
+    else {
+        return Err(OllamaError::model_not_found(name));
+    };
+
+    // 同一个文件可能已经以其他 id 加载
+    let path = canonical_path(&local.path);
+    if let Some(model) = state
+        .registry
+        .list()
+        .into_iter()
+        .find(|model| model.kind == ModelKind::Gguf && model.source == path)
+    {
+        return Ok(ResolvedModel {
+            model_id: model.model_id,
+            load_duration: Duration::ZERO,
+        });
+    }
+
+    info!("按需加载 Ollama 模型 {}: {}", name, local.path);
+    let started = Instant::now();
+    let request = InitGGUFFileRequest {
+        model_id: Some(name.trim_end_matches(":latest").to_string()),
+        model_path: local.path,
+        tokenizer_path: local.tokenizer_path,
+        architecture: None,
+    };
+    let response =
+        load_gguf_from_file(state.gguf.clone(), &state.worker, request, ignore_progress).await?;
+    Ok(ResolvedModel {
+        model_id: response.model_id,
+        load_duration: started.elapsed(),
+    })
+}
+
+fn done_reason(reason: FinishReason) -> &'static str {
+    match reason {
+        FinishReason::Length => "length",
+        FinishReason::Eos | FinishReason::Stop | FinishReason::Cancelled => "stop",
+    }
+}
+
+/// 生成响应的格式
+#[derive(Clone, Copy)]
+enum LineKind {
+    Generate,
+    Chat,
+}
+
+/// 构建响应行，记录首个 token 的时间用于统计
+struct LineBuilder {
+    kind: LineKind,
+    model: String,
+    started: Instant,
+    load_duration: Duration,
+    first_token: Option<Instant>,
+}
+
+impl LineBuilder {
+    fn line(&self, text: String, stats: Option<DoneStats>) -> ResponseLine {
+        let (response, message) = match self.kind {
+            LineKind::Generate => (Some(text), None),
+            LineKind::Chat => (
+                None,
+                Some(AssistantMessage {
+                    role: "assistant",
+                    content: text,
+                }),
+            ),
+        };
+        ResponseLine {
+            model: self.model.clone(),
+            created_at: rfc3339_now(),
+            response,
+            message,
+            done: stats.is_some(),
+            stats,
+        }
+    }
+
+    fn delta(&mut self, text: String) -> ResponseLine {
+        self.first_token.get_or_insert_with(Instant::now);
+        self.line(text, None)
+    }
+
+    /// 最后一行，`text` 为非流式响应的完整文本
+    fn done(&self, text: String, output: &GenerationOutput) -> ResponseLine {
+        let now = Instant::now();
+        let first_token = self.first_token.unwrap_or(now);
+        let stats = DoneStats {
+            done_reason: done_reason(output.finish_reason),
+            total_duration: self.started.elapsed().as_nanos() as u64,
+            load_duration: self.load_duration.as_nanos() as u64,
+            prompt_eval_count: output.prompt_tokens,
+            prompt_eval_duration: first_token.duration_since(self.started).as_nanos() as u64,
+            eval_count: output.completion_tokens,
+            eval_duration: now.duration_since(first_token).as_nanos() as u64,
+        };
+        self.line(text, Some(stats))
+    }
+
+    /// 只加载模型、不生成时的响应
+    fn loaded(&self) -> ResponseLine {
+        let mut line = self.line(String::new(), None);
+        line.done = true;
+        line.stats = Some(DoneStats {
+            done_reason: "load",
+            total_duration: self.started.elapsed().as_nanos() as u64,
+            load_duration: self.load_duration.as_nanos() as u64,
+            prompt_eval_count: 0,
+            prompt_eval_duration: 0,
+            eval_count: 0,
+            eval_duration: 0,
+        });
+        line
+    }
+}
+
+fn ndjson_line<T: Serialize>(value: &T) -> String {
+    let mut line = serde_json::to_string(value).unwrap_or_default();
+    line.push('\n');
+    line
+}
+
+/// 执行生成并按请求的 `stream` 输出 NDJSON 流或单个 JSON 对象
+async fn respond(
+    mut builder: LineBuilder,
+    mut rx: mpsc::UnboundedReceiver<GenerationMessage>,
+    streaming: bool,
+) -> Result<Response, OllamaError> {
+    if !streaming {
+        let mut text = String::new();
+        while let Some(message) = rx.recv().await {
+            match message {
+                GenerationMessage::Delta(delta) => {
+                    builder.first_token.get_or_insert_with(Instant::now);
+                    text.push_str(&delta);
+                }
+                GenerationMessage::Done(result) => {
+                    let output = result.map_err(|e| {
+                        error!("Ollama 接口生成失败: {}", e);
+                        ApiError::from(e)
+                    })?;
+                    return Ok(Json(builder.done(output.text.clone(), &output)).into_response());
+                }
+            }
+        }
+        return Err(ApiError::internal("推理任务异常终止").into());
+    }
+
+    // 生成在输出任何内容前失败时直接返回错误状态码
+    let first = match rx.recv().await {
+        Some(GenerationMessage::Done(Err(e))) => {
+            error!("Ollama 接口生成失败: {}", e);
+            return Err(ApiError::from(e).into());
+        }
+        Some(message) => message,
+        None => return Err(ApiError::internal("推理任务异常终止").into()),
+    };
+
+    let rest = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|message| (message, rx))
+    });
+    let lines = stream::once(async move { first })
+        .chain(rest)
+        .map(move |message| {
+            let line = match message {
+                GenerationMessage::Delta(text) => ndjson_line(&builder.delta(text)),
+                GenerationMessage::Done(Ok(output)) => {
+                    ndjson_line(&builder.done(String::new(), &output))
+                }
+                GenerationMessage::Done(Err(e)) => {
+                    error!("Ollama 流式生成失败: {}", e);
+                    ndjson_line(&serde_json::json!({ "error": format!("{:#}", e) }))
+                }
+            };
+            Ok::<_, Infallible>(line)
+        });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+/// `POST /api/generate`：默认套用对话模板，`raw: true` 时直接补全提示词
+async fn generate(
+    State(state): State<ApiState>,
+    OllamaJson(request): OllamaJson<GenerateRequest>,
+) -> Result<Response, OllamaError> {
+    let started = Instant::now();
+    let resolved = resolve_model(&state, &request.model).await?;
+    let builder = LineBuilder {
+        kind: LineKind::Generate,
+        model: request.model.clone(),
+        started,
+        load_duration: resolved.load_duration,
+        first_token: None,
+    };
+    // 空提示词只加载模型
+    if request.prompt.is_empty() {
+        return Ok(Json(builder.loaded()).into_response());
+    }
+    debug!(
+        "收到 Ollama 生成请求，模型: {}，prompt 长度: {}，流式: {}",
+        resolved.model_id,
+        request.prompt.len(),
+        request.stream
+    );
+
+    let input = if request.raw {
+        GenerationInput::Text(request.prompt)
+    } else {
+        let mut messages = Vec::new();
+        if let Some(system) = request.system.filter(|s| !s.is_empty()) {
+            messages.push(ChatMessage::system(system));
+        }
+        messages.push(ChatMessage::user(request.prompt));
+        GenerationInput::Chat(messages)
+    };
+    let rx = spawn_generation(
+        &state,
+        "/api/generate",
+        format!("ollama-{}", uuid::Uuid::new_v4()),
+        Some(resolved.model_id),
+        input,
+        request.options.max_tokens(),
+        request.options.generation_params(),
+    )
+    .await?;
+    respond(builder, rx, request.stream).await
+}
+
+/// `POST /api/chat`：按模型的对话模板渲染消息后生成助手回复
+async fn chat(
+    State(state): State<ApiState>,
+    OllamaJson(request): OllamaJson<ChatRequest>,
+) -> Result<Response, OllamaError> {
+    let started = Instant::now();
+    let resolved = resolve_model(&state, &request.model).await?;
+    let builder = LineBuilder {
+        kind: LineKind::Chat,
+        model: request.model.clone(),
+        started,
+        load_duration: resolved.load_duration,
+        first_token: None,
+    };
+    // 没有消息时只加载模型
+    if request.messages.is_empty() {
+        return Ok(Json(builder.loaded()).into_response());
+    }
+    debug!(
+        "收到 Ollama 对话请求，模型: {}，消息数: {}，流式: {}",
+        resolved.model_id,
+        request.messages.len(),
+        request.stream
+    );
+
+    let rx = spawn_generation(
+        &state,
+        "/api/chat",
+        format!("ollama-{}", uuid::Uuid::new_v4()),
+        Some(resolved.model_id),
+        GenerationInput::Chat(request.messages),
+        request.options.max_tokens(),
+        request.options.generation_params(),
+    )
+    .await?;
+    respond(builder, rx, request.stream).await
+}
+
+/// 已加载的 GGUF 模型中不在模型目录里的（例如从 Hub 下载的）
+fn loaded_elsewhere(state: &ApiState, local: &[(String, LocalModelInfo)]) -> Vec<LoadedModelInfo> {
+    let local_paths: Vec<String> = local
+        .iter()
+        .map(|(_, model)| canonical_path(&model.path))
+        .collect();
+    state
+        .registry
+        .list()
+        .into_iter()
+        .filter(|model| model.kind == ModelKind::Gguf && !local_paths.contains(&model.source))
+        .collect()
+}
+
+/// `GET /api/tags`：模型目录中的 GGUF 模型，以及在其他位置加载的 GGUF 模型
+async fn tags(State(state): State<ApiState>) -> Result<Json<TagsResponse>, OllamaError> {
+    let local = local_gguf_models()?;
+    let mut models: Vec<TagModel> = local
+        .iter()
+        .map(|(name, model)| {
+            let info = GGUFFileInfo::from_file(Path::new(&model.path)).ok();
+            TagModel {
+                name: name.clone(),
+                model: name.clone(),
+                modified_at: modified_at(&model.path).unwrap_or_default(),
+                size: model.size,
+                digest: String::new(),
+                details: ModelDetails::from_info(info.as_ref()),
+            }
+        })
+        .collect();
+
+    models.extend(loaded_elsewhere(&state, &local).into_iter().map(|model| {
+        let info = GGUFFileInfo::from_file(Path::new(&model.source)).ok();
+        TagModel {
+            name: with_tag(&model.model_id),
+            model: with_tag(&model.model_id),
+            modified_at: modified_at(&model.source).unwrap_or_default(),
+            size: std::fs::metadata(&model.source)
+                .map(|m| m.len())
+                .unwrap_or(model.estimated_bytes),
+            digest: String::new(),
+            details: ModelDetails::from_info(info.as_ref()),
+        }
+    }));
+
+    Ok(Json(TagsResponse { models }))
+}
+
+/// `POST /api/show`：从 GGUF 文件头读取模型信息
+async fn show(
+    State(state): State<ApiState>,
+    OllamaJson(request): OllamaJson<ShowRequest>,
+) -> Result<Json<ShowResponse>, OllamaError> {
+    let name = request.model;
+    let loaded_source = [name.as_str(), name.trim_end_matches(":latest")]
+        .into_iter()
+        .find_map(|id| state.registry.get(id))
+        .filter(|model| model.kind == ModelKind::Gguf)
+        .map(|model| model.source.clone());
+    let path = match loaded_source {
+        Some(source) => source,
+        None => {
+            let tagged = with_tag(&name);
+            local_gguf_models()?
+                .into_iter()
+                .find(|(local_name, _)| *local_name == tagged)
+                .map(|(_, model)| model.path)
+                .ok_or_else(|| OllamaError::model_not_found(&name))?
+        }
+    };
+
+    // 从 Hub 加载的模型 source 不是本地路径，只返回格式
+    let info = GGUFFileInfo::from_file(Path::new(&path)).ok();
+    Ok(Json(ShowResponse {
+        modelfile: String::new(),
+        parameters: String::new(),
+        template: info
+            .as_ref()
+            .and_then(|info| info.chat_template.clone())
+            .unwrap_or_default(),
+        details: ModelDetails::from_info(info.as_ref()),
+        model_info: info
+            .map(|info| info.metadata.into_iter().collect())
+            .unwrap_or_default(),
+        modified_at: modified_at(&path),
+    }))
+}
+
+/// `GET /api/version`
+async fn version() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "version": env!("CARGO_PKG_VERSION") }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+
+    fn builder(kind: LineKind) -> LineBuilder {
+        LineBuilder {
+            kind,
+            model: "m:latest".to_string(),
+            started: Instant::now(),
+            load_duration: Duration::ZERO,
+            first_token: None,
+        }
+    }
+
+    fn output(text: &str, finish_reason: FinishReason) -> GenerationOutput {
+        GenerationOutput {
+            text: text.to_string(),
+            finish_reason,
+            prompt_tokens: 5,
+            cached_tokens: 0,
+            completion_tokens: 2,
+        }
+    }
+
+    /// 依次发送 `messages` 后执行 [`respond`]，返回响应和响应体
+    async fn run(
+        kind: LineKind,
+        messages: Vec<GenerationMessage>,
+        streaming: bool,
+    ) -> (Response, String) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        for message in messages {
+            tx.send(message).ok().unwrap();
+        }
+        drop(tx);
+        let response = match respond(builder(kind), rx, streaming).await {
+            Ok(response) => response,
+            Err(error) => error.into_response(),
+        };
+        let (parts, body) = response.into_parts();
+        let body = to_bytes(body, usize::MAX).await.unwrap();
+        (
+            Response::from_parts(parts, Body::empty()),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    /// NDJSON 响应体的每一行，每行都必须以换行结束
+    fn lines(body: &str) -> Vec<Value> {
+        assert!(body.ends_with('\n'));
+        body.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_option_mapping() {
+        let request: GenerateRequest = serde_json::from_value(json!({
+            "model": "m",
+            "prompt": "hi",
+            "options": {
+                "num_predict": 5,
+                "temperature": 0.1,
+                "top_k": 7,
+                "repeat_penalty": 1.1,
+                "stop": ["\n", "###"]
+            }
+        }))
+        .unwrap();
+        // 未指定 stream 时默认流式输出
+        assert!(request.stream);
+        assert_eq!(request.options.max_tokens(), 5);
+        assert_eq!(
+            request.options.generation_params(),
+            GenerationParams {
+                temperature: Some(0.1),
+                top_k: Some(7),
+                repetition_penalty: Some(1.1),
+                stop: vec!["\n".to_string(), "###".to_string()],
+                ..Default::default()
+            }
+        );
+
+        // num_predict 为负数或未指定时使用默认长度
+        let options: ModelOptions = serde_json::from_value(json!({ "num_predict": -1 })).unwrap();
+        assert_eq!(options.max_tokens(), DEFAULT_NUM_PREDICT);
+        assert_eq!(ModelOptions::default().max_tokens(), DEFAULT_NUM_PREDICT);
+        assert_eq!(
+            ModelOptions::default().generation_params(),
+            GenerationParams::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_ndjson_stream() {
+        let messages = vec![
+            GenerationMessage::Delta("he".to_string()),
+            GenerationMessage::Delta("llo".to_string()),
+            GenerationMessage::Done(Ok(output("hello", FinishReason::Length))),
+        ];
+        let (response, body) = run(LineKind::Generate, messages, true).await;
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+
+        let lines = lines(&body);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["model"], "m:latest");
+        assert_eq!(lines[0]["response"], "he");
+        assert_eq!(lines[0]["done"], false);
+        assert!(lines[0].get("done_reason").is_none());
+        assert!(lines[0].get("message").is_none());
+        assert_eq!(lines[1]["response"], "llo");
+
+        // 最后一行带有结束原因与统计信息，不再重复文本
+        let done = &lines[2];
+        assert_eq!(done["done"], true);
+        assert_eq!(done["response"], "");
+        assert_eq!(done["done_reason"], "length");
+        assert_eq!(done["prompt_eval_count"], 5);
+        assert_eq!(done["eval_count"], 2);
+        assert!(done["total_duration"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn test_chat_ndjson_stream() {
+        let messages = vec![
+            GenerationMessage::Delta("hi".to_string()),
+            GenerationMessage::Done(Ok(output("hi", FinishReason::Eos))),
+        ];
+        let (_, body) = run(LineKind::Chat, messages, true).await;
+        let lines = lines(&body);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0]["message"],
+            json!({ "role": "assistant", "content": "hi" })
+        );
+        assert!(lines[0].get("response").is_none());
+        assert_eq!(lines[1]["done"], true);
+        assert_eq!(lines[1]["done_reason"], "stop");
+        assert_eq!(lines[1]["message"]["content"], "");
+    }
+
+    #[tokio::test]
+    async fn test_non_streaming_response() {
+        let messages = vec![
+            GenerationMessage::Delta("he".to_string()),
+            GenerationMessage::Delta("llo".to_string()),
+            GenerationMessage::Done(Ok(output("hello", FinishReason::Stop))),
+        ];
+        let (response, body) = run(LineKind::Generate, messages, false).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let value: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["response"], "hello");
+        assert_eq!(value["done"], true);
+        assert_eq!(value["done_reason"], "stop");
+    }
+
+    #[tokio::test]
+    async fn test_stream_errors() {
+        // 输出任何内容前失败时返回错误状态码
+        let error = anyhow::Error::new(ApiError::model_not_loaded("m"));
+        let (response, body) = run(
+            LineKind::Generate,
+            vec![GenerationMessage::Done(Err(error))],
+            true,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let value: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["error"], "模型 m 未加载");
+
+        // 输出过内容后失败时以一行错误结束
+        let messages = vec![
+            GenerationMessage::Delta("a".to_string()),
+            GenerationMessage::Done(Err(anyhow::anyhow!("推理失败"))),
+        ];
+        let (response, body) = run(LineKind::Generate, messages, true).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let lines = lines(&body);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], json!({ "error": "推理失败" }));
+    }
+}
@@ -0,0 +1,943 @@
+//! OpenAI 兼容的 HTTP 接口
+//!
+//! 提供 `POST /v1/chat/completions` 与 `POST /v1/completions`，支持 `stream: true` 的 SSE 输出，
+//! 由与 Tauri 命令共用的 GGUF 推理服务执行，Continue 等编辑器和脚本可以把本应用作为本地后端。
+//! `GET /v1/models` 列出已加载的模型和模型目录中可加载的模型。
+
+use crate::commands::api::ApiState;
+use crate::commands::models::{get_models_directory, scan_directory_for_models};
+use crate::error::{ApiError, ErrorCode};
+use crate::inference::GGUFInferenceService;
+use crate::queue::QueueFull;
+use crate::registry::{ModelKind, DEFAULT_GGUF_MODEL_ID};
+use ai_base::{ChatMessage, FinishReason, GenerationOutput, GenerationParams};
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest, State},
+    http::{header, HeaderValue},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Json, Router,
+};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info};
+
+/// 未指定 max_tokens 时的最大生成长度
+const DEFAULT_MAX_TOKENS: usize = 512;
+
+/// OpenAI 兼容接口的路由
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/completions", post(completions))
+        .route("/v1/models", get(list_models))
+}
+
+/// 停止序列：OpenAI 允许单个字符串或字符串数组
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StopSequences {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// 两种接口共用的采样参数（`top_k`、`min_p`、`repetition_penalty` 为扩展字段）
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SamplingFields {
+    max_tokens: Option<usize>,
+    max_completion_tokens: Option<usize>,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    top_k: Option<usize>,
+    min_p: Option<f64>,
+    repetition_penalty: Option<f32>,
+    frequency_penalty: Option<f32>,
+    presence_penalty: Option<f32>,
+    seed: Option<u64>,
+    stop: Option<StopSequences>,
+    n: Option<usize>,
+    stream: bool,
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+impl SamplingFields {
+    fn max_tokens(&self) -> usize {
+        self.max_completion_tokens
+            .or(self.max_tokens)
+            .unwrap_or(DEFAULT_MAX_TOKENS)
+    }
+
+    fn generation_params(&self) -> GenerationParams {
+        GenerationParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            min_p: self.min_p,
+            repetition_penalty: self.repetition_penalty,
+            frequency_penalty: self.frequency_penalty,
+            presence_penalty: self.presence_penalty,
+            seed: self.seed,
+            stop: match &self.stop {
+                None => Vec::new(),
+                Some(StopSequences::One(stop)) => vec![stop.clone()],
+                Some(StopSequences::Many(stops)) => stops.clone(),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn include_usage(&self) -> bool {
+        self.stream_options
+            .as_ref()
+            .is_some_and(|options| options.include_usage)
+    }
+
+    fn validate(&self) -> Result<(), OpenAIError> {
+        if self.n.is_some_and(|n| n != 1) {
+            return Err(ApiError::invalid_request("只支持 n = 1").into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionsRequest {
+    #[serde(default)]
+    model: Option<String>,
+    messages: Vec<ChatMessage>,
+    #[serde(flatten)]
+    sampling: SamplingFields,
+}
+
+/// 补全提示词：OpenAI 允许单个字符串或只含一个元素的数组
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PromptInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionsRequest {
+    #[serde(default)]
+    model: Option<String>,
+    prompt: PromptInput,
+    #[serde(flatten)]
+    sampling: SamplingFields,
+}
+
+#[derive(Debug, Serialize)]
+struct Usage {
+    prompt_tokens: usize,
+    completion_tokens: usize,
+    total_tokens: usize,
+    prompt_tokens_details: PromptTokensDetails,
+}
+
+#[derive(Debug, Serialize)]
+struct PromptTokensDetails {
+    cached_tokens: usize,
+}
+
+impl From<&GenerationOutput> for Usage {
+    fn from(output: &GenerationOutput) -> Self {
+        Self {
+            prompt_tokens: output.prompt_tokens,
+            completion_tokens: output.completion_tokens,
+            total_tokens: output.prompt_tokens + output.completion_tokens,
+            prompt_tokens_details: PromptTokensDetails {
+                cached_tokens: output.cached_tokens,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AssistantMessage {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatChoice {
+    index: usize,
+    message: AssistantMessage,
+    finish_reason: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionResponse {
+    id: String,
+    object: &'static str,
+    created: u64,
+    model: String,
+    choices: Vec<ChatChoice>,
+    usage: Usage,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatChunkChoice {
+    index: usize,
+    delta: ChatDelta,
+    finish_reason: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct CompletionChoice {
+    index: usize,
+    text: String,
+    logprobs: Option<()>,
+    finish_reason: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct CompletionResponse {
+    id: String,
+    object: &'static str,
+    created: u64,
+    model: String,
+    choices: Vec<CompletionChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<Usage>,
+}
+
+/// 流式响应的一个数据块，`choices` 为对话或补全的增量
+#[derive(Debug, Serialize)]
+struct StreamChunk<C> {
+    id: String,
+    object: &'static str,
+    created: u64,
+    model: String,
+    choices: Vec<C>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<Usage>,
+}
+
+/// `GET /v1/models` 中的一个模型
+///
+/// `status`、`kind`、`path` 为扩展字段：已加载的模型可以直接用 id 请求，
+/// `available` 的模型需要先通过 `/admin/models/load` 加载。
+#[derive(Debug, Serialize)]
+struct ModelObject {
+    id: String,
+    object: &'static str,
+    created: u64,
+    owned_by: &'static str,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<ModelKind>,
+    path: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ModelList {
+    object: &'static str,
+    data: Vec<ModelObject>,
+}
+
+/// OpenAI 格式的错误响应，`code` 为 [`ApiError`] 的错误码
+#[derive(Debug)]
+pub struct OpenAIError(ApiError);
+
+impl From<ApiError> for OpenAIError {
+    fn from(error: ApiError) -> Self {
+        Self(error)
+    }
+}
+
+impl From<QueueFull> for OpenAIError {
+    fn from(full: QueueFull) -> Self {
+        Self(full.into())
+    }
+}
+
+impl From<JsonRejection> for OpenAIError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self(rejection.into())
+    }
+}
+
+/// 解析失败时返回 OpenAI 格式 `invalid_request_error` 的 JSON 请求体
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(OpenAIError))]
+struct OpenAIJson<T>(T);
+
+impl IntoResponse for OpenAIError {
+    fn into_response(self) -> Response {
+        let status = self.0.status();
+        let kind = match self.0.code {
+            ErrorCode::QueueFull => "rate_limit_error",
+            ErrorCode::Unauthorized => "authentication_error",
+            _ if status.is_client_error() => "invalid_request_error",
+            _ => "server_error",
+        };
+        let body = serde_json::json!({
+            "error": {
+                "message": self.0.message,
+                "type": kind,
+                "param": null,
+                "code": self.0.code.as_str(),
+            }
+        });
+        let mut response = (status, Json(body)).into_response();
+        if let Some(secs) = self.0.retry_after_secs {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+/// OpenAI 的结束原因：采样到结束标记与停止序列都是 `stop`
+fn finish_reason(reason: FinishReason) -> &'static str {
+    match reason {
+        FinishReason::Length => "length",
+        FinishReason::Eos | FinishReason::Stop | FinishReason::Cancelled => "stop",
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn modified_secs(path: &str) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// 确定请求使用的模型 id：未指定时使用默认 GGUF 模型，指定的模型必须已加载
+fn resolve_model(state: &ApiState, model: Option<&str>) -> Result<Option<String>, OpenAIError> {
+    match model.filter(|model| !model.is_empty()) {
+        None => Ok(None),
+        Some(model) if state.registry.get(model).is_some() => Ok(Some(model.to_string())),
+        Some(model) => Err(ApiError::model_not_loaded(model).into()),
+    }
+}
+
+/// 工作线程发往 HTTP 处理函数的生成消息
+pub(crate) enum GenerationMessage {
+    Delta(String),
+    Done(anyhow::Result<GenerationOutput>),
+}
+
+/// 要执行的生成：对话或文本补全
+pub(crate) enum GenerationInput {
+    Chat(Vec<ChatMessage>),
+    Text(String),
+}
+
+/// 在模型的请求队列中排队，轮到后在阻塞线程池上执行生成，token 增量与最终结果通过通道发送
+///
+/// 不同模型的生成可以同时进行；队列已满时返回 [`QueueFull`]。
+/// 接收端关闭（客户端断开连接）时中止生成。
+pub(crate) async fn spawn_generation(
+    state: &ApiState,
+    route: &str,
+    request_id: String,
+    model_id: Option<String>,
+    input: GenerationInput,
+    max_tokens: usize,
+    params: GenerationParams,
+) -> Result<mpsc::UnboundedReceiver<GenerationMessage>, QueueFull> {
+    let queue_key = model_id.as_deref().unwrap_or(DEFAULT_GGUF_MODEL_ID);
+    let permit = state.queue.acquire(queue_key, &request_id, route).await?;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let service: Arc<GGUFInferenceService> = state.gguf.clone();
+    tokio::task::spawn_blocking(move || {
+        // 生成结束后归还名额
+        let _permit = permit;
+        let on_token = |event: ai_base::TokenEvent| {
+            if event.text.is_empty() {
+                return ControlFlow::Continue(());
+            }
+            match tx.send(GenerationMessage::Delta(event.text)) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            }
+        };
+        let model_id = model_id.as_deref();
+        let result = match &input {
+            GenerationInput::Chat(messages) => service.chat_stream(
+                model_id,
+                &request_id,
+                messages,
+                max_tokens,
+                &params,
+                on_token,
+            ),
+            GenerationInput::Text(prompt) => service.generate_stream(
+                model_id,
+                &request_id,
+                prompt,
+                max_tokens,
+                &params,
+                on_token,
+            ),
+        };
+        let _ = tx.send(GenerationMessage::Done(result));
+    });
+    Ok(rx)
+}
+
+/// 等待生成结束，丢弃中间的增量
+async fn collect_generation(
+    mut rx: mpsc::UnboundedReceiver<GenerationMessage>,
+) -> Result<GenerationOutput, OpenAIError> {
+    while let Some(message) = rx.recv().await {
+        if let GenerationMessage::Done(result) = message {
+            return result.map_err(|e| {
+                error!("OpenAI 接口生成失败: {}", e);
+                ApiError::from(e).into()
+            });
+        }
+    }
+    Err(ApiError::internal("推理任务异常终止").into())
+}
+
+/// 等待第一条消息，生成在输出任何内容前失败时直接返回错误响应
+async fn first_message(
+    rx: &mut mpsc::UnboundedReceiver<GenerationMessage>,
+) -> Result<GenerationMessage, OpenAIError> {
+    match rx.recv().await {
+        Some(GenerationMessage::Done(Err(e))) => {
+            error!("OpenAI 接口生成失败: {}", e);
+            Err(ApiError::from(e).into())
+        }
+        Some(message) => Ok(message),
+        None => Err(ApiError::internal("推理任务异常终止").into()),
+    }
+}
+
+/// 把生成消息转换为 SSE 流：增量、带结束原因的最后一块、可选的用量块和 `[DONE]`
+fn sse_stream<C, F>(
+    first: GenerationMessage,
+    rx: mpsc::UnboundedReceiver<GenerationMessage>,
+    include_usage: bool,
+    chunk: F,
+) -> impl Stream<Item = Result<Event, Infallible>>
+where
+    C: Serialize,
+    F: Fn(Option<String>, Option<&'static str>, Option<Usage>) -> StreamChunk<C> + Send + 'static,
+{
+    let rest = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|message| (message, rx))
+    });
+    stream::once(async move { first })
+        .chain(rest)
+        .flat_map(move |message| {
+            let events: Vec<Event> = match message {
+                GenerationMessage::Delta(text) => vec![json_event(&chunk(Some(text), None, None))],
+                GenerationMessage::Done(Ok(output)) => {
+                    let reason = finish_reason(output.finish_reason);
+                    let mut events = vec![json_event(&chunk(None, Some(reason), None))];
+                    if include_usage {
+                        events.push(json_event(&chunk(None, None, Some(Usage::from(&output)))));
+                    }
+                    events.push(Event::default().data("[DONE]"));
+                    events
+                }
+                GenerationMessage::Done(Err(e)) => {
+                    error!("OpenAI 流式生成失败: {}", e);
+                    let error = ApiError::from(e);
+                    let error = serde_json::json!({
+                        "error": {
+                            "message": error.message,
+                            "type": "server_error",
+                            "code": error.code.as_str(),
+                        }
+                    });
+                    vec![json_event(&error), Event::default().data("[DONE]")]
+                }
+            };
+            stream::iter(events.into_iter().map(Ok))
+        })
+}
+
+fn json_event<T: Serialize>(value: &T) -> Event {
+    Event::default().data(serde_json::to_string(value).unwrap_or_default())
+}
+
+/// `POST /v1/chat/completions`：按模型的对话模板渲染消息后生成助手回复
+async fn chat_completions(
+    State(state): State<ApiState>,
+    OpenAIJson(request): OpenAIJson<ChatCompletionsRequest>,
+) -> Result<Response, OpenAIError> {
+    request.sampling.validate()?;
+    if request.messages.is_empty() {
+        return Err(ApiError::invalid_request("messages 不能为空").into());
+    }
+    let model_id = resolve_model(&state, request.model.as_deref())?;
+    let model = model_id
+        .clone()
+        .or(request.model.clone())
+        .unwrap_or_default();
+    let id = format!("chatcmpl-{}", uuid::Uuid::new_v4());
+    let created = unix_now();
+    debug!(
+        "收到 OpenAI 对话补全请求 {}，模型: {:?}，消息数: {}，流式: {}",
+        id,
+        model_id,
+        request.messages.len(),
+        request.sampling.stream
+    );
+
+    let mut rx = spawn_generation(
+        &state,
+        "/v1/chat/completions",
+        id.clone(),
+        model_id,
+        GenerationInput::Chat(request.messages),
+        request.sampling.max_tokens(),
+        request.sampling.generation_params(),
+    )
+    .await?;
+
+    if !request.sampling.stream {
+        let output = collect_generation(rx).await?;
+        info!(
+            "OpenAI 对话补全 {} 完成，生成 {} 个 token",
+            id, output.completion_tokens
+        );
+        let response = ChatCompletionResponse {
+            id,
+            object: "chat.completion",
+            created,
+            model,
+            choices: vec![ChatChoice {
+                index: 0,
+                message: AssistantMessage {
+                    role: "assistant",
+                    content: output.text.clone(),
+                },
+                finish_reason: finish_reason(output.finish_reason),
+            }],
+            usage: Usage::from(&output),
+        };
+        return Ok(Json(response).into_response());
+    }
+
+    let first = first_message(&mut rx).await?;
+    let include_usage = request.sampling.include_usage();
+    let chunk = chat_chunk(id, created, model);
+    Ok(Sse::new(sse_stream(first, rx, include_usage, chunk))
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+/// 构建对话补全的流式数据块，第一个增量携带 role，与 OpenAI 的输出一致
+fn chat_chunk(
+    id: String,
+    created: u64,
+    model: String,
+) -> impl Fn(Option<String>, Option<&'static str>, Option<Usage>) -> StreamChunk<ChatChunkChoice>
+       + Send
+       + 'static {
+    let first_delta = std::sync::atomic::AtomicBool::new(true);
+    move |content, finish, usage| {
+        let is_usage = usage.is_some();
+        let role = (!is_usage && first_delta.swap(false, std::sync::atomic::Ordering::Relaxed))
+            .then_some("assistant");
+        StreamChunk {
+            id: id.clone(),
+            object: "chat.completion.chunk",
+            created,
+            model: model.clone(),
+            choices: if is_usage {
+                Vec::new()
+            } else {
+                vec![ChatChunkChoice {
+                    index: 0,
+                    delta: ChatDelta { role, content },
+                    finish_reason: finish,
+                }]
+            },
+            usage,
+        }
+    }
+}
+
+/// `POST /v1/completions`：对原始提示词做文本补全
+async fn completions(
+    State(state): State<ApiState>,
+    OpenAIJson(request): OpenAIJson<CompletionsRequest>,
+) -> Result<Response, OpenAIError> {
+    request.sampling.validate()?;
+    let prompt = match request.prompt {
+        PromptInput::One(prompt) => prompt,
+        PromptInput::Many(mut prompts) if prompts.len() == 1 => prompts.remove(0),
+        PromptInput::Many(_) => {
+            return Err(ApiError::invalid_request("prompt 数组只支持一个元素").into());
+        }
+    };
+    let model_id = resolve_model(&state, request.model.as_deref())?;
+    let model = model_id
+        .clone()
+        .or(request.model.clone())
+        .unwrap_or_default();
+    let id = format!("cmpl-{}", uuid::Uuid::new_v4());
+    let created = unix_now();
+    debug!(
+        "收到 OpenAI 文本补全请求 {}，模型: {:?}，prompt 长度: {}，流式: {}",
+        id,
+        model_id,
+        prompt.len(),
+        request.sampling.stream
+    );
+
+    let mut rx = spawn_generation(
+        &state,
+        "/v1/completions",
+        id.clone(),
+        model_id,
+        GenerationInput::Text(prompt),
+        request.sampling.max_tokens(),
+        request.sampling.generation_params(),
+    )
+    .await?;
+
+    if !request.sampling.stream {
+        let output = collect_generation(rx).await?;
+        info!(
+            "OpenAI 文本补全 {} 完成，生成 {} 个 token",
+            id, output.completion_tokens
+        );
+        let response = CompletionResponse {
+            id,
+            object: "text_completion",
+            created,
+            model,
+            choices: vec![CompletionChoice {
+                index: 0,
+                text: output.text.clone(),
+                logprobs: None,
+                finish_reason: Some(finish_reason(output.finish_reason)),
+            }],
+            usage: Some(Usage::from(&output)),
+        };
+        return Ok(Json(response).into_response());
+    }
+
+    let first = first_message(&mut rx).await?;
+    let include_usage = request.sampling.include_usage();
+    let chunk = completion_chunk(id, created, model);
+    Ok(Sse::new(sse_stream(first, rx, include_usage, chunk))
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+/// 构建文本补全的流式数据块
+fn completion_chunk(
+    id: String,
+    created: u64,
+    model: String,
+) -> impl Fn(Option<String>, Option<&'static str>, Option<Usage>) -> StreamChunk<CompletionChoice>
+       + Send
+       + 'static {
+    move |text, finish, usage| {
+        let is_usage = usage.is_some();
+        StreamChunk {
+            id: id.clone(),
+            object: "text_completion",
+            created,
+            model: model.clone(),
+            choices: if is_usage {
+                Vec::new()
+            } else {
+                vec![CompletionChoice {
+                    index: 0,
+                    text: text.unwrap_or_default(),
+                    logprobs: None,
+                    finish_reason: finish,
+                }]
+            },
+            usage,
+        }
+    }
+}
+
+/// `GET /v1/models`：已加载的模型在前，之后是模型目录中尚未加载的模型
+async fn list_models(State(state): State<ApiState>) -> Result<Json<ModelList>, OpenAIError> {
+    let loaded = state.registry.list();
+    let mut data: Vec<ModelObject> = loaded
+        .iter()
+        .map(|model| ModelObject {
+            id: model.model_id.clone(),
+            object: "model",
+            created: model.loaded_at,
+            owned_by: "local",
+            status: "loaded",
+            kind: Some(model.kind),
+            path: model.source.clone(),
+        })
+        .collect();
+
+    let models_dir = get_models_directory();
+    if models_dir.is_dir() {
+        let local = scan_directory_for_models(&models_dir).map_err(|e| {
+            error!("扫描模型目录失败: {}", e);
+            ApiError::internal(format!("扫描模型目录失败: {}", e))
+        })?;
+        data.extend(
+            local
+                .into_iter()
+                .filter(|model| {
+                    // 已加载模型的 source 是规范化后的绝对路径
+                    let path = std::fs::canonicalize(&model.path)
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_else(|_| model.path.clone());
+                    !loaded.iter().any(|m| m.source == path)
+                })
+                .map(|model| ModelObject {
+                    created: modified_secs(&model.path),
+                    id: model.name,
+                    object: "model",
+                    owned_by: "local",
+                    status: "available",
+                    kind: None,
+                    path: model.path,
+                }),
+        );
+    }
+
+    debug!("列出 {} 个模型", data.len());
+    Ok(Json(ModelList {
+        object: "list",
+        data,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+
+    fn output(text: &str, finish_reason: FinishReason) -> GenerationOutput {
+        GenerationOutput {
+            text: text.to_string(),
+            finish_reason,
+            prompt_tokens: 5,
+            cached_tokens: 1,
+            completion_tokens: 2,
+        }
+    }
+
+    /// 把 SSE 流写成响应体，返回每个事件的 `data`
+    async fn sse_data<C, F>(
+        messages: Vec<GenerationMessage>,
+        include_usage: bool,
+        chunk: F,
+    ) -> Vec<String>
+    where
+        C: Serialize + 'static,
+        F: Fn(Option<String>, Option<&'static str>, Option<Usage>) -> StreamChunk<C>
+            + Send
+            + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut messages = messages.into_iter();
+        let first = messages.next().unwrap();
+        for message in messages {
+            tx.send(message).ok().unwrap();
+        }
+        drop(tx);
+
+        let response = Sse::new(sse_stream(first, rx, include_usage, chunk)).into_response();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        // 每个事件以空行结束
+        assert!(body.ends_with("\n\n"));
+        body.trim_end()
+            .split("\n\n")
+            .map(|event| event.strip_prefix("data: ").unwrap().to_string())
+            .collect()
+    }
+
+    fn parse(data: &str) -> Value {
+        serde_json::from_str(data).unwrap()
+    }
+
+    #[test]
+    fn test_chat_request_mapping() {
+        let request: ChatCompletionsRequest = serde_json::from_value(json!({
+            "model": "m",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "max_tokens": 10,
+            "max_completion_tokens": 20,
+            "temperature": 0.2,
+            "top_p": 0.5,
+            "top_k": 3,
+            "seed": 42,
+            "stop": "\n",
+            "stream": true,
+            "stream_options": { "include_usage": true }
+        }))
+        .unwrap();
+        assert_eq!(request.model.as_deref(), Some("m"));
+        assert_eq!(request.messages.len(), 1);
+        // max_completion_tokens 优先于 max_tokens
+        assert_eq!(request.sampling.max_tokens(), 20);
+        assert!(request.sampling.stream);
+        assert!(request.sampling.include_usage());
+        assert_eq!(
+            request.sampling.generation_params(),
+            GenerationParams {
+                temperature: Some(0.2),
+                top_p: Some(0.5),
+                top_k: Some(3),
+                seed: Some(42),
+                stop: vec!["\n".to_string()],
+                ..Default::default()
+            }
+        );
+
+        let request: CompletionsRequest = serde_json::from_value(json!({
+            "prompt": ["once"],
+            "stop": ["a", "b"],
+            "n": 2
+        }))
+        .unwrap();
+        assert!(matches!(request.prompt, PromptInput::Many(ref p) if p == &["once"]));
+        assert_eq!(request.sampling.max_tokens(), DEFAULT_MAX_TOKENS);
+        assert!(!request.sampling.stream);
+        assert!(!request.sampling.include_usage());
+        assert_eq!(request.sampling.generation_params().stop, ["a", "b"]);
+        assert!(request.sampling.validate().is_err());
+    }
+
+    #[test]
+    fn test_finish_reason_and_usage() {
+        assert_eq!(finish_reason(FinishReason::Length), "length");
+        assert_eq!(finish_reason(FinishReason::Eos), "stop");
+        assert_eq!(finish_reason(FinishReason::Stop), "stop");
+        assert_eq!(finish_reason(FinishReason::Cancelled), "stop");
+
+        let usage = Usage::from(&output("", FinishReason::Eos));
+        assert_eq!(
+            serde_json::to_value(usage).unwrap(),
+            json!({
+                "prompt_tokens": 5,
+                "completion_tokens": 2,
+                "total_tokens": 7,
+                "prompt_tokens_details": { "cached_tokens": 1 }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_sse_framing() {
+        let messages = vec![
+            GenerationMessage::Delta("he".to_string()),
+            GenerationMessage::Delta("llo".to_string()),
+            GenerationMessage::Done(Ok(output("hello", FinishReason::Length))),
+        ];
+        let data = sse_data(messages, true, chat_chunk("id".into(), 1, "m".into())).await;
+        assert_eq!(data.len(), 5);
+
+        let first = parse(&data[0]);
+        assert_eq!(first["object"], "chat.completion.chunk");
+        assert_eq!(first["model"], "m");
+        assert_eq!(
+            first["choices"],
+            json!([{ "index": 0, "delta": { "role": "assistant", "content": "he" }, "finish_reason": null }])
+        );
+        assert!(first.get("usage").is_none());
+        // 只有第一个增量携带 role
+        assert_eq!(
+            parse(&data[1])["choices"][0]["delta"],
+            json!({ "content": "llo" })
+        );
+
+        let last = parse(&data[2]);
+        assert_eq!(last["choices"][0]["delta"], json!({}));
+        assert_eq!(last["choices"][0]["finish_reason"], "length");
+
+        let usage = parse(&data[3]);
+        assert_eq!(usage["choices"], json!([]));
+        assert_eq!(usage["usage"]["total_tokens"], 7);
+        assert_eq!(data[4], "[DONE]");
+    }
+
+    #[tokio::test]
+    async fn test_completion_sse_without_usage() {
+        let messages = vec![
+            GenerationMessage::Delta("a".to_string()),
+            GenerationMessage::Done(Ok(output("a", FinishReason::Eos))),
+        ];
+        let data = sse_data(
+            messages,
+            false,
+            completion_chunk("id".into(), 1, "m".into()),
+        )
+        .await;
+        assert_eq!(data.len(), 3);
+        assert_eq!(
+            parse(&data[0])["choices"],
+            json!([{ "index": 0, "text": "a", "logprobs": null, "finish_reason": null }])
+        );
+        assert_eq!(parse(&data[1])["choices"][0]["finish_reason"], "stop");
+        assert_eq!(data[2], "[DONE]");
+    }
+
+    #[tokio::test]
+    async fn test_sse_error_ends_with_done() {
+        let error = anyhow::Error::new(ApiError::model_not_loaded("m"));
+        let messages = vec![
+            GenerationMessage::Delta("a".to_string()),
+            GenerationMessage::Done(Err(error)),
+        ];
+        let data = sse_data(messages, true, completion_chunk("id".into(), 1, "m".into())).await;
+        assert_eq!(data.len(), 3);
+        let error = parse(&data[1]);
+        assert_eq!(error["error"]["code"], "model_not_loaded");
+        assert_eq!(error["error"]["type"], "server_error");
+        assert_eq!(data[2], "[DONE]");
+    }
+
+    #[tokio::test]
+    async fn test_openai_error_response() {
+        let response = OpenAIError::from(QueueFull {
+            retry_after_secs: 3,
+        })
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["type"], "rate_limit_error");
+        assert_eq!(body["error"]["code"], "queue_full");
+    }
+}
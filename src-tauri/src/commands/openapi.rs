@@ -0,0 +1,518 @@
+//! OpenAPI 文档
+//!
+//! `GET /openapi.json` 返回 HTTP 接口的 OpenAPI 3 描述，与 `/health` 一样不需要 API key。
+//! 原生接口的错误响应为 `ErrorEnvelope`；OpenAI 与 Ollama 兼容接口保持各自的错误格式，
+//! 但使用相同的错误码。
+
+use crate::commands::api::ApiState;
+use crate::error::ErrorCode;
+use axum::{response::Json, routing::get, Router};
+use serde_json::{json, Value};
+
+/// OpenAPI 文档的路由
+pub fn routes() -> Router<ApiState> {
+    Router::new().route("/openapi.json", get(openapi_json))
+}
+
+async fn openapi_json() -> Json<Value> {
+    Json(openapi_document())
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+/// JSON 请求体
+fn json_body(schema: &str) -> Value {
+    json!({
+        "required": true,
+        "content": { "application/json": { "schema": schema_ref(schema) } }
+    })
+}
+
+/// JSON 响应
+fn json_response(description: &str, schema: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema_ref(schema) } }
+    })
+}
+
+/// 成功响应加上按状态码分组的错误响应，`codes` 为该接口可能返回的错误码
+fn responses(ok: Value, codes: &[ErrorCode]) -> Value {
+    let mut responses = serde_json::Map::new();
+    responses.insert("200".to_string(), ok);
+    for code in codes {
+        let status = code.status();
+        let entry = responses
+            .entry(status.as_str().to_string())
+            .or_insert_with(|| {
+                json!({
+                    "description": status.canonical_reason().unwrap_or_default(),
+                    "content": { "application/json": { "schema": schema_ref("ErrorEnvelope") } },
+                    "x-error-codes": [],
+                })
+            });
+        entry["x-error-codes"]
+            .as_array_mut()
+            .expect("x-error-codes 是数组")
+            .push(json!(code.as_str()));
+    }
+    Value::Object(responses)
+}
+
+fn operation(tag: &str, summary: &str, body: Option<&str>, responses: Value) -> Value {
+    let mut operation = json!({
+        "tags": [tag],
+        "summary": summary,
+        "responses": responses,
+    });
+    if let Some(body) = body {
+        operation["requestBody"] = json_body(body);
+    }
+    operation
+}
+
+/// 生成 OpenAPI 3 文档
+pub fn openapi_document() -> Value {
+    use ErrorCode::*;
+
+    let generation_errors = [
+        InvalidRequest,
+        ModelNotLoaded,
+        TokenizerMissing,
+        ContextOverflow,
+        RequestInProgress,
+        QueueFull,
+        InternalError,
+    ];
+    let sse = json!({
+        "description": "非流式请求返回 JSON；`stream: true` 时返回 Server-Sent Events，以 `data: [DONE]` 结束",
+        "content": {
+            "application/json": { "schema": { "type": "object" } },
+            "text/event-stream": { "schema": { "type": "string" } }
+        }
+    });
+    let ndjson = json!({
+        "description": "`stream: false` 时返回单个 JSON 对象，否则每行一个 JSON 对象",
+        "content": {
+            "application/json": { "schema": { "type": "object" } },
+            "application/x-ndjson": { "schema": { "type": "string" } }
+        }
+    });
+    let any_object = json!({ "type": "object", "additionalProperties": true });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "SeekerAI Tools API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "本地模型推理服务。配置了 API key 时，除 /health 与 /openapi.json 外的接口都需要 `Authorization: Bearer <key>`；未配置 API key 时 /admin 接口不可用。"
+        },
+        "security": [{ "bearerAuth": [] }],
+        "paths": {
+            "/health": {
+                "get": {
+                    "tags": ["system"],
+                    "summary": "健康检查",
+                    "security": [],
+                    "responses": { "200": json_response("服务正在运行", "ApiResponse") }
+                }
+            },
+            "/openapi.json": {
+                "get": {
+                    "tags": ["system"],
+                    "summary": "本文档",
+                    "security": [],
+                    "responses": { "200": { "description": "OpenAPI 3 文档" } }
+                }
+            },
+            "/metrics": {
+                "get": operation("system", "Prometheus 格式的推理统计", None, responses(
+                    json!({ "description": "Prometheus 文本格式", "content": { "text/plain": { "schema": { "type": "string" } } } }),
+                    &[Unauthorized],
+                ))
+            },
+            "/api/inference": {
+                "post": operation("native", "GGUF 文本推理", Some("InferenceRequest"), responses(
+                    json_response("生成结果", "InferenceResponse"),
+                    &[Unauthorized, InvalidRequest, ModelNotLoaded, TokenizerMissing, ContextOverflow, RequestInProgress, QueueFull, InternalError],
+                ))
+            },
+            "/admin/models/load": {
+                "post": operation("admin", "从本地文件加载 GGUF 模型", Some("InitGGUFFileRequest"), responses(
+                    json_response("加载成功", "InitModelResponse"),
+                    &[Unauthorized, InvalidRequest, ModelNotFound, TokenizerMissing, InternalError],
+                ))
+            },
+            "/admin/models/unload": {
+                "post": operation("admin", "卸载模型", Some("UnloadModelRequest"), responses(
+                    json_response("卸载成功", "ApiResponse"),
+                    &[Unauthorized, InvalidRequest, ModelNotLoaded],
+                ))
+            },
+            "/admin/models/{id}": {
+                "get": {
+                    "tags": ["admin"],
+                    "summary": "已加载模型的信息",
+                    "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+                    "responses": responses(json_response("模型信息", "LoadedModelInfo"), &[Unauthorized, ModelNotLoaded])
+                }
+            },
+            "/ws/chat": {
+                "get": {
+                    "tags": ["native"],
+                    "summary": "WebSocket 对话会话",
+                    "description": "升级为 WebSocket 后以 JSON 文本帧通信。客户端发送 `configure`（model、system、max_tokens 与 GenerationParams 中的采样参数）、`message`（content）、`cancel`、`reset`；服务端返回 `session`、`delta`（request_id、content）、`done`（request_id、finish_reason、usage）与 `error`（ErrorEnvelope 中的 error）。对话历史与 KV cache 按连接保存。API key 也可以通过 `api_key` 查询参数传入；带 `Origin` 的握手请求需要来源在 allowed_origins 中。",
+                    "parameters": [{ "name": "api_key", "in": "query", "required": false, "schema": { "type": "string" } }],
+                    "responses": {
+                        "101": { "description": "切换到 WebSocket 协议" },
+                        "401": {
+                            "description": "Unauthorized",
+                            "content": { "application/json": { "schema": schema_ref("ErrorEnvelope") } },
+                            "x-error-codes": ["unauthorized"]
+                        },
+                        "403": {
+                            "description": "Origin 不在 allowed_origins 中",
+                            "content": { "application/json": { "schema": schema_ref("ErrorEnvelope") } },
+                            "x-error-codes": ["forbidden"]
+                        }
+                    }
+                }
+            },
+            "/admin/queue": {
+                "get": operation("admin", "各模型正在执行和排队中的请求", None, responses(
+                    json_response("队列状态", "QueueStatus"),
+                    &[Unauthorized],
+                ))
+            },
+            "/v1/chat/completions": {
+                "post": operation("openai", "OpenAI 兼容的对话补全", Some("OpenAIChatCompletionRequest"), responses(sse.clone(), &generation_errors))
+            },
+            "/v1/completions": {
+                "post": operation("openai", "OpenAI 兼容的文本补全", Some("OpenAICompletionRequest"), responses(sse, &generation_errors))
+            },
+            "/v1/models": {
+                "get": operation("openai", "已加载和模型目录中的模型", None, responses(
+                    json!({ "description": "模型列表", "content": { "application/json": { "schema": any_object } } }),
+                    &[Unauthorized, InternalError],
+                ))
+            },
+            "/api/generate": {
+                "post": operation("ollama", "Ollama 兼容的文本生成，未加载的模型按需加载", Some("OllamaGenerateRequest"), responses(ndjson.clone(), &[ModelNotFound, TokenizerMissing, ContextOverflow, QueueFull, InternalError]))
+            },
+            "/api/chat": {
+                "post": operation("ollama", "Ollama 兼容的对话，未加载的模型按需加载", Some("OllamaChatRequest"), responses(ndjson, &[ModelNotFound, TokenizerMissing, ContextOverflow, QueueFull, InternalError]))
+            },
+            "/api/tags": {
+                "get": operation("ollama", "模型目录中的 GGUF 模型", None, responses(
+                    json!({ "description": "模型列表", "content": { "application/json": { "schema": any_object } } }),
+                    &[InternalError],
+                ))
+            },
+            "/api/show": {
+                "post": operation("ollama", "模型详情", Some("OllamaShowRequest"), responses(
+                    json!({ "description": "模型详情", "content": { "application/json": { "schema": any_object } } }),
+                    &[ModelNotFound, InternalError],
+                ))
+            },
+            "/api/version": {
+                "get": operation("ollama", "兼容的 Ollama 版本", None, responses(
+                    json!({ "description": "版本号", "content": { "application/json": { "schema": any_object } } }),
+                    &[],
+                ))
+            }
+        },
+        "components": {
+            "securitySchemes": {
+                "bearerAuth": { "type": "http", "scheme": "bearer" }
+            },
+            "schemas": {
+                "ErrorCode": {
+                    "type": "string",
+                    "enum": ErrorCode::ALL.iter().map(|code| code.as_str()).collect::<Vec<_>>()
+                },
+                "ApiError": {
+                    "type": "object",
+                    "required": ["code", "message"],
+                    "properties": {
+                        "code": schema_ref("ErrorCode"),
+                        "message": { "type": "string" },
+                        "retry_after_secs": { "type": "integer", "description": "队列已满时建议等待的秒数，同时通过 Retry-After 头返回" }
+                    }
+                },
+                "ErrorEnvelope": {
+                    "type": "object",
+                    "required": ["error"],
+                    "properties": { "error": schema_ref("ApiError") }
+                },
+                "ApiResponse": {
+                    "type": "object",
+                    "properties": {
+                        "message": { "type": "string" },
+                        "status": { "type": "string" }
+                    }
+                },
+                "GenerationParams": {
+                    "type": "object",
+                    "description": "采样参数与停止序列，未设置的参数使用模型的默认值",
+                    "properties": {
+                        "temperature": { "type": "number" },
+                        "top_p": { "type": "number" },
+                        "top_k": { "type": "integer" },
+                        "min_p": { "type": "number" },
+                        "typical_p": { "type": "number" },
+                        "repetition_penalty": { "type": "number" },
+                        "frequency_penalty": { "type": "number" },
+                        "presence_penalty": { "type": "number" },
+                        "seed": { "type": "integer" },
+                        "stop": { "type": "array", "items": { "type": "string" } },
+                        "session_id": { "type": "string", "description": "KV cache 会话标识，同一会话的请求复用相同前缀的缓存；qwen2 架构的模型不支持，指定时返回 invalid_request" }
+                    }
+                },
+                "InferenceRequest": {
+                    "allOf": [
+                        schema_ref("GenerationParams"),
+                        {
+                            "type": "object",
+                            "required": ["prompt"],
+                            "properties": {
+                                "model_id": { "type": "string", "description": "未提供时使用默认 GGUF 模型" },
+                                "request_id": { "type": "string" },
+                                "prompt": { "type": "string" },
+                                "max_tokens": { "type": "integer", "default": 512 }
+                            }
+                        }
+                    ]
+                },
+                "TokenUsage": {
+                    "type": "object",
+                    "properties": {
+                        "prompt_tokens": { "type": "integer" },
+                        "cached_tokens": { "type": "integer" },
+                        "completion_tokens": { "type": "integer" }
+                    }
+                },
+                "InferenceResponse": {
+                    "type": "object",
+                    "properties": {
+                        "text": { "type": "string" },
+                        "finish_reason": { "type": "string", "enum": ["eos", "stop", "length", "cancelled"], "nullable": true },
+                        "usage": { "allOf": [schema_ref("TokenUsage")], "nullable": true }
+                    }
+                },
+                "InitGGUFFileRequest": {
+                    "type": "object",
+                    "required": ["model_path"],
+                    "properties": {
+                        "model_id": { "type": "string" },
+                        "model_path": { "type": "string" },
+                        "tokenizer_path": { "type": "string" },
+                        "architecture": { "type": "string", "enum": ["llama", "mistral", "qwen2", "qwen3", "phi3", "gemma3"] }
+                    }
+                },
+                "InitModelResponse": {
+                    "type": "object",
+                    "properties": {
+                        "message": { "type": "string" },
+                        "model_id": { "type": "string" }
+                    }
+                },
+                "UnloadModelRequest": {
+                    "type": "object",
+                    "required": ["model_id"],
+                    "properties": { "model_id": { "type": "string" } }
+                },
+                "LoadedModelInfo": {
+                    "type": "object",
+                    "properties": {
+                        "model_id": { "type": "string" },
+                        "kind": { "type": "string", "enum": ["gguf", "safetensors", "qwen3vl"] },
+                        "source": { "type": "string" },
+                        "loaded_at": { "type": "integer" },
+                        "busy": { "type": "boolean" },
+                        "estimated_bytes": { "type": "integer" },
+                        "actual_bytes": { "type": "integer", "nullable": true }
+                    }
+                },
+                "QueueStatus": {
+                    "type": "object",
+                    "properties": {
+                        "max_concurrent_per_model": { "type": "integer" },
+                        "max_queue_depth": { "type": "integer" },
+                        "retry_after_secs": { "type": "integer" },
+                        "models": { "type": "array", "items": any_object }
+                    }
+                },
+                "ChatMessage": {
+                    "type": "object",
+                    "required": ["role", "content"],
+                    "properties": {
+                        "role": { "type": "string", "enum": ["system", "user", "assistant", "tool"] },
+                        "content": { "type": "string" }
+                    }
+                },
+                "OpenAIChatCompletionRequest": {
+                    "type": "object",
+                    "required": ["messages"],
+                    "properties": {
+                        "model": { "type": "string" },
+                        "messages": { "type": "array", "items": schema_ref("ChatMessage") },
+                        "max_tokens": { "type": "integer" },
+                        "stream": { "type": "boolean" }
+                    },
+                    "additionalProperties": true
+                },
+                "OpenAICompletionRequest": {
+                    "type": "object",
+                    "required": ["prompt"],
+                    "properties": {
+                        "model": { "type": "string" },
+                        "prompt": { "oneOf": [{ "type": "string" }, { "type": "array", "items": { "type": "string" }, "maxItems": 1 }] },
+                        "max_tokens": { "type": "integer" },
+                        "stream": { "type": "boolean" }
+                    },
+                    "additionalProperties": true
+                },
+                "OllamaGenerateRequest": {
+                    "type": "object",
+                    "required": ["model"],
+                    "properties": {
+                        "model": { "type": "string" },
+                        "prompt": { "type": "string" },
+                        "system": { "type": "string" },
+                        "raw": { "type": "boolean" },
+                        "stream": { "type": "boolean", "default": true },
+                        "options": any_object
+                    }
+                },
+                "OllamaChatRequest": {
+                    "type": "object",
+                    "required": ["model"],
+                    "properties": {
+                        "model": { "type": "string" },
+                        "messages": { "type": "array", "items": schema_ref("ChatMessage") },
+                        "stream": { "type": "boolean", "default": true },
+                        "options": any_object
+                    }
+                },
+                "OllamaShowRequest": {
+                    "type": "object",
+                    "properties": {
+                        "model": { "type": "string" },
+                        "name": { "type": "string" }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::api::{build_router, ServerConfig};
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use tokio::sync::watch;
+    use tower::ServiceExt;
+
+    const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+    /// 收集文档中全部 `$ref` 的值
+    fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    match value {
+                        Value::String(target) if key == "$ref" => refs.push(target.clone()),
+                        _ => collect_refs(value, refs),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| collect_refs(item, refs)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_document_is_valid() {
+        let document = openapi_document();
+        assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(document["info"]["title"].is_string());
+        assert!(document["info"]["version"].is_string());
+
+        // 所有引用都指向已定义的 schema
+        let schemas = document["components"]["schemas"].as_object().unwrap();
+        let mut refs = Vec::new();
+        collect_refs(&document, &mut refs);
+        assert!(!refs.is_empty());
+        for target in refs {
+            let name = target
+                .strip_prefix("#/components/schemas/")
+                .unwrap_or_else(|| panic!("不支持的引用: {}", target));
+            assert!(schemas.contains_key(name), "未定义的 schema: {}", name);
+        }
+
+        for (path, item) in document["paths"].as_object().unwrap() {
+            assert!(path.starts_with('/'), "{}", path);
+            for (method, operation) in item.as_object().unwrap() {
+                assert!(METHODS.contains(&method.as_str()), "{} {}", method, path);
+                let responses = operation["responses"].as_object().unwrap();
+                assert!(!responses.is_empty(), "{} {} 没有响应", method, path);
+                for (status, response) in responses {
+                    assert!(response["description"].is_string(), "{} {}", method, path);
+                    // 错误码与所在状态码一致
+                    for code in response["x-error-codes"].as_array().into_iter().flatten() {
+                        let code = ErrorCode::ALL
+                            .into_iter()
+                            .find(|c| Some(c.as_str()) == code.as_str())
+                            .unwrap_or_else(|| panic!("未知的错误码: {}", code));
+                        assert_eq!(code.status().as_str(), status, "{} {}", method, path);
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_documented_routes_are_mounted() {
+        let config = ServerConfig {
+            api_keys: vec!["secret".to_string()],
+            ..ServerConfig::default()
+        };
+        let (_close_sockets, sockets_closed) = watch::channel(false);
+        let app = build_router(ApiState::for_test(), &config, sockets_closed).unwrap();
+
+        let document = openapi_document();
+        for (path, item) in document["paths"].as_object().unwrap() {
+            let uri = path.replace("{id}", "missing");
+            for method in item.as_object().unwrap().keys() {
+                // 请求体为空，JSON 接口在解析请求体时就返回错误，不会执行推理
+                let request = Request::builder()
+                    .method(method.to_uppercase().as_str())
+                    .uri(&uri)
+                    .header(header::AUTHORIZATION, "Bearer secret")
+                    .body(Body::empty())
+                    .unwrap();
+                let response = app.clone().oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                // 路由未匹配时返回空响应体的 404，方法不匹配时返回 405
+                assert_ne!(
+                    status,
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "{} {}",
+                    method,
+                    path
+                );
+                assert!(
+                    !(status == StatusCode::NOT_FOUND && body.is_empty()),
+                    "{} {} 没有挂载",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+}
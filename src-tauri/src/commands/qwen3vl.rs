@@ -1,79 +1,41 @@
 use crate::commands::common::*;
+use crate::commands::registry::load_progress_emitter;
+use crate::error::{ApiError, ErrorCode};
 use crate::inference::InferenceService;
-use std::path::PathBuf;
+use crate::registry::DEFAULT_SAFETENSORS_MODEL_ID;
+use crate::worker::InferenceWorker;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tauri::State;
+use tauri::{AppHandle, State};
 use tracing::{debug, error, info};
 
-use ai_base::models::qwen3vl::inference::Qwen3VLInferenceEngine;
 use ai_base::models::qwen3vl::Qwen3VLConfig;
-use candle_core::Device;
 
 /// 初始化 qwen3vl-8b 模型
+///
+/// 加载过程中发送 `model-load-progress` 事件。
 #[tauri::command]
 pub async fn init_qwen3vl_model(
+    app: AppHandle,
     state: State<'_, Arc<InferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
     request: InitModelRequest,
-) -> Result<InitModelResponse, String> {
+) -> Result<InitModelResponse, ApiError> {
     info!("开始初始化 Qwen3VL 模型");
     info!(
         "模型路径: {}, Tokenizer 路径: {}",
         request.model_path, request.tokenizer_path
     );
-    let model_path = PathBuf::from(&request.model_path);
-    let config_path = model_path.join("../config.json");
-
-    tracing::info!("config_path: {}", config_path.display());
-
-    let config_str = std::fs::read_to_string(config_path).unwrap();
-
-    let config: Qwen3VLConfig = serde_json::from_str(&config_str).unwrap();
-    let engine = Qwen3VLInferenceEngine::new(
-        request.model_path.as_str(),
-        request.tokenizer_path.as_str(),
-        config,
-        Device::Cpu,
-    );
-    tracing::debug!("Qwen3VL 模型初始化成功");
+    let dtype = request
+        .dtype
+        .as_deref()
+        .map(ai_base::weights::parse_dtype)
+        .transpose()
+        .map_err(|e| ApiError::invalid_request(e.to_string()))?;
 
+    let model_path = PathBuf::from(&request.model_path);
     let tokenizer_path = PathBuf::from(&request.tokenizer_path);
 
-    // 如果模型路径是目录，尝试自动查找模型文件
-    let final_model_path = if model_path.is_dir() {
-        // 尝试查找 safetensors 文件
-        let safetensors = model_path.join("model.safetensors");
-        if safetensors.exists() {
-            safetensors
-        } else {
-            // 查找其他可能的模型文件
-            let model_files: Vec<PathBuf> = std::fs::read_dir(&model_path)
-                .ok()
-                .and_then(|dir| {
-                    dir.filter_map(|entry| {
-                        entry.ok().and_then(|e| {
-                            let path = e.path();
-                            if path.extension().and_then(|s| s.to_str()) == Some("safetensors") {
-                                Some(path)
-                            } else {
-                                None
-                            }
-                        })
-                    })
-                    .collect::<Vec<_>>()
-                    .into()
-                })
-                .unwrap_or_default();
-
-            if let Some(first_file) = model_files.first() {
-                first_file.clone()
-            } else {
-                model_path
-            }
-        }
-    } else {
-        model_path
-    };
-
     // 如果 tokenizer 路径是目录，尝试自动查找 tokenizer 文件
     let final_tokenizer_path = if tokenizer_path.is_dir() {
         let tokenizer_json = tokenizer_path.join("tokenizer.json");
@@ -86,61 +48,74 @@ pub async fn init_qwen3vl_model(
         tokenizer_path
     };
 
-    // 尝试从模型目录加载配置
-    let model_dir = if final_model_path.is_file() {
-        final_model_path
-            .parent()
-            .unwrap_or(&final_model_path)
-            .to_path_buf()
-    } else {
-        final_model_path.clone()
-    };
-
-    let model_config = match crate::inference::create_qwen3vl_config_from_dir(&model_dir) {
-        Ok(config) => config,
-        Err(e) => {
-            return Ok(InitModelResponse {
-                success: false,
-                message: format!(
-                    "无法加载模型配置: {}. \
-                    请确保模型目录包含有效的 config.json 文件，或手动提供 Config 结构体。\
-                    模型目录: {:?}",
-                    e, model_dir
-                ),
-            });
-        }
-    };
-
     // 初始化模型
     info!("开始加载模型文件");
-    match state.init_model(
-        final_model_path.clone(),
-        final_tokenizer_path.clone(),
-        model_config,
-    ) {
-        Ok(_) => {
-            info!("Qwen3VL-8B 模型初始化成功");
+    let model_id = request
+        .model_id
+        .unwrap_or_else(|| DEFAULT_SAFETENSORS_MODEL_ID.to_string());
+    let on_progress = load_progress_emitter(app, model_id.clone());
+    let service = state.inner().clone();
+    // 读取配置和加载权重都在推理线程上进行，不阻塞异步运行时
+    let queue_key = model_id.clone();
+    let job = worker.submit(
+        &queue_key,
+        uuid::Uuid::new_v4().to_string(),
+        "load_model",
+        move || {
+            let config = read_qwen3vl_config(&model_path)?;
+            service.init_qwen3vl_model(
+                Some(model_id),
+                model_path,
+                final_tokenizer_path,
+                config,
+                dtype,
+                &on_progress,
+            )
+        },
+    );
+    match job.join().await {
+        Ok(model_id) => {
+            info!("Qwen3VL-8B 模型 {} 初始化成功", model_id);
             Ok(InitModelResponse {
-                success: true,
                 message: "Qwen3VL-8B 模型初始化成功".to_string(),
+                model_id,
             })
         }
         Err(e) => {
-            error!("模型初始化失败: {}", e);
-            Ok(InitModelResponse {
-                success: false,
-                message: format!("模型初始化失败: {}", e),
-            })
+            error!("模型初始化失败: {:#}", e);
+            Err(ApiError::from(e).context("模型初始化失败"))
         }
     }
 }
 
+/// 读取模型目录中的 Qwen3VL config.json
+///
+/// `model_path` 为模型目录或其中的权重文件时，都读取该目录下的 `config.json`。
+fn read_qwen3vl_config(model_path: &Path) -> Result<Qwen3VLConfig, ApiError> {
+    let config_path = ai_base::weights::model_dir(model_path).join("config.json");
+    debug!("Qwen3VL 配置文件: {}", config_path.display());
+
+    let config_str = std::fs::read_to_string(&config_path).map_err(|e| {
+        ApiError::new(
+            ErrorCode::ModelNotFound,
+            format!("无法读取 Qwen3VL 配置文件 {:?}: {}", config_path, e),
+        )
+    })?;
+    serde_json::from_str(&config_str).map_err(|e| {
+        ApiError::invalid_request(format!(
+            "Qwen3VL 配置文件 {:?} 格式无效: {}",
+            config_path, e
+        ))
+    })
+}
+
 /// 执行推理
 #[tauri::command]
 pub async fn generate_text(
     state: State<'_, Arc<InferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
     request: InferenceRequest,
-) -> Result<InferenceResponse, String> {
+) -> Result<InferenceResponse, ApiError> {
     let max_tokens = request.max_tokens.unwrap_or(512);
     debug!(
         "收到文本推理请求，prompt 长度: {}, max_tokens: {}",
@@ -148,31 +123,46 @@ pub async fn generate_text(
         max_tokens
     );
 
-    match state.generate(&request.prompt, max_tokens) {
+    let service = state.inner().clone();
+    let request_id = request
+        .request_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let queue_key = request
+        .model_id
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SAFETENSORS_MODEL_ID.to_string());
+    let job = worker.submit(&queue_key, request_id, "generate", move || {
+        service.generate(
+            request.model_id.as_deref(),
+            &request.prompt,
+            max_tokens,
+            &request.params,
+        )
+    });
+    match job.join().await {
         Ok(text) => {
             info!("文本推理成功，生成长度: {}", text.len());
             Ok(InferenceResponse {
                 text,
-                success: true,
-                error: None,
+                finish_reason: None,
+                usage: None,
             })
         }
         Err(e) => {
-            error!("文本推理失败: {}", e);
-            Ok(InferenceResponse {
-                text: String::new(),
-                success: false,
-                error: Some(format!("推理失败: {}", e)),
-            })
+            error!("文本推理失败: {:#}", e);
+            Err(ApiError::from(e).context("推理失败"))
         }
     }
 }
 
 /// 检查模型是否已加载
 #[tauri::command]
-pub async fn is_model_loaded(state: State<'_, Arc<InferenceService>>) -> Result<bool, String> {
-    let loaded = state.is_loaded();
-    debug!("检查模型加载状态: {}", loaded);
+pub async fn is_model_loaded(
+    state: State<'_, Arc<InferenceService>>,
+    model_id: Option<String>,
+) -> Result<bool, ApiError> {
+    let loaded = state.is_loaded(model_id.as_deref());
+    debug!("检查模型 {:?} 加载状态: {}", model_id, loaded);
     Ok(loaded)
 }
 
@@ -180,8 +170,9 @@ pub async fn is_model_loaded(state: State<'_, Arc<InferenceService>>) -> Result<
 #[tauri::command]
 pub async fn generate_multimodal(
     state: State<'_, Arc<InferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
     request: MultimodalInferenceRequest,
-) -> Result<InferenceResponse, String> {
+) -> Result<InferenceResponse, ApiError> {
     let max_tokens = request.max_tokens.unwrap_or(512);
     let image_path = PathBuf::from(&request.image_path);
     info!(
@@ -191,22 +182,36 @@ pub async fn generate_multimodal(
         max_tokens
     );
 
-    match state.generate_multimodal(image_path.clone(), &request.prompt, max_tokens) {
+    let service = state.inner().clone();
+    let queue_key = request
+        .model_id
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SAFETENSORS_MODEL_ID.to_string());
+    let job = worker.submit(
+        &queue_key,
+        uuid::Uuid::new_v4().to_string(),
+        "generate",
+        move || {
+            service.generate_multimodal(
+                request.model_id.as_deref(),
+                image_path,
+                &request.prompt,
+                max_tokens,
+            )
+        },
+    );
+    match job.join().await {
         Ok(text) => {
             info!("多模态推理成功，生成长度: {}", text.len());
             Ok(InferenceResponse {
                 text,
-                success: true,
-                error: None,
+                finish_reason: None,
+                usage: None,
             })
         }
         Err(e) => {
-            error!("多模态推理失败: {}", e);
-            Ok(InferenceResponse {
-                text: String::new(),
-                success: false,
-                error: Some(format!("多模态推理失败: {}", e)),
-            })
+            error!("多模态推理失败: {:#}", e);
+            Err(ApiError::from(e).context("多模态推理失败"))
         }
     }
 }
@@ -215,8 +220,9 @@ pub async fn generate_multimodal(
 #[tauri::command]
 pub async fn generate_multimodal_from_bytes(
     state: State<'_, Arc<InferenceService>>,
+    worker: State<'_, Arc<InferenceWorker>>,
     request: MultimodalInferenceFromBytesRequest,
-) -> Result<InferenceResponse, String> {
+) -> Result<InferenceResponse, ApiError> {
     let max_tokens = request.max_tokens.unwrap_or(512);
     info!(
         "收到多模态推理请求（字节数据），图像数据大小: {} bytes, prompt 长度: {}, max_tokens: {}",
@@ -225,22 +231,36 @@ pub async fn generate_multimodal_from_bytes(
         max_tokens
     );
 
-    match state.generate_multimodal_from_bytes(&request.image_data, &request.prompt, max_tokens) {
+    let service = state.inner().clone();
+    let queue_key = request
+        .model_id
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SAFETENSORS_MODEL_ID.to_string());
+    let job = worker.submit(
+        &queue_key,
+        uuid::Uuid::new_v4().to_string(),
+        "generate",
+        move || {
+            service.generate_multimodal_from_bytes(
+                request.model_id.as_deref(),
+                &request.image_data,
+                &request.prompt,
+                max_tokens,
+            )
+        },
+    );
+    match job.join().await {
         Ok(text) => {
             info!("多模态推理（字节数据）成功，生成长度: {}", text.len());
             Ok(InferenceResponse {
                 text,
-                success: true,
-                error: None,
+                finish_reason: None,
+                usage: None,
             })
         }
         Err(e) => {
-            error!("多模态推理（字节数据）失败: {}", e);
-            Ok(InferenceResponse {
-                text: String::new(),
-                success: false,
-                error: Some(format!("多模态推理失败: {}", e)),
-            })
+            error!("多模态推理（字节数据）失败: {:#}", e);
+            Err(ApiError::from(e).context("多模态推理失败"))
         }
     }
 }
@@ -0,0 +1,75 @@
+use crate::commands::common::LoadProgressEvent;
+use crate::error::ApiError;
+use crate::registry::{LoadedModelInfo, MemoryReport, ModelRegistry};
+use ai_base::LoadProgress;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, State};
+use tracing::{info, warn};
+
+/// 模型加载进度事件名
+pub const MODEL_LOAD_PROGRESS_EVENT: &str = "model-load-progress";
+
+/// 创建把加载进度作为 `model-load-progress` 事件发送的回调
+pub(crate) fn load_progress_emitter(
+    app: AppHandle,
+    model_id: String,
+) -> impl Fn(LoadProgress) + Send + Sync {
+    move |progress| {
+        let payload = LoadProgressEvent {
+            model_id: model_id.clone(),
+            progress,
+        };
+        if let Err(e) = app.emit(MODEL_LOAD_PROGRESS_EVENT, payload) {
+            warn!("发送加载进度事件失败: {}", e);
+        }
+    }
+}
+
+/// 列出全部已加载的模型
+#[tauri::command]
+pub async fn list_loaded_models(
+    registry: State<'_, Arc<ModelRegistry>>,
+) -> Result<Vec<LoadedModelInfo>, ApiError> {
+    Ok(registry.list())
+}
+
+/// 卸载指定模型，返回是否找到该模型
+///
+/// 该模型上正在进行的生成会继续完成，之后释放模型占用的内存。
+#[tauri::command]
+pub async fn unload_model(
+    registry: State<'_, Arc<ModelRegistry>>,
+    model_id: String,
+) -> Result<bool, ApiError> {
+    match registry.remove(&model_id) {
+        Some(_) => {
+            info!("已卸载模型: {}", model_id);
+            Ok(true)
+        }
+        None => {
+            warn!("未找到要卸载的模型: {}", model_id);
+            Ok(false)
+        }
+    }
+}
+
+/// 获取已加载模型的内存占用（加载前估算值与加载前后进程常驻内存的差值）
+#[tauri::command]
+pub async fn get_model_memory(
+    registry: State<'_, Arc<ModelRegistry>>,
+) -> Result<MemoryReport, ApiError> {
+    Ok(registry.memory_report())
+}
+
+/// 设置已加载模型的内存预算（字节），None 表示不限制
+///
+/// 超出预算时，新模型加载成功后会卸载最久未使用的空闲模型。
+#[tauri::command]
+pub async fn set_memory_budget(
+    registry: State<'_, Arc<ModelRegistry>>,
+    budget_bytes: Option<u64>,
+) -> Result<(), ApiError> {
+    registry.set_budget(budget_bytes);
+    info!("模型内存预算已设置为: {:?}", budget_bytes);
+    Ok(())
+}
@@ -2,22 +2,24 @@
 //!
 //! 提供文件读写、数据持久化等功能
 
+use crate::error::ApiError;
 use serde::Serialize;
 use std::path::PathBuf;
 use tauri::Manager;
 use tracing::{debug, info};
 
 /// 获取应用数据目录
-fn get_app_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+fn get_app_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, ApiError> {
     app.path()
         .app_data_dir()
-        .map_err(|e| format!("无法获取应用数据目录: {}", e))
+        .map_err(|e| ApiError::internal(format!("无法获取应用数据目录: {}", e)))
 }
 
 /// 确保目录存在
-fn ensure_dir_exists(path: &PathBuf) -> Result<(), String> {
+fn ensure_dir_exists(path: &PathBuf) -> Result<(), ApiError> {
     if !path.exists() {
-        std::fs::create_dir_all(path).map_err(|e| format!("创建目录失败: {}", e))?;
+        std::fs::create_dir_all(path)
+            .map_err(|e| ApiError::internal(format!("创建目录失败: {}", e)))?;
     }
     Ok(())
 }
@@ -34,20 +36,20 @@ pub struct AppPaths {
 
 /// 获取应用路径
 #[tauri::command]
-pub async fn get_app_paths(app: tauri::AppHandle) -> Result<AppPaths, String> {
+pub async fn get_app_paths(app: tauri::AppHandle) -> Result<AppPaths, ApiError> {
     let data_dir = get_app_data_dir(&app)?;
     let config_dir = app
         .path()
         .app_config_dir()
-        .map_err(|e| format!("无法获取配置目录: {}", e))?;
+        .map_err(|e| ApiError::internal(format!("无法获取配置目录: {}", e)))?;
     let cache_dir = app
         .path()
         .app_cache_dir()
-        .map_err(|e| format!("无法获取缓存目录: {}", e))?;
+        .map_err(|e| ApiError::internal(format!("无法获取缓存目录: {}", e)))?;
     let log_dir = app
         .path()
         .app_log_dir()
-        .map_err(|e| format!("无法获取日志目录: {}", e))?;
+        .map_err(|e| ApiError::internal(format!("无法获取日志目录: {}", e)))?;
 
     let models_dir = data_dir.join("models");
 
@@ -69,7 +71,7 @@ pub async fn get_app_paths(app: tauri::AppHandle) -> Result<AppPaths, String> {
 
 /// 读取文件内容
 #[tauri::command]
-pub async fn read_file(path: String) -> Result<String, String> {
+pub async fn read_file(path: String) -> Result<String, ApiError> {
     debug!("读取文件: {}", path);
 
     // 安全检查：防止路径遍历
@@ -78,15 +80,15 @@ pub async fn read_file(path: String) -> Result<String, String> {
         .components()
         .any(|c| matches!(c, std::path::Component::ParentDir))
     {
-        return Err("不允许路径遍历".to_string());
+        return Err(ApiError::invalid_request("不允许路径遍历"));
     }
 
-    std::fs::read_to_string(&path).map_err(|e| format!("读取文件失败: {}", e))
+    std::fs::read_to_string(&path).map_err(|e| ApiError::internal(format!("读取文件失败: {}", e)))
 }
 
 /// 写入文件内容
 #[tauri::command]
-pub async fn write_file(path: String, content: String) -> Result<(), String> {
+pub async fn write_file(path: String, content: String) -> Result<(), ApiError> {
     debug!("写入文件: {}", path);
 
     let path = PathBuf::from(&path);
@@ -96,7 +98,7 @@ pub async fn write_file(path: String, content: String) -> Result<(), String> {
         .components()
         .any(|c| matches!(c, std::path::Component::ParentDir))
     {
-        return Err("不允许路径遍历".to_string());
+        return Err(ApiError::invalid_request("不允许路径遍历"));
     }
 
     // 确保父目录存在
@@ -104,7 +106,7 @@ pub async fn write_file(path: String, content: String) -> Result<(), String> {
         ensure_dir_exists(&parent.to_path_buf())?;
     }
 
-    std::fs::write(&path, content).map_err(|e| format!("写入文件失败: {}", e))
+    std::fs::write(&path, content).map_err(|e| ApiError::internal(format!("写入文件失败: {}", e)))
 }
 
 /// 文件/目录信息
@@ -119,28 +121,29 @@ pub struct FileEntry {
 
 /// 列出目录内容
 #[tauri::command]
-pub async fn list_directory(path: String) -> Result<Vec<FileEntry>, String> {
+pub async fn list_directory(path: String) -> Result<Vec<FileEntry>, ApiError> {
     debug!("列出目录: {}", path);
 
     let path = PathBuf::from(&path);
 
     if !path.exists() {
-        return Err("目录不存在".to_string());
+        return Err(ApiError::invalid_request("目录不存在"));
     }
 
     if !path.is_dir() {
-        return Err("路径不是目录".to_string());
+        return Err(ApiError::invalid_request("路径不是目录"));
     }
 
     let mut entries = Vec::new();
 
-    let read_dir = std::fs::read_dir(&path).map_err(|e| format!("读取目录失败: {}", e))?;
+    let read_dir =
+        std::fs::read_dir(&path).map_err(|e| ApiError::internal(format!("读取目录失败: {}", e)))?;
 
     for entry in read_dir {
-        let entry = entry.map_err(|e| format!("读取条目失败: {}", e))?;
+        let entry = entry.map_err(|e| ApiError::internal(format!("读取条目失败: {}", e)))?;
         let metadata = entry
             .metadata()
-            .map_err(|e| format!("获取元数据失败: {}", e))?;
+            .map_err(|e| ApiError::internal(format!("获取元数据失败: {}", e)))?;
 
         let modified_at = metadata
             .modified()
@@ -169,17 +172,17 @@ pub async fn list_directory(path: String) -> Result<Vec<FileEntry>, String> {
 
 /// 创建目录
 #[tauri::command]
-pub async fn create_directory(path: String) -> Result<(), String> {
+pub async fn create_directory(path: String) -> Result<(), ApiError> {
     debug!("创建目录: {}", path);
 
     let path = PathBuf::from(&path);
 
-    std::fs::create_dir_all(&path).map_err(|e| format!("创建目录失败: {}", e))
+    std::fs::create_dir_all(&path).map_err(|e| ApiError::internal(format!("创建目录失败: {}", e)))
 }
 
 /// 删除文件或目录
 #[tauri::command]
-pub async fn delete_file(path: String, recursive: bool) -> Result<(), String> {
+pub async fn delete_file(path: String, recursive: bool) -> Result<(), ApiError> {
     info!("删除文件/目录: {}, recursive: {}", path, recursive);
 
     let path = PathBuf::from(&path);
@@ -190,12 +193,15 @@ pub async fn delete_file(path: String, recursive: bool) -> Result<(), String> {
 
     if path.is_dir() {
         if recursive {
-            std::fs::remove_dir_all(&path).map_err(|e| format!("删除目录失败: {}", e))?;
+            std::fs::remove_dir_all(&path)
+                .map_err(|e| ApiError::internal(format!("删除目录失败: {}", e)))?;
         } else {
-            std::fs::remove_dir(&path).map_err(|e| format!("删除空目录失败: {}", e))?;
+            std::fs::remove_dir(&path)
+                .map_err(|e| ApiError::internal(format!("删除空目录失败: {}", e)))?;
         }
     } else {
-        std::fs::remove_file(&path).map_err(|e| format!("删除文件失败: {}", e))?;
+        std::fs::remove_file(&path)
+            .map_err(|e| ApiError::internal(format!("删除文件失败: {}", e)))?;
     }
 
     Ok(())
@@ -219,7 +225,7 @@ pub struct SystemInfo {
 
 /// 获取系统信息
 #[tauri::command]
-pub async fn get_system_info() -> Result<SystemInfo, String> {
+pub async fn get_system_info() -> Result<SystemInfo, ApiError> {
     // 简化版本：不使用 sysinfo 库
     // 如需详细信息，可以添加 sysinfo 依赖
     let cpu_count = std::thread::available_parallelism()
@@ -235,22 +241,30 @@ pub async fn get_system_info() -> Result<SystemInfo, String> {
     })
 }
 
-/// 保存设置
-#[tauri::command]
-pub async fn save_setting(app: tauri::AppHandle, key: String, value: String) -> Result<(), String> {
+/// 设置文件路径（配置目录下的 settings.json）
+fn settings_file(app: &tauri::AppHandle) -> Result<PathBuf, ApiError> {
     let config_dir = app
         .path()
         .app_config_dir()
-        .map_err(|e| format!("无法获取配置目录: {}", e))?;
-
-    ensure_dir_exists(&config_dir)?;
+        .map_err(|e| ApiError::internal(format!("无法获取配置目录: {}", e)))?;
+    Ok(config_dir.join("settings.json"))
+}
 
-    let settings_file = config_dir.join("settings.json");
+/// 写入一项设置，供命令和其他模块共用
+pub(crate) fn write_setting(
+    app: &tauri::AppHandle,
+    key: String,
+    value: String,
+) -> Result<(), ApiError> {
+    let settings_file = settings_file(app)?;
+    if let Some(config_dir) = settings_file.parent() {
+        ensure_dir_exists(&config_dir.to_path_buf())?;
+    }
 
     // 读取现有设置
     let mut settings: serde_json::Value = if settings_file.exists() {
         let content = std::fs::read_to_string(&settings_file)
-            .map_err(|e| format!("读取设置文件失败: {}", e))?;
+            .map_err(|e| ApiError::internal(format!("读取设置文件失败: {}", e)))?;
         serde_json::from_str(&content).unwrap_or(serde_json::json!({}))
     } else {
         serde_json::json!({})
@@ -262,63 +276,67 @@ pub async fn save_setting(app: tauri::AppHandle, key: String, value: String) ->
     }
 
     // 保存设置
-    let content =
-        serde_json::to_string_pretty(&settings).map_err(|e| format!("序列化设置失败: {}", e))?;
+    let content = serde_json::to_string_pretty(&settings)
+        .map_err(|e| ApiError::internal(format!("序列化设置失败: {}", e)))?;
 
-    std::fs::write(&settings_file, content).map_err(|e| format!("保存设置文件失败: {}", e))?;
+    std::fs::write(&settings_file, content)
+        .map_err(|e| ApiError::internal(format!("保存设置文件失败: {}", e)))?;
 
     Ok(())
 }
 
-/// 读取设置
-#[tauri::command]
-pub async fn get_setting(
-    app: tauri::AppHandle,
-    key: String,
-    default_value: Option<String>,
-) -> Result<Option<String>, String> {
-    let config_dir = app
-        .path()
-        .app_config_dir()
-        .map_err(|e| format!("无法获取配置目录: {}", e))?;
-
-    let settings_file = config_dir.join("settings.json");
+/// 读取一项设置，不存在时返回 None
+pub(crate) fn read_setting(app: &tauri::AppHandle, key: &str) -> Result<Option<String>, ApiError> {
+    let settings_file = settings_file(app)?;
 
     if !settings_file.exists() {
-        return Ok(default_value);
+        return Ok(None);
     }
 
-    let content =
-        std::fs::read_to_string(&settings_file).map_err(|e| format!("读取设置文件失败: {}", e))?;
+    let content = std::fs::read_to_string(&settings_file)
+        .map_err(|e| ApiError::internal(format!("读取设置文件失败: {}", e)))?;
 
-    let settings: serde_json::Value =
-        serde_json::from_str(&content).map_err(|e| format!("解析设置文件失败: {}", e))?;
+    let settings: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| ApiError::internal(format!("解析设置文件失败: {}", e)))?;
 
-    if let Some(value) = settings.get(&key) {
-        if let Some(s) = value.as_str() {
-            return Ok(Some(s.to_string()));
-        }
-    }
+    Ok(settings
+        .get(key)
+        .and_then(|value| value.as_str())
+        .map(|s| s.to_string()))
+}
 
-    Ok(default_value)
+/// 保存设置
+#[tauri::command]
+pub async fn save_setting(
+    app: tauri::AppHandle,
+    key: String,
+    value: String,
+) -> Result<(), ApiError> {
+    write_setting(&app, key, value)
 }
 
-/// 获取所有设置
+/// 读取设置
 #[tauri::command]
-pub async fn get_all_settings(app: tauri::AppHandle) -> Result<serde_json::Value, String> {
-    let config_dir = app
-        .path()
-        .app_config_dir()
-        .map_err(|e| format!("无法获取配置目录: {}", e))?;
+pub async fn get_setting(
+    app: tauri::AppHandle,
+    key: String,
+    default_value: Option<String>,
+) -> Result<Option<String>, ApiError> {
+    Ok(read_setting(&app, &key)?.or(default_value))
+}
 
-    let settings_file = config_dir.join("settings.json");
+/// 获取所有设置
+#[tauri::command]
+pub async fn get_all_settings(app: tauri::AppHandle) -> Result<serde_json::Value, ApiError> {
+    let settings_file = settings_file(&app)?;
 
     if !settings_file.exists() {
         return Ok(serde_json::json!({}));
     }
 
-    let content =
-        std::fs::read_to_string(&settings_file).map_err(|e| format!("读取设置文件失败: {}", e))?;
+    let content = std::fs::read_to_string(&settings_file)
+        .map_err(|e| ApiError::internal(format!("读取设置文件失败: {}", e)))?;
 
-    serde_json::from_str(&content).map_err(|e| format!("解析设置文件失败: {}", e))
+    serde_json::from_str(&content)
+        .map_err(|e| ApiError::internal(format!("解析设置文件失败: {}", e)))
 }
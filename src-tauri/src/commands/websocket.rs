@@ -0,0 +1,497 @@
+//! WebSocket 对话接口
+//!
+//! `GET /ws/chat` 升级为 WebSocket 后，一个连接对应一个对话会话：服务端保存对话历史，
+//! 并以连接独有的 KV cache 会话生成，后续轮次只需计算新增的消息。消息均为 JSON 文本帧，以 `type` 区分：
+//!
+//! - 客户端：`configure`（模型、系统提示词、max_tokens 与采样参数）、`message`、`cancel`、`reset`
+//! - 服务端：`session`（连接建立、配置或重置后的会话状态）、`delta`、`done`、`error`
+//!
+//! 同一连接同时只进行一次生成，生成与其他推理接口共用按模型的请求队列。
+//! 浏览器无法为 WebSocket 设置请求头，启用 API key 时也可以通过 `?api_key=` 传入。
+//! WebSocket 不受 CORS 限制，因此握手时按 `allowed_origins` 检查浏览器发送的 `Origin`，
+//! 不在列表中时返回 403；不带 `Origin` 的非浏览器客户端不受影响。
+//! 服务器停止时关闭所有连接。
+
+use crate::commands::api::ApiState;
+use crate::commands::common::TokenUsage;
+use crate::commands::openai::{spawn_generation, GenerationInput, GenerationMessage};
+use crate::error::{ApiError, ErrorCode};
+use ai_base::{ChatMessage, FinishReason, GenerationParams};
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::{header, HeaderMap, HeaderValue},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// 对话接口的路由
+pub const CHAT_ROUTE: &str = "/ws/chat";
+
+/// 未指定 max_tokens 时的最大生成长度
+const DEFAULT_MAX_TOKENS: usize = 512;
+
+/// WebSocket 接口的路由，`shutdown` 变化时关闭所有连接
+///
+/// `allowed_origins` 与服务器的跨域配置相同：包含 "*" 时允许所有来源。
+pub fn routes(shutdown: watch::Receiver<bool>, allowed_origins: Vec<String>) -> Router<ApiState> {
+    let allowed_origins = Arc::new(allowed_origins);
+    Router::new().route(
+        CHAT_ROUTE,
+        get(
+            move |headers: HeaderMap, ws: WebSocketUpgrade, State(state): State<ApiState>| {
+                let shutdown = shutdown.clone();
+                let allowed_origins = allowed_origins.clone();
+                async move {
+                    if let Some(origin) = headers.get(header::ORIGIN) {
+                        if !origin_allowed(&allowed_origins, origin) {
+                            warn!("拒绝来源不被允许的 WebSocket 连接: {:?}", origin);
+                            return ApiError::new(
+                                ErrorCode::Forbidden,
+                                "WebSocket 连接的来源不在允许的列表中",
+                            )
+                            .into_response();
+                        }
+                    }
+                    ws.on_upgrade(move |socket| run_session(socket, state, shutdown))
+                }
+            },
+        ),
+    )
+}
+
+/// `origin` 是否在允许的来源列表中
+fn origin_allowed(allowed_origins: &[String], origin: &HeaderValue) -> bool {
+    allowed_origins
+        .iter()
+        .any(|allowed| allowed == "*" || allowed.as_bytes() == origin.as_bytes())
+}
+
+/// 客户端消息
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientFrame {
+    /// 替换会话配置，对话历史保留
+    Configure(Box<SessionConfig>),
+    /// 发送用户消息并生成回复
+    Message { content: String },
+    /// 中止正在进行的生成，已生成的部分保留在对话历史中
+    Cancel,
+    /// 清空对话历史
+    Reset,
+}
+
+/// 会话配置
+#[derive(Debug, Default, Deserialize)]
+struct SessionConfig {
+    /// 已加载模型的 id，未设置时使用默认 GGUF 模型
+    model: Option<String>,
+    /// 系统提示词
+    system: Option<String>,
+    max_tokens: Option<usize>,
+    /// 采样参数与停止序列，与其他字段位于同一层级；`session_id` 由服务端设置
+    #[serde(flatten)]
+    params: GenerationParams,
+}
+
+/// 服务端消息
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerFrame {
+    /// 会话状态
+    Session {
+        session_id: String,
+        model: Option<String>,
+        /// 对话历史中的消息数（不含系统提示词）
+        messages: usize,
+    },
+    /// 新生成的文本
+    Delta { request_id: String, content: String },
+    /// 生成结束，排队期间被取消时没有 usage
+    Done {
+        request_id: String,
+        /// "eos"、"stop"、"length" 或 "cancelled"
+        finish_reason: &'static str,
+        usage: Option<TokenUsage>,
+    },
+    /// 错误，生成失败时带有对应的 request_id，该轮的用户消息不会保留
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        error: ApiError,
+    },
+}
+
+/// 正在进行的一轮生成
+struct Turn {
+    request_id: String,
+    rx: mpsc::UnboundedReceiver<GenerationMessage>,
+    /// 排队并转发生成消息的任务
+    task: JoinHandle<()>,
+}
+
+/// 一个连接的对话状态
+struct ChatSession {
+    state: ApiState,
+    /// KV cache 会话标识，每个连接一个
+    session_id: String,
+    config: SessionConfig,
+    /// 对话历史（不含系统提示词）
+    history: Vec<ChatMessage>,
+    turn: Option<Turn>,
+}
+
+impl ChatSession {
+    fn new(state: ApiState) -> Self {
+        Self {
+            state,
+            session_id: format!("ws-{}", uuid::Uuid::new_v4()),
+            config: SessionConfig::default(),
+            history: Vec::new(),
+            turn: None,
+        }
+    }
+
+    fn status(&self) -> ServerFrame {
+        ServerFrame::Session {
+            session_id: self.session_id.clone(),
+            model: self.config.model.clone(),
+            messages: self.history.len(),
+        }
+    }
+
+    fn ensure_idle(&self) -> Result<(), ApiError> {
+        match &self.turn {
+            Some(turn) => Err(ApiError::new(
+                ErrorCode::RequestInProgress,
+                format!("请求 {} 正在进行中", turn.request_id),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// 处理一条客户端消息，连接已断开时返回 false
+    async fn handle_text(&mut self, socket: &mut WebSocket, text: &str) -> bool {
+        let frame = match serde_json::from_str::<ClientFrame>(text) {
+            Ok(frame) => frame,
+            Err(e) => {
+                let error = ApiError::invalid_request(format!("无效的消息: {}", e));
+                return send(
+                    socket,
+                    &ServerFrame::Error {
+                        request_id: None,
+                        error,
+                    },
+                )
+                .await;
+            }
+        };
+        let result = match frame {
+            ClientFrame::Configure(config) => self.configure(*config).map(|()| Some(self.status())),
+            ClientFrame::Message { content } => self.start_turn(content).map(|()| None),
+            ClientFrame::Cancel => Ok(self.cancel()),
+            ClientFrame::Reset => self.ensure_idle().map(|()| {
+                self.history.clear();
+                Some(self.status())
+            }),
+        };
+        match result {
+            Ok(Some(frame)) => send(socket, &frame).await,
+            Ok(None) => true,
+            Err(error) => {
+                send(
+                    socket,
+                    &ServerFrame::Error {
+                        request_id: None,
+                        error,
+                    },
+                )
+                .await
+            }
+        }
+    }
+
+    fn configure(&mut self, config: SessionConfig) -> Result<(), ApiError> {
+        self.ensure_idle()?;
+        if let Some(model) = config.model.as_deref() {
+            if self.state.registry.get(model).is_none() {
+                return Err(ApiError::model_not_loaded(model));
+            }
+        }
+        if config.model != self.config.model {
+            self.release_cache();
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    /// 把用户消息加入历史，并在模型的请求队列中排队生成回复
+    fn start_turn(&mut self, content: String) -> Result<(), ApiError> {
+        self.ensure_idle()?;
+        self.history.push(ChatMessage::user(content));
+
+        let mut messages = Vec::with_capacity(self.history.len() + 1);
+        if let Some(system) = &self.config.system {
+            messages.push(ChatMessage::system(system.clone()));
+        }
+        messages.extend(self.history.iter().cloned());
+        let mut params = self.config.params.clone();
+        params.session_id = Some(self.session_id.clone());
+        let max_tokens = self.config.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+        let model_id = self.config.model.clone();
+        let request_id = uuid::Uuid::new_v4().to_string();
+        debug!(
+            "WebSocket 会话 {} 开始生成 {}，消息数: {}",
+            self.session_id,
+            request_id,
+            messages.len()
+        );
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let state = self.state.clone();
+        let id = request_id.clone();
+        let task = tokio::spawn(async move {
+            let input = GenerationInput::Chat(messages);
+            match spawn_generation(&state, CHAT_ROUTE, id, model_id, input, max_tokens, params)
+                .await
+            {
+                Ok(mut generation) => {
+                    while let Some(message) = generation.recv().await {
+                        if tx.send(message).is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(full) => {
+                    let _ = tx.send(GenerationMessage::Done(Err(full.into())));
+                }
+            }
+        });
+        self.turn = Some(Turn {
+            request_id,
+            rx,
+            task,
+        });
+        Ok(())
+    }
+
+    /// 中止正在进行的生成
+    ///
+    /// 已开始的生成会以 `cancelled` 结束并照常返回 `done`；仍在排队的请求直接移出队列。
+    fn cancel(&mut self) -> Option<ServerFrame> {
+        let Some(turn) = &self.turn else {
+            debug!("WebSocket 会话 {} 没有正在进行的生成", self.session_id);
+            return None;
+        };
+        if self.state.gguf.cancel(&turn.request_id) {
+            return None;
+        }
+        let turn = self.turn.take()?;
+        turn.task.abort();
+        self.history.pop();
+        Some(ServerFrame::Done {
+            request_id: turn.request_id,
+            finish_reason: FinishReason::Cancelled.as_str(),
+            usage: None,
+        })
+    }
+
+    /// 处理生成消息，`None` 表示生成任务异常终止
+    fn handle_generation(&mut self, message: Option<GenerationMessage>) -> Option<ServerFrame> {
+        let request_id = self.turn.as_ref()?.request_id.clone();
+        match message {
+            Some(GenerationMessage::Delta(content)) => Some(ServerFrame::Delta {
+                request_id,
+                content,
+            }),
+            Some(GenerationMessage::Done(Ok(output))) => {
+                self.turn = None;
+                if output.text.is_empty() && output.finish_reason == FinishReason::Cancelled {
+                    self.history.pop();
+                } else {
+                    self.history.push(ChatMessage::assistant(output.text));
+                }
+                Some(ServerFrame::Done {
+                    request_id,
+                    finish_reason: output.finish_reason.as_str(),
+                    usage: Some(TokenUsage {
+                        prompt_tokens: output.prompt_tokens,
+                        cached_tokens: output.cached_tokens,
+                        completion_tokens: output.completion_tokens,
+                    }),
+                })
+            }
+            Some(GenerationMessage::Done(Err(e))) => {
+                warn!("WebSocket 会话 {} 生成失败: {:#}", self.session_id, e);
+                self.turn = None;
+                self.history.pop();
+                Some(ServerFrame::Error {
+                    request_id: Some(request_id),
+                    error: ApiError::from(e),
+                })
+            }
+            None => {
+                self.turn = None;
+                self.history.pop();
+                Some(ServerFrame::Error {
+                    request_id: Some(request_id),
+                    error: ApiError::internal("推理任务异常终止"),
+                })
+            }
+        }
+    }
+
+    /// 在后台释放本会话在当前模型上的 KV cache
+    fn release_cache(&self) {
+        let gguf = self.state.gguf.clone();
+        let model_id = self.config.model.clone();
+        let session_id = self.session_id.clone();
+        tokio::task::spawn_blocking(move || {
+            match gguf.remove_session(model_id.as_deref(), &session_id) {
+                Ok(removed) => debug!("释放会话 {} 的 KV cache: {}", session_id, removed),
+                Err(e) => debug!("释放会话 {} 的 KV cache 失败: {}", session_id, e),
+            }
+        });
+    }
+
+    /// 连接关闭：中止生成并释放 KV cache
+    fn close(mut self) {
+        if let Some(turn) = self.turn.take() {
+            self.state.gguf.cancel(&turn.request_id);
+            turn.task.abort();
+        }
+        self.release_cache();
+    }
+}
+
+/// 等待当前一轮生成的下一条消息，没有进行中的生成时一直等待
+async fn next_message(turn: &mut Option<Turn>) -> Option<GenerationMessage> {
+    match turn {
+        Some(turn) => turn.rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 发送一条服务端消息，连接已断开时返回 false
+async fn send(socket: &mut WebSocket, frame: &ServerFrame) -> bool {
+    let text = serde_json::to_string(frame).unwrap_or_default();
+    socket.send(Message::Text(text.into())).await.is_ok()
+}
+
+async fn run_session(mut socket: WebSocket, state: ApiState, mut shutdown: watch::Receiver<bool>) {
+    let mut session = ChatSession::new(state);
+    info!("WebSocket 对话会话 {} 已建立", session.session_id);
+
+    let mut open = send(&mut socket, &session.status()).await;
+    while open {
+        tokio::select! {
+            frame = socket.recv() => {
+                open = match frame {
+                    Some(Ok(Message::Text(text))) => session.handle_text(&mut socket, text.as_str()).await,
+                    Some(Ok(Message::Binary(_))) => {
+                        let error = ApiError::invalid_request("只支持 JSON 文本帧");
+                        send(&mut socket, &ServerFrame::Error { request_id: None, error }).await
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => false,
+                    // ping 由底层自动回复
+                    Some(Ok(_)) => true,
+                };
+            }
+            message = next_message(&mut session.turn) => {
+                if let Some(frame) = session.handle_generation(message) {
+                    open = send(&mut socket, &frame).await;
+                }
+            }
+            _ = shutdown.changed() => {
+                let _ = socket
+                    .send(Message::Close(Some(CloseFrame {
+                        code: close_code::AWAY,
+                        reason: "服务器正在关闭".into(),
+                    })))
+                    .await;
+                open = false;
+            }
+        }
+    }
+
+    info!("WebSocket 对话会话 {} 已关闭", session.session_id);
+    session.close();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// 在随机端口上启动只有对话接口的服务器，返回监听地址和保持连接的关闭信号
+    async fn serve(allowed_origins: &[&str]) -> (std::net::SocketAddr, watch::Sender<bool>) {
+        let (close, shutdown) = watch::channel(false);
+        let allowed_origins = allowed_origins.iter().map(|o| o.to_string()).collect();
+        let app = routes(shutdown, allowed_origins).with_state(ApiState::for_test());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (address, close)
+    }
+
+    /// 发送 WebSocket 握手请求，返回响应的状态码
+    async fn handshake(address: std::net::SocketAddr, origin: Option<&str>) -> u16 {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let origin = origin
+            .map(|origin| format!("Origin: {}\r\n", origin))
+            .unwrap_or_default();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+             Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n{}\r\n",
+            CHAT_ROUTE, address, origin
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !response.windows(2).any(|w| w == b"\r\n") {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "连接在返回状态行前关闭");
+            response.extend_from_slice(&buf[..n]);
+        }
+        // 状态行形如 "HTTP/1.1 101 Switching Protocols"
+        String::from_utf8_lossy(&response)
+            .split(' ')
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_origin_checked_before_upgrade() {
+        let (address, _close) = serve(&["http://allowed.example"]).await;
+
+        assert_eq!(
+            handshake(address, Some("http://allowed.example")).await,
+            101
+        );
+        assert_eq!(handshake(address, Some("http://evil.example")).await, 403);
+        // 非浏览器客户端不发送 Origin
+        assert_eq!(handshake(address, None).await, 101);
+    }
+
+    #[tokio::test]
+    async fn test_origin_wildcard_and_empty_list() {
+        let (address, _close) = serve(&["*"]).await;
+        assert_eq!(handshake(address, Some("http://any.example")).await, 101);
+
+        // 未配置允许的来源时拒绝所有浏览器发起的连接
+        let (address, _close) = serve(&[]).await;
+        assert_eq!(handshake(address, Some("http://any.example")).await, 403);
+        assert_eq!(handshake(address, None).await, 101);
+    }
+}
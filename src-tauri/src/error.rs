@@ -0,0 +1,302 @@
+//! Tauri 命令与 HTTP 接口共用的错误类型
+//!
+//! [`ApiError`] 带有稳定的错误码 [`ErrorCode`]，前端和脚本按 `code` 区分错误，`message` 只用于展示。
+//! Tauri 命令以 `{ code, message }` 作为 reject 的值，HTTP 接口返回对应的状态码和
+//! `{ "error": { code, message } }`；OpenAI 与 Ollama 兼容接口保持各自的错误格式，但使用相同的错误码。
+
+use crate::queue::QueueFull;
+use crate::worker::JobError;
+use ai_base::InferenceError;
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// 错误码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// 请求参数无效
+    InvalidRequest,
+    /// 缺少或无效的 API key
+    Unauthorized,
+    /// 请求来源不在允许的列表中，例如 WebSocket 的 Origin
+    Forbidden,
+    /// 指定的模型没有加载
+    ModelNotLoaded,
+    /// 模型文件或模型名称不存在
+    ModelNotFound,
+    /// 模型没有可用的 tokenizer
+    TokenizerMissing,
+    /// 提示词超过模型的最大序列长度
+    ContextOverflow,
+    /// 相同 request_id 的请求正在进行中
+    RequestInProgress,
+    /// 请求队列已满
+    QueueFull,
+    /// 该类型的模型尚不支持此操作，例如 Qwen3VL 的文本生成
+    NotImplemented,
+    /// 其他内部错误，例如模型加载或推理失败
+    InternalError,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::ModelNotLoaded => "model_not_loaded",
+            ErrorCode::ModelNotFound => "model_not_found",
+            ErrorCode::TokenizerMissing => "tokenizer_missing",
+            ErrorCode::ContextOverflow => "context_overflow",
+            ErrorCode::RequestInProgress => "request_in_progress",
+            ErrorCode::QueueFull => "queue_full",
+            ErrorCode::NotImplemented => "not_implemented",
+            ErrorCode::InternalError => "internal_error",
+        }
+    }
+
+    /// 全部错误码，用于生成 OpenAPI 文档
+    pub const ALL: [ErrorCode; 11] = [
+        ErrorCode::InvalidRequest,
+        ErrorCode::Unauthorized,
+        ErrorCode::Forbidden,
+        ErrorCode::ModelNotLoaded,
+        ErrorCode::ModelNotFound,
+        ErrorCode::TokenizerMissing,
+        ErrorCode::ContextOverflow,
+        ErrorCode::RequestInProgress,
+        ErrorCode::QueueFull,
+        ErrorCode::NotImplemented,
+        ErrorCode::InternalError,
+    ];
+
+    /// 对应的 HTTP 状态码
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest
+            | ErrorCode::TokenizerMissing
+            | ErrorCode::ContextOverflow => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::ModelNotLoaded | ErrorCode::ModelNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::RequestInProgress => StatusCode::CONFLICT,
+            ErrorCode::QueueFull => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::NotImplemented => StatusCode::NOT_IMPLEMENTED,
+            ErrorCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// 带错误码的错误
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// 队列已满时建议客户端等待的秒数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            retry_after_secs: None,
+        }
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidRequest, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InternalError, message)
+    }
+
+    pub fn model_not_loaded(model_id: &str) -> Self {
+        Self::new(
+            ErrorCode::ModelNotLoaded,
+            format!("模型 {} 未加载", model_id),
+        )
+    }
+
+    /// 在错误信息前加上说明，错误码不变
+    pub fn context(mut self, context: impl fmt::Display) -> Self {
+        self.message = format!("{}: {}", context, self.message);
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.code.status()
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<QueueFull> for ApiError {
+    fn from(full: QueueFull) -> Self {
+        Self {
+            code: ErrorCode::QueueFull,
+            message: full.to_string(),
+            retry_after_secs: Some(full.retry_after_secs),
+        }
+    }
+}
+
+impl From<InferenceError> for ApiError {
+    fn from(error: InferenceError) -> Self {
+        let code = match error {
+            InferenceError::TokenizerMissing { .. } => ErrorCode::TokenizerMissing,
+            InferenceError::SessionUnsupported { .. } => ErrorCode::InvalidRequest,
+            InferenceError::ContextOverflow { .. } => ErrorCode::ContextOverflow,
+        };
+        Self::new(code, error.to_string())
+    }
+}
+
+/// 请求体不是合法的 JSON 或缺少字段
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::invalid_request(rejection.body_text())
+    }
+}
+
+impl From<JobError> for ApiError {
+    fn from(error: JobError) -> Self {
+        Self::internal(error.to_string())
+    }
+}
+
+/// 按错误链中最先出现的 [`ApiError`]、[`InferenceError`] 或 [`QueueFull`] 确定错误码，
+/// 都没有时为 `internal_error`；错误信息包含完整的错误链
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        let classified = error.chain().find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<ApiError>() {
+                Some(e.clone())
+            } else if let Some(e) = cause.downcast_ref::<InferenceError>() {
+                Some(ApiError::from(e.clone()))
+            } else {
+                cause
+                    .downcast_ref::<QueueFull>()
+                    .map(|e| ApiError::from(*e))
+            }
+        });
+        let mut api_error = classified.unwrap_or_else(|| ApiError::internal(String::new()));
+        api_error.message = format!("{:#}", error);
+        api_error
+    }
+}
+
+/// 响应体为 `{ "error": { code, message } }`，队列已满时带有 `Retry-After` 头
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let retry_after = self.retry_after_secs;
+        let mut response = (status, Json(serde_json::json!({ "error": self }))).into_response();
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+/// 解析失败时返回 `invalid_request` 错误的 JSON 请求体
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use serde_json::{json, Value};
+
+    /// 把错误转换为 HTTP 响应，返回状态码、Retry-After 头和 JSON 响应体
+    async fn respond(error: ApiError) -> (StatusCode, Option<String>, Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, retry_after, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_error_code_status_mapping() {
+        let expected = [
+            (ErrorCode::InvalidRequest, "invalid_request", 400),
+            (ErrorCode::Unauthorized, "unauthorized", 401),
+            (ErrorCode::Forbidden, "forbidden", 403),
+            (ErrorCode::ModelNotLoaded, "model_not_loaded", 404),
+            (ErrorCode::ModelNotFound, "model_not_found", 404),
+            (ErrorCode::TokenizerMissing, "tokenizer_missing", 400),
+            (ErrorCode::ContextOverflow, "context_overflow", 400),
+            (ErrorCode::RequestInProgress, "request_in_progress", 409),
+            (ErrorCode::QueueFull, "queue_full", 429),
+            (ErrorCode::NotImplemented, "not_implemented", 501),
+            (ErrorCode::InternalError, "internal_error", 500),
+        ];
+        assert_eq!(
+            ErrorCode::ALL.to_vec(),
+            expected
+                .iter()
+                .map(|(code, _, _)| *code)
+                .collect::<Vec<_>>()
+        );
+        for (code, name, status) in expected {
+            assert_eq!(code.as_str(), name);
+            assert_eq!(code.status().as_u16(), status, "{}", name);
+            // 序列化的值与 as_str 一致
+            assert_eq!(serde_json::to_value(code).unwrap(), json!(name));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_response_shape() {
+        let (status, retry_after, body) = respond(ApiError::model_not_loaded("m")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(retry_after, None);
+        assert_eq!(
+            body,
+            json!({ "error": { "code": "model_not_loaded", "message": "模型 m 未加载" } })
+        );
+
+        let full = ApiError::from(QueueFull {
+            retry_after_secs: 7,
+        });
+        let (status, retry_after, body) = respond(full).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(retry_after.as_deref(), Some("7"));
+        assert_eq!(body["error"]["code"], "queue_full");
+        assert_eq!(body["error"]["retry_after_secs"], 7);
+    }
+
+    #[test]
+    fn test_anyhow_error_keeps_code_and_chain() {
+        let error = anyhow::Error::new(ApiError::model_not_loaded("m")).context("推理失败");
+        let api_error = ApiError::from(error);
+        assert_eq!(api_error.code, ErrorCode::ModelNotLoaded);
+        assert_eq!(api_error.message, "推理失败: 模型 m 未加载");
+
+        let api_error = ApiError::from(anyhow::anyhow!("其他错误"));
+        assert_eq!(api_error.code, ErrorCode::InternalError);
+        assert_eq!(api_error.message, "其他错误");
+    }
+}
@@ -1,40 +1,50 @@
+use crate::error::{ApiError, ErrorCode};
+use crate::metrics::InferenceMetrics;
+use crate::registry::{
+    LoadedEngine, LoadedModel, ModelKind, ModelRegistry, DEFAULT_GGUF_MODEL_ID,
+    DEFAULT_SAFETENSORS_MODEL_ID,
+};
+use ai_base::models::qwen3vl::{Qwen3VLConfig, Qwen3VLInferenceEngine};
 use ai_base::{
-    GGUFConfig, GGUFInferenceEngine, ImagePreprocessConfig, InferenceConfig, InferenceEngine,
+    CancellationToken, ChatMessage, GGUFConfig, GGUFFileInfo, GGUFInferenceEngine,
+    GenerationOutput, GenerationParams, ImagePreprocessConfig, InferenceConfig, InferenceEngine,
+    LoadProgress, ProgressCallback, SafetensorsFiles, SamplingParams, TokenEvent,
 };
 use anyhow::{Context, Result};
+use candle_core::{DType, Device};
 use candle_transformers::models::llama::Config;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-/// 推理服务状态
+/// Safetensors 模型推理服务（Llama 与 Qwen3VL），模型保存在共享的注册表中
 pub struct InferenceService {
-    engine: Arc<Mutex<Option<InferenceEngine>>>,
+    registry: Arc<ModelRegistry>,
+    metrics: Arc<InferenceMetrics>,
 }
 
 impl InferenceService {
-    pub fn new() -> Self {
-        Self {
-            engine: Arc::new(Mutex::new(None)),
-        }
+    pub fn new(registry: Arc<ModelRegistry>, metrics: Arc<InferenceMetrics>) -> Self {
+        Self { registry, metrics }
     }
 
-    /// 初始化模型
+    /// 初始化模型并以 `model_id` 登记（未指定时使用默认 id），返回模型 id
+    ///
+    /// `dtype` 为 None 时使用模型 config.json 中声明的精度，加载进度通过 `on_progress` 报告
+    #[allow(clippy::too_many_arguments)]
     pub fn init_model(
         &self,
+        model_id: Option<String>,
+        kind: ModelKind,
         model_path: PathBuf,
         tokenizer_path: PathBuf,
         model_config: Config,
-    ) -> Result<()> {
-        // 验证文件是否存在
-        if !model_path.exists() {
-            return Err(anyhow::anyhow!("模型文件不存在: {:?}", model_path));
-        }
-        if !tokenizer_path.exists() {
-            return Err(anyhow::anyhow!(
-                "Tokenizer 文件不存在: {:?}",
-                tokenizer_path
-            ));
-        }
+        dtype: Option<DType>,
+        on_progress: ProgressCallback<'_>,
+    ) -> Result<String> {
+        ensure_model_files(&model_path, &tokenizer_path)?;
 
         // 为 Qwen3-VL 配置图像预处理
         let image_preprocess_config = ImagePreprocessConfig {
@@ -51,164 +61,349 @@ impl InferenceService {
             temperature: 0.7,
             top_p: 0.9,
             top_k: 50,
+            seed: None,
+            dtype,
             image_preprocess_config: Some(image_preprocess_config),
         };
 
+        // 按加载时实际使用的精度估算权重大小
+        let estimated_bytes = SafetensorsFiles::from_path(&model_path)?
+            .estimated_bytes(ai_base::weights::resolve_dtype(dtype, &model_path)?)?;
+
         println!("正在加载模型: {:?}", model_path);
-        let engine = InferenceEngine::new(config, model_config)
-            .with_context(|| format!("加载模型失败: {:?}", model_path))?;
+        let model_id = model_id.unwrap_or_else(|| DEFAULT_SAFETENSORS_MODEL_ID.to_string());
+        let started = Instant::now();
+        let (engine, memory) =
+            self.registry
+                .load_within_budget(&model_id, estimated_bytes, || {
+                    InferenceEngine::new_with_progress(config, model_config, None, on_progress)
+                        .with_context(|| format!("加载模型失败: {:?}", model_path))
+                })?;
 
         println!("模型加载成功");
-        let mut guard = self.engine.lock().unwrap();
-        *guard = Some(engine);
+        self.metrics.record_load(&model_id, kind, started.elapsed());
+        self.registry.insert(
+            model_id.clone(),
+            kind,
+            model_path.display().to_string(),
+            memory,
+            LoadedEngine::Safetensors(Box::new(engine)),
+        );
 
-        Ok(())
+        Ok(model_id)
     }
 
-    /// 执行推理
-    pub fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String> {
-        let guard = self.engine.lock().unwrap();
-        let engine = guard
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("模型未初始化，请先调用 init_model"))?;
+    /// 加载 Qwen3-VL 模型并以 `model_id` 登记（未指定时使用默认 id），返回模型 id
+    ///
+    /// `dtype` 为 None 时使用 `text_config.dtype` 声明的精度。
+    /// Qwen3-VL 引擎不报告逐个张量的进度，`on_progress` 只收到开始和完成事件
+    pub fn init_qwen3vl_model(
+        &self,
+        model_id: Option<String>,
+        model_path: PathBuf,
+        tokenizer_path: PathBuf,
+        config: Qwen3VLConfig,
+        dtype: Option<DType>,
+        on_progress: ProgressCallback<'_>,
+    ) -> Result<String> {
+        ensure_model_files(&model_path, &tokenizer_path)?;
+
+        let dtype = match dtype {
+            Some(dtype) => dtype,
+            None => ai_base::weights::parse_dtype(&config.text_config.dtype)?,
+        };
+        let files = SafetensorsFiles::from_path(&model_path)?;
+        let estimated_bytes = files.estimated_bytes(dtype)?;
+
+        let model_id = model_id.unwrap_or_else(|| DEFAULT_SAFETENSORS_MODEL_ID.to_string());
+        let started = Instant::now();
+        let (engine, memory) =
+            self.registry
+                .load_within_budget(&model_id, estimated_bytes, || {
+                    on_progress(LoadProgress::Open {
+                        path: model_path.clone(),
+                        file_bytes: files.file_bytes(),
+                    });
+                    let engine = Qwen3VLInferenceEngine::new_with_dtype(
+                        &model_path,
+                        &tokenizer_path,
+                        config,
+                        Device::Cpu,
+                        dtype,
+                    )
+                    .with_context(|| format!("加载 Qwen3VL 模型失败: {:?}", model_path))?;
+                    on_progress(LoadProgress::Done);
+                    Ok(engine)
+                })?;
+
+        self.metrics
+            .record_load(&model_id, ModelKind::Qwen3vl, started.elapsed());
+        self.registry.insert(
+            model_id.clone(),
+            ModelKind::Qwen3vl,
+            model_path.display().to_string(),
+            memory,
+            LoadedEngine::Qwen3vl(Box::new(engine)),
+        );
+
+        Ok(model_id)
+    }
 
-        engine.generate(prompt, max_tokens)
+    /// 在 `model_id` 指定（未指定时为默认）的 Safetensors 或 Qwen3VL 模型上执行操作
+    fn with_engine<T>(
+        &self,
+        model_id: Option<&str>,
+        f: impl FnOnce(&LoadedEngine) -> Result<T>,
+    ) -> Result<T> {
+        let model = find_model(&self.registry, model_id, DEFAULT_SAFETENSORS_MODEL_ID).ok_or_else(
+            || {
+                ApiError::new(
+                    ErrorCode::ModelNotLoaded,
+                    "模型未初始化，请先调用 init_model",
+                )
+            },
+        )?;
+        let guard = model.lock();
+        if let LoadedEngine::Gguf(_) = &*guard {
+            return Err(ApiError::invalid_request(format!(
+                "模型 {} 不是 Safetensors 模型",
+                model.id
+            ))
+            .into());
+        }
+        f(&guard)
     }
 
-    /// 检查模型是否已加载
-    pub fn is_loaded(&self) -> bool {
-        let guard = self.engine.lock().unwrap();
-        guard.is_some()
+    /// 执行推理，`params` 中未设置的采样参数使用加载模型时的默认值
+    pub fn generate(
+        &self,
+        model_id: Option<&str>,
+        prompt: &str,
+        max_tokens: usize,
+        params: &GenerationParams,
+    ) -> Result<String> {
+        self.with_engine(model_id, |engine| {
+            let engine = match engine {
+                LoadedEngine::Qwen3vl(_) => return Err(qwen3vl_not_implemented().into()),
+                LoadedEngine::Safetensors(engine) => engine,
+                LoadedEngine::Gguf(_) => unreachable!("with_engine 不会传入 GGUF 模型"),
+            };
+            let started = Instant::now();
+            let mut first_token = None;
+            let output = engine.generate_stream(
+                prompt,
+                max_tokens,
+                params,
+                &CancellationToken::new(),
+                |_| {
+                    first_token.get_or_insert_with(Instant::now);
+                    ControlFlow::Continue(())
+                },
+            )?;
+            self.metrics.record_generation(
+                model_id.unwrap_or(DEFAULT_SAFETENSORS_MODEL_ID),
+                &output,
+                first_token.map(|first| first - started),
+                first_token.map(|first| first.elapsed()).unwrap_or_default(),
+            );
+            Ok(output.text)
+        })
+    }
+
+    /// 检查模型是否已加载（不需要等待模型锁）
+    pub fn is_loaded(&self, model_id: Option<&str>) -> bool {
+        find_model(&self.registry, model_id, DEFAULT_SAFETENSORS_MODEL_ID)
+            .is_some_and(|model| model.kind != ModelKind::Gguf)
     }
 
     /// 多模态生成（图像 + 文本）
     pub fn generate_multimodal(
         &self,
+        model_id: Option<&str>,
         image_path: PathBuf,
         prompt: &str,
         max_tokens: usize,
     ) -> Result<String> {
-        let guard = self.engine.lock().unwrap();
-        let engine = guard
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("模型未初始化，请先调用 init_model"))?;
-
-        engine.generate_multimodal(image_path, prompt, max_tokens)
+        self.with_engine(model_id, |engine| match engine {
+            LoadedEngine::Qwen3vl(_) => Err(qwen3vl_not_implemented().into()),
+            LoadedEngine::Safetensors(engine) => {
+                engine.generate_multimodal(image_path, prompt, max_tokens)
+            }
+            LoadedEngine::Gguf(_) => unreachable!("with_engine 不会传入 GGUF 模型"),
+        })
     }
 
     /// 多模态生成（从图像字节数据）
     pub fn generate_multimodal_from_bytes(
         &self,
+        model_id: Option<&str>,
         image_data: &[u8],
         prompt: &str,
         max_tokens: usize,
     ) -> Result<String> {
-        let guard = self.engine.lock().unwrap();
-        let engine = guard
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("模型未初始化，请先调用 init_model"))?;
-
-        engine.generate_multimodal_from_bytes(image_data, prompt, max_tokens)
+        self.with_engine(model_id, |engine| match engine {
+            LoadedEngine::Qwen3vl(_) => Err(qwen3vl_not_implemented().into()),
+            LoadedEngine::Safetensors(engine) => {
+                engine.generate_multimodal_from_bytes(image_data, prompt, max_tokens)
+            }
+            LoadedEngine::Gguf(_) => unreachable!("with_engine 不会传入 GGUF 模型"),
+        })
     }
 }
 
-impl Default for InferenceService {
-    fn default() -> Self {
-        Self::new()
+/// Qwen3VL 引擎的语言模型部分尚未实现，生成请求返回明确的错误而不是占位文本
+fn qwen3vl_not_implemented() -> ApiError {
+    ApiError::new(
+        ErrorCode::NotImplemented,
+        "Qwen3VL 模型的文本生成尚未实现，请使用 GGUF 或 Safetensors Llama 模型",
+    )
+}
+
+/// 验证模型与 tokenizer 文件是否存在
+fn ensure_model_files(model_path: &Path, tokenizer_path: &Path) -> Result<()> {
+    if !model_path.exists() {
+        return Err(ApiError::new(
+            ErrorCode::ModelNotFound,
+            format!("模型文件不存在: {:?}", model_path),
+        )
+        .into());
+    }
+    if !tokenizer_path.exists() {
+        return Err(ApiError::new(
+            ErrorCode::TokenizerMissing,
+            format!("Tokenizer 文件不存在: {:?}", tokenizer_path),
+        )
+        .into());
     }
+    Ok(())
 }
 
-/// 从模型目录自动检测并创建配置
+/// 构建 GGUF 模型配置
 ///
-/// 尝试从模型目录读取 config.json 并创建配置
-/// 注意：由于 Config 结构体比较复杂，建议直接使用模型目录的 config.json
-/// 如果无法从文件加载，此函数会返回错误，提示用户需要手动提供配置
-pub fn create_qwen3vl_config_from_dir(model_dir: &PathBuf) -> Result<Config> {
-    let config_path = model_dir.join("config.json");
-
-    if config_path.exists() {
-        println!("找到配置文件: {:?}", config_path);
-        // 注意：candle-transformers 的 Config 可能不直接支持从 JSON 反序列化
-        // 这里返回错误，提示用户需要手动构建 Config 或使用其他方法
-        return Err(anyhow::anyhow!(
-            "Config 结构体不支持直接从 JSON 反序列化。\
-            请参考 candle-transformers 文档手动构建 Config，或使用其他配置加载方法。\
-            配置文件路径: {:?}",
-            config_path
-        ));
+/// 上下文长度取自 GGUF 头部的 `<architecture>.context_length`（缺失时沿用 [`GGUFConfig`] 的默认值），
+/// 采样默认值统一来自 [`GenerationParams::default`]
+fn gguf_config(
+    model_path: PathBuf,
+    tokenizer_path: Option<PathBuf>,
+    architecture: Option<String>,
+) -> GGUFConfig {
+    let defaults = GGUFConfig::default();
+    let max_seq_len = GGUFFileInfo::from_file(&model_path)
+        .ok()
+        .and_then(|info| info.context_length)
+        .map_or(defaults.max_seq_len, |len| len as usize);
+    let sampling = GenerationParams::default().sampling_params(SamplingParams::default());
+    GGUFConfig {
+        model_path,
+        tokenizer_path,
+        max_seq_len,
+        temperature: sampling.temperature,
+        top_p: sampling.top_p,
+        top_k: sampling.top_k,
+        seed: sampling.seed,
+        architecture,
+        ..defaults
     }
+}
 
-    Err(anyhow::anyhow!(
-        "未找到配置文件 config.json: {:?}。\
-        请确保模型目录包含配置文件，或手动提供 Config 结构",
-        model_dir
-    ))
+/// 按 `model_id` 查找模型，未指定时使用该服务的默认 id
+fn find_model(
+    registry: &ModelRegistry,
+    model_id: Option<&str>,
+    default_id: &str,
+) -> Option<Arc<LoadedModel>> {
+    registry.get(model_id.unwrap_or(default_id))
 }
 
-/// 创建 qwen3vl-8b 的默认配置
-///
-/// 注意：这个方法需要手动构建完整的 Config 结构体。
-/// 由于 Config 包含很多字段且可能因版本而异，
-/// 建议从模型的 config.json 文件手动读取字段并构建 Config。
-///
-/// 这是一个占位符实现，实际使用时应该：
-/// 1. 从 config.json 读取各个字段的值
-/// 2. 使用这些值构建 Config 结构体
-pub fn create_qwen3vl_config() -> Config {
-    // TODO: 实现从 config.json 加载配置的逻辑
-    // 由于 Config 结构体的字段较多且可能因版本变化，这里暂时 panic
-    // 用户需要根据实际的 candle-transformers 版本和模型配置手动构建
-    panic!(
-        "create_qwen3vl_config 需要手动实现。\
-        请从模型的 config.json 文件读取配置并手动构建 Config 结构体，\
-        或使用其他配置加载方法。\
-        参考: https://github.com/huggingface/candle/tree/main/candle-transformers"
-    )
+/// 正在进行的生成请求，离开作用域时自动注销
+struct ActiveRequest<'a> {
+    requests: &'a Mutex<HashMap<String, CancellationToken>>,
+    request_id: String,
+    cancel: CancellationToken,
+}
+
+impl Drop for ActiveRequest<'_> {
+    fn drop(&mut self) {
+        self.requests.lock().unwrap().remove(&self.request_id);
+    }
 }
 
-/// GGUF 模型推理服务
+/// GGUF 模型推理服务，模型保存在共享的注册表中
 pub struct GGUFInferenceService {
-    engine: Arc<Mutex<Option<GGUFInferenceEngine>>>,
+    registry: Arc<ModelRegistry>,
+    metrics: Arc<InferenceMetrics>,
+    /// 正在进行的生成请求（request_id -> 取消令牌）
+    active_requests: Arc<Mutex<HashMap<String, CancellationToken>>>,
 }
 
 impl GGUFInferenceService {
-    pub fn new() -> Self {
+    pub fn new(registry: Arc<ModelRegistry>, metrics: Arc<InferenceMetrics>) -> Self {
         Self {
-            engine: Arc::new(Mutex::new(None)),
+            registry,
+            metrics,
+            active_requests: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 以 request_id 登记一次生成，重复的 request_id 会被拒绝
+    fn register_request(&self, request_id: &str) -> Result<ActiveRequest<'_>> {
+        let mut requests = self.active_requests.lock().unwrap();
+        if requests.contains_key(request_id) {
+            return Err(ApiError::new(
+                ErrorCode::RequestInProgress,
+                format!("请求 {} 正在进行中", request_id),
+            )
+            .into());
+        }
+        let cancel = CancellationToken::new();
+        requests.insert(request_id.to_string(), cancel.clone());
+        Ok(ActiveRequest {
+            requests: &self.active_requests,
+            request_id: request_id.to_string(),
+            cancel,
+        })
+    }
+
+    /// 取消正在进行（或正在等待模型锁）的生成请求，返回是否找到该请求
+    pub fn cancel(&self, request_id: &str) -> bool {
+        match self.active_requests.lock().unwrap().get(request_id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
         }
     }
 
-    /// 从本地文件初始化 GGUF 模型
+    /// 从本地文件初始化 GGUF 模型并以 `model_id` 登记（未指定时使用默认 id），返回模型 id
     pub fn init_model_from_file(
         &self,
+        model_id: Option<String>,
         model_path: PathBuf,
         tokenizer_path: Option<PathBuf>,
         architecture: Option<String>,
-    ) -> Result<()> {
+        on_progress: ProgressCallback<'_>,
+    ) -> Result<String> {
         // 验证文件是否存在
         if !model_path.exists() {
-            return Err(anyhow::anyhow!("模型文件不存在: {:?}", model_path));
+            return Err(ApiError::new(
+                ErrorCode::ModelNotFound,
+                format!("模型文件不存在: {:?}", model_path),
+            )
+            .into());
         }
 
         if let Some(ref tokenizer_path) = tokenizer_path {
             if !tokenizer_path.exists() {
-                return Err(anyhow::anyhow!(
-                    "Tokenizer 文件不存在: {:?}",
-                    tokenizer_path
-                ));
+                return Err(ApiError::new(
+                    ErrorCode::TokenizerMissing,
+                    format!("Tokenizer 文件不存在: {:?}", tokenizer_path),
+                )
+                .into());
             }
         }
 
-        let config = GGUFConfig {
-            model_path: model_path.clone(),
-            tokenizer_path: tokenizer_path.clone(),
-            max_seq_len: 2048,
-            temperature: 0.7,
-            top_p: 0.9,
-            top_k: 50,
-            architecture,
-            ..Default::default()
-        };
+        let config = gguf_config(model_path.clone(), tokenizer_path, architecture);
 
         tracing::info!("正在加载 GGUF 模型: {:?}", model_path);
         tracing::info!("模型文件是否存在: {}", model_path.exists());
@@ -218,33 +413,36 @@ impl GGUFInferenceService {
             }
         }
 
-        let engine = GGUFInferenceEngine::from_file(config)
-            .with_context(|| format!("加载 GGUF 模型失败，模型路径: {:?}", model_path))?;
-
+        let model_id = self.load(
+            model_id,
+            model_path.display().to_string(),
+            config,
+            on_progress,
+        )?;
         tracing::info!("GGUF 模型加载成功");
-        let mut guard = self.engine.lock().unwrap();
-        *guard = Some(engine);
-
-        Ok(())
+        Ok(model_id)
     }
 
-    /// 从 HuggingFace Hub 下载并初始化 GGUF 模型
+    /// 从 HuggingFace Hub 下载并初始化 GGUF 模型，返回模型 id
     pub fn init_model_from_hf_hub(
         &self,
+        model_id: Option<String>,
         hf_repo: impl Into<String>,
         hf_filename: impl Into<String>,
         tokenizer_path: Option<PathBuf>,
         architecture: Option<String>,
-    ) -> Result<()> {
+        on_progress: ProgressCallback<'_>,
+    ) -> Result<String> {
         let hf_repo_str = hf_repo.into();
         let hf_filename_str = hf_filename.into();
 
         if let Some(ref tokenizer_path) = tokenizer_path {
             if !tokenizer_path.exists() {
-                return Err(anyhow::anyhow!(
-                    "Tokenizer 文件不存在: {:?}",
-                    tokenizer_path
-                ));
+                return Err(ApiError::new(
+                    ErrorCode::TokenizerMissing,
+                    format!("Tokenizer 文件不存在: {:?}", tokenizer_path),
+                )
+                .into());
             }
         }
 
@@ -252,55 +450,193 @@ impl GGUFInferenceService {
             "正在从 HuggingFace Hub 下载 GGUF 模型: {}/{}",
             hf_repo_str, hf_filename_str
         );
-        let engine = GGUFInferenceEngine::from_hf_hub(
-            hf_repo_str.clone(),
-            hf_filename_str.clone(),
-            tokenizer_path,
-            architecture,
-        )
-        .with_context(|| {
-            format!(
-                "从 HuggingFace Hub 加载 GGUF 模型失败: {}/{}",
-                hf_repo_str, hf_filename_str
-            )
-        })?;
+        let source = format!("{}/{}", hf_repo_str, hf_filename_str);
+        let model_path = GGUFInferenceEngine::download_from_hf_hub(&hf_repo_str, &hf_filename_str)?;
+        let config = GGUFConfig {
+            hf_repo: Some(hf_repo_str),
+            hf_filename: Some(hf_filename_str),
+            ..gguf_config(model_path, tokenizer_path, architecture)
+        };
 
+        let model_id = self
+            .load(model_id, source.clone(), config, on_progress)
+            .with_context(|| format!("从 HuggingFace Hub 加载 GGUF 模型失败: {}", source))?;
         println!("GGUF 模型下载并加载成功");
-        let mut guard = self.engine.lock().unwrap();
-        *guard = Some(engine);
+        Ok(model_id)
+    }
 
-        Ok(())
+    /// 在内存预算内加载 GGUF 模型并登记到注册表，返回模型 id
+    fn load(
+        &self,
+        model_id: Option<String>,
+        source: String,
+        config: GGUFConfig,
+        on_progress: ProgressCallback<'_>,
+    ) -> Result<String> {
+        let model_id = model_id.unwrap_or_else(|| DEFAULT_GGUF_MODEL_ID.to_string());
+        let model_path = config.model_path.clone();
+        let estimated_bytes = ai_base::memory::estimate_gguf_file(&model_path)?;
+
+        let started = Instant::now();
+        let (engine, memory) =
+            self.registry
+                .load_within_budget(&model_id, estimated_bytes, || {
+                    GGUFInferenceEngine::from_file_with_progress(config, None, on_progress)
+                        .with_context(|| format!("加载 GGUF 模型失败，模型路径: {:?}", model_path))
+                })?;
+        self.metrics
+            .record_load(&model_id, ModelKind::Gguf, started.elapsed());
+        self.registry.insert(
+            model_id.clone(),
+            ModelKind::Gguf,
+            source,
+            memory,
+            LoadedEngine::Gguf(Box::new(engine)),
+        );
+        Ok(model_id)
     }
 
-    /// 执行推理
-    pub fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String> {
-        let mut guard = self.engine.lock().unwrap();
-        let engine = guard.as_mut().ok_or_else(|| {
-            anyhow::anyhow!("模型未初始化，请先调用 init_model_from_file 或 init_model_from_hf_hub")
-        })?;
+    /// 执行推理，`params` 中未设置的采样参数使用加载模型时的默认值
+    pub fn generate(
+        &self,
+        model_id: Option<&str>,
+        request_id: &str,
+        prompt: &str,
+        max_tokens: usize,
+        params: &GenerationParams,
+    ) -> Result<GenerationOutput> {
+        self.generate_stream(model_id, request_id, prompt, max_tokens, params, |_| {
+            ControlFlow::Continue(())
+        })
+    }
 
-        engine.generate(prompt, max_tokens)
+    /// 流式推理，每生成一个 token 调用一次 `on_token`
+    ///
+    /// 生成期间以 `request_id` 登记，可通过 [`Self::cancel`] 中止，中止时返回已生成的部分文本。
+    pub fn generate_stream<F>(
+        &self,
+        model_id: Option<&str>,
+        request_id: &str,
+        prompt: &str,
+        max_tokens: usize,
+        params: &GenerationParams,
+        on_token: F,
+    ) -> Result<GenerationOutput>
+    where
+        F: FnMut(TokenEvent) -> ControlFlow<()>,
+    {
+        self.with_generation(
+            model_id,
+            request_id,
+            on_token,
+            |engine, cancel, on_token| {
+                engine.generate_stream(prompt, max_tokens, params, cancel, on_token)
+            },
+        )
     }
 
-    /// 检查模型是否已加载
-    pub fn is_loaded(&self) -> bool {
-        let guard = self.engine.lock().unwrap();
-        guard.is_some()
+    /// 按模型的对话模板渲染消息并生成助手回复，每生成一个 token 调用一次 `on_token`
+    pub fn chat_stream<F>(
+        &self,
+        model_id: Option<&str>,
+        request_id: &str,
+        messages: &[ChatMessage],
+        max_tokens: usize,
+        params: &GenerationParams,
+        on_token: F,
+    ) -> Result<GenerationOutput>
+    where
+        F: FnMut(TokenEvent) -> ControlFlow<()>,
+    {
+        self.with_generation(
+            model_id,
+            request_id,
+            on_token,
+            |engine, cancel, on_token| {
+                engine.chat_stream(messages, max_tokens, params, cancel, on_token)
+            },
+        )
     }
 
-    /// 测试模型前向传播
-    pub fn test_forward(&self, seq_len: usize) -> Result<()> {
-        let mut guard = self.engine.lock().unwrap();
-        let engine = guard.as_mut().ok_or_else(|| {
-            anyhow::anyhow!("模型未初始化，请先调用 init_model_from_file 或 init_model_from_hf_hub")
+    /// 执行一次生成，并记录首 token 延迟、生成速度和 token 数
+    ///
+    /// 计时从拿到模型锁开始，不包含排队等待的时间。
+    fn with_generation<F>(
+        &self,
+        model_id: Option<&str>,
+        request_id: &str,
+        mut on_token: F,
+        f: impl FnOnce(
+            &mut GGUFInferenceEngine,
+            &CancellationToken,
+            &mut dyn FnMut(TokenEvent) -> ControlFlow<()>,
+        ) -> Result<GenerationOutput>,
+    ) -> Result<GenerationOutput>
+    where
+        F: FnMut(TokenEvent) -> ControlFlow<()>,
+    {
+        self.with_engine(model_id, request_id, |engine, cancel| {
+            let started = Instant::now();
+            let mut first_token = None;
+            let output = f(engine, cancel, &mut |event| {
+                first_token.get_or_insert_with(Instant::now);
+                on_token(event)
+            })?;
+            self.metrics.record_generation(
+                model_id.unwrap_or(DEFAULT_GGUF_MODEL_ID),
+                &output,
+                first_token.map(|first| first - started),
+                first_token.map(|first| first.elapsed()).unwrap_or_default(),
+            );
+            Ok(output)
+        })
+    }
+
+    /// 以 `request_id` 登记请求后在 `model_id` 指定（未指定时为默认）的模型上执行生成
+    fn with_engine<T>(
+        &self,
+        model_id: Option<&str>,
+        request_id: &str,
+        f: impl FnOnce(&mut GGUFInferenceEngine, &CancellationToken) -> Result<T>,
+    ) -> Result<T> {
+        let active = self.register_request(request_id)?;
+        self.with_model(model_id, |engine| f(engine, &active.cancel))
+    }
+
+    fn with_model<T>(
+        &self,
+        model_id: Option<&str>,
+        f: impl FnOnce(&mut GGUFInferenceEngine) -> Result<T>,
+    ) -> Result<T> {
+        let model =
+            find_model(&self.registry, model_id, DEFAULT_GGUF_MODEL_ID).ok_or_else(|| {
+                ApiError::new(
+                    ErrorCode::ModelNotLoaded,
+                    "模型未初始化，请先调用 init_model_from_file 或 init_model_from_hf_hub",
+                )
+            })?;
+        let mut guard = model.lock();
+        let engine = guard.as_gguf_mut().ok_or_else(|| {
+            ApiError::invalid_request(format!("模型 {} 不是 GGUF 模型", model.id))
         })?;
+        f(engine)
+    }
 
-        engine.test_forward(seq_len)
+    /// 检查模型是否已加载（不需要等待模型锁）
+    pub fn is_loaded(&self, model_id: Option<&str>) -> bool {
+        find_model(&self.registry, model_id, DEFAULT_GGUF_MODEL_ID)
+            .is_some_and(|model| model.kind == ModelKind::Gguf)
     }
-}
 
-impl Default for GGUFInferenceService {
-    fn default() -> Self {
-        Self::new()
+    /// 释放模型上指定会话的 KV cache，返回会话是否存在
+    ///
+    /// 需要等待模型锁，正在进行的生成结束后才会执行。
+    pub fn remove_session(&self, model_id: Option<&str>, session_id: &str) -> Result<bool> {
+        self.with_model(model_id, |engine| Ok(engine.remove_session(session_id)))
+    }
+
+    /// 测试模型前向传播
+    pub fn test_forward(&self, model_id: Option<&str>, seq_len: usize) -> Result<()> {
+        self.with_model(model_id, |engine| engine.test_forward(seq_len))
     }
 }
@@ -1,12 +1,20 @@
 mod commands;
+mod error;
 mod inference;
+mod metrics;
+mod queue;
+mod registry;
+mod worker;
 
 use commands::api::ServerHandle;
 use commands::logging::LogHandle;
 use inference::{GGUFInferenceService, InferenceService};
+use metrics::InferenceMetrics;
+use registry::ModelRegistry;
 use std::sync::{Arc, Mutex};
 use tracing::info;
 use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
+use worker::InferenceWorker;
 
 /// 初始化日志系统，返回 reload handle 以便动态更改日志级别
 /// 使用轻量级配置以加快启动速度
@@ -47,9 +55,21 @@ pub fn run() {
     // 初始化日志系统（必须在启动前完成，因为需要记录日志）
     let log_reload_handle = init_logger();
 
-    // 创建推理服务（轻量级操作，只是创建空服务）
-    let inference_service = Arc::new(InferenceService::new());
-    let gguf_inference_service = Arc::new(GGUFInferenceService::new());
+    // 创建推理服务（轻量级操作，只是创建空服务），已加载的模型保存在共享的注册表中
+    // 推理统计在 Tauri 命令与 HTTP 服务之间共享
+    let model_registry = Arc::new(ModelRegistry::new());
+    let inference_metrics = Arc::new(InferenceMetrics::new());
+    let inference_service = Arc::new(InferenceService::new(
+        model_registry.clone(),
+        inference_metrics.clone(),
+    ));
+    let gguf_inference_service = Arc::new(GGUFInferenceService::new(
+        model_registry.clone(),
+        inference_metrics.clone(),
+    ));
+
+    // 创建推理工作线程，模型加载和推理按模型 id 在各自的线程上排队执行
+    let inference_worker = Arc::new(InferenceWorker::new());
 
     // 创建日志级别管理状态
     let log_handle_state = Arc::new(Mutex::new(log_reload_handle));
@@ -61,8 +81,11 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .manage(model_registry)
+        .manage(inference_metrics)
         .manage(inference_service)
         .manage(gguf_inference_service)
+        .manage(inference_worker)
         .manage(log_handle_state)
         .manage(server_handle_state)
         .invoke_handler(tauri::generate_handler![
@@ -73,6 +96,7 @@ pub fn run() {
             // 服务器控制相关命令
             commands::api::get_server_status,
             commands::api::start_server,
+            commands::api::get_server_config,
             commands::api::stop_server,
             // Qwen3VL 相关命令
             commands::qwen3vl::init_qwen3vl_model,
@@ -84,8 +108,19 @@ pub fn run() {
             commands::gguf::init_gguf_model_from_file,
             commands::gguf::init_gguf_model_from_hub,
             commands::gguf::generate_gguf_text,
+            commands::gguf::generate_gguf_text_stream,
+            commands::gguf::chat_completion,
+            commands::gguf::cancel_generation,
+            commands::gguf::get_inference_queue,
             commands::gguf::is_gguf_model_loaded,
             commands::gguf::test_gguf_forward,
+            // 已加载模型相关命令
+            commands::registry::list_loaded_models,
+            commands::registry::unload_model,
+            commands::registry::get_model_memory,
+            commands::registry::set_memory_budget,
+            // 推理统计相关命令
+            commands::metrics::get_inference_stats,
             // 模型管理相关命令
             commands::models::get_local_models,
             commands::models::get_local_tokenizers,
@@ -0,0 +1,609 @@
+//! 推理统计
+//!
+//! 记录 HTTP 请求数（按路由和状态码）、每个模型的 token 数、首 token 延迟与生成速度的直方图、
+//! 模型加载耗时，供 `/metrics`（Prometheus 文本格式）和 `get_inference_stats` 命令使用。
+//! 内存占用在导出时从模型注册表读取。
+
+use crate::registry::{MemoryReport, ModelKind, ModelRegistry};
+use ai_base::GenerationOutput;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// 首 token 延迟的直方图分桶（秒）
+const TTFT_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+/// 生成速度的直方图分桶（token/秒）
+const TOKENS_PER_SECOND_BUCKETS: &[f64] =
+    &[1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 50.0, 75.0, 100.0, 200.0];
+/// 模型加载耗时的直方图分桶（秒）
+const LOAD_BUCKETS: &[f64] = &[0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    bounds: &'static [f64],
+    /// 每个分桶（最后一个为 +Inf）内的观测数，不累加
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let index = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[index] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// 每个上界对应的累计观测数（不含 +Inf）
+    fn cumulative(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        self.bounds
+            .iter()
+            .zip(self.counts.iter().scan(0, |total, count| {
+                *total += count;
+                Some(*total)
+            }))
+            .map(|(bound, count)| (*bound, count))
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            count: self.count,
+            sum: self.sum,
+            buckets: self
+                .cumulative()
+                .map(|(upper_bound, count)| HistogramBucket { upper_bound, count })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ModelMetrics {
+    generations: u64,
+    prompt_tokens: u64,
+    cached_tokens: u64,
+    completion_tokens: u64,
+    time_to_first_token: Histogram,
+    tokens_per_second: Histogram,
+}
+
+impl Default for ModelMetrics {
+    fn default() -> Self {
+        Self {
+            generations: 0,
+            prompt_tokens: 0,
+            cached_tokens: 0,
+            completion_tokens: 0,
+            time_to_first_token: Histogram::new(TTFT_BUCKETS),
+            tokens_per_second: Histogram::new(TOKENS_PER_SECOND_BUCKETS),
+        }
+    }
+}
+
+/// 按模型统计的计数器：指标名、说明、取值
+type ModelCounter = (&'static str, &'static str, fn(&ModelMetrics) -> u64);
+
+#[derive(Debug, Clone)]
+struct LoadMetrics {
+    kind: ModelKind,
+    duration: Histogram,
+    last_duration: Duration,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    /// (路由, 状态码) -> 请求数
+    requests: BTreeMap<(String, u16), u64>,
+    models: BTreeMap<String, ModelMetrics>,
+    loads: BTreeMap<String, LoadMetrics>,
+}
+
+/// 直方图的一个分桶：不超过 `upper_bound` 的累计观测数
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramBucket {
+    pub upper_bound: f64,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub sum: f64,
+    pub buckets: Vec<HistogramBucket>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestCount {
+    pub route: String,
+    pub status: u16,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelStats {
+    pub model_id: String,
+    pub generations: u64,
+    pub prompt_tokens: u64,
+    /// 提示词中复用 KV cache 的 token 数
+    pub cached_tokens: u64,
+    pub completion_tokens: u64,
+    /// 首 token 延迟（秒）
+    pub time_to_first_token: HistogramSnapshot,
+    /// 生成速度（token/秒）
+    pub tokens_per_second: HistogramSnapshot,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadStats {
+    pub model_id: String,
+    pub kind: ModelKind,
+    /// 加载耗时（秒）
+    pub duration: HistogramSnapshot,
+    pub last_duration_ms: u64,
+}
+
+/// 推理统计快照
+#[derive(Debug, Clone, Serialize)]
+pub struct InferenceStats {
+    pub requests: Vec<RequestCount>,
+    pub models: Vec<ModelStats>,
+    pub loads: Vec<LoadStats>,
+    pub memory: MemoryReport,
+}
+
+/// 推理统计，在 Tauri 命令与 HTTP 服务之间共享
+#[derive(Default)]
+pub struct InferenceMetrics {
+    state: Mutex<MetricsState>,
+}
+
+impl InferenceMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次 HTTP 请求
+    pub fn record_request(&self, route: &str, status: u16) {
+        let mut state = self.state.lock().unwrap();
+        *state
+            .requests
+            .entry((route.to_string(), status))
+            .or_default() += 1;
+    }
+
+    /// 记录一次生成
+    ///
+    /// `time_to_first_token` 为开始生成到产生第一个 token 的时间，没有生成 token 时为 None；
+    /// `decode` 为第一个 token 之后的解码时间，用于计算生成速度。
+    pub fn record_generation(
+        &self,
+        model_id: &str,
+        output: &GenerationOutput,
+        time_to_first_token: Option<Duration>,
+        decode: Duration,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let model = state.models.entry(model_id.to_string()).or_default();
+        model.generations += 1;
+        model.prompt_tokens += output.prompt_tokens as u64;
+        model.cached_tokens += output.cached_tokens as u64;
+        model.completion_tokens += output.completion_tokens as u64;
+        if let Some(ttft) = time_to_first_token {
+            model.time_to_first_token.observe(ttft.as_secs_f64());
+        }
+        // 第一个 token 在预填充阶段产生，速度按其余 token 计算
+        if output.completion_tokens > 1 && !decode.is_zero() {
+            let tokens = (output.completion_tokens - 1) as f64;
+            model
+                .tokens_per_second
+                .observe(tokens / decode.as_secs_f64());
+        }
+    }
+
+    /// 记录一次模型加载
+    pub fn record_load(&self, model_id: &str, kind: ModelKind, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let load = state
+            .loads
+            .entry(model_id.to_string())
+            .or_insert_with(|| LoadMetrics {
+                kind,
+                duration: Histogram::new(LOAD_BUCKETS),
+                last_duration: duration,
+            });
+        load.kind = kind;
+        load.duration.observe(duration.as_secs_f64());
+        load.last_duration = duration;
+    }
+
+    /// 统计快照
+    pub fn snapshot(&self, registry: &ModelRegistry) -> InferenceStats {
+        let state = self.state.lock().unwrap();
+        InferenceStats {
+            requests: state
+                .requests
+                .iter()
+                .map(|((route, status), count)| RequestCount {
+                    route: route.clone(),
+                    status: *status,
+                    count: *count,
+                })
+                .collect(),
+            models: state
+                .models
+                .iter()
+                .map(|(model_id, model)| ModelStats {
+                    model_id: model_id.clone(),
+                    generations: model.generations,
+                    prompt_tokens: model.prompt_tokens,
+                    cached_tokens: model.cached_tokens,
+                    completion_tokens: model.completion_tokens,
+                    time_to_first_token: model.time_to_first_token.snapshot(),
+                    tokens_per_second: model.tokens_per_second.snapshot(),
+                })
+                .collect(),
+            loads: state
+                .loads
+                .iter()
+                .map(|(model_id, load)| LoadStats {
+                    model_id: model_id.clone(),
+                    kind: load.kind,
+                    duration: load.duration.snapshot(),
+                    last_duration_ms: load.last_duration.as_millis() as u64,
+                })
+                .collect(),
+            memory: registry.memory_report(),
+        }
+    }
+
+    /// 以 Prometheus 文本格式导出
+    pub fn render_prometheus(&self, registry: &ModelRegistry) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "seekerai_http_requests_total",
+            "counter",
+            "HTTP 请求数",
+        );
+        for ((route, status), count) in &state.requests {
+            let labels = [("route", route.as_str()), ("status", &status.to_string())];
+            sample(
+                &mut out,
+                "seekerai_http_requests_total",
+                &labels,
+                *count as f64,
+            );
+        }
+
+        let counters: [ModelCounter; 4] = [
+            ("seekerai_generations_total", "生成次数", |m| {
+                m.generations
+            }),
+            ("seekerai_prompt_tokens_total", "提示词 token 数", |m| {
+                m.prompt_tokens
+            }),
+            (
+                "seekerai_cached_prompt_tokens_total",
+                "复用 KV cache 的提示词 token 数",
+                |m| m.cached_tokens,
+            ),
+            (
+                "seekerai_completion_tokens_total",
+                "生成的 token 数",
+                |m| m.completion_tokens,
+            ),
+        ];
+        for (name, help, value) in counters {
+            header(&mut out, name, "counter", help);
+            for (model_id, model) in &state.models {
+                sample(&mut out, name, &[("model", model_id)], value(model) as f64);
+            }
+        }
+
+        header(
+            &mut out,
+            "seekerai_time_to_first_token_seconds",
+            "histogram",
+            "首 token 延迟",
+        );
+        for (model_id, model) in &state.models {
+            histogram(
+                &mut out,
+                "seekerai_time_to_first_token_seconds",
+                &[("model", model_id)],
+                &model.time_to_first_token,
+            );
+        }
+        header(
+            &mut out,
+            "seekerai_tokens_per_second",
+            "histogram",
+            "生成速度（token/秒）",
+        );
+        for (model_id, model) in &state.models {
+            histogram(
+                &mut out,
+                "seekerai_tokens_per_second",
+                &[("model", model_id)],
+                &model.tokens_per_second,
+            );
+        }
+
+        header(
+            &mut out,
+            "seekerai_model_load_duration_seconds",
+            "histogram",
+            "模型加载耗时",
+        );
+        for (model_id, load) in &state.loads {
+            let kind = serde_json::to_value(load.kind)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_default();
+            histogram(
+                &mut out,
+                "seekerai_model_load_duration_seconds",
+                &[("model", model_id), ("kind", &kind)],
+                &load.duration,
+            );
+        }
+        drop(state);
+
+        let memory = registry.memory_report();
+        header(
+            &mut out,
+            "seekerai_model_memory_estimated_bytes",
+            "gauge",
+            "已加载模型的估算权重内存",
+        );
+        for model in &memory.models {
+            sample(
+                &mut out,
+                "seekerai_model_memory_estimated_bytes",
+                &[("model", &model.model_id)],
+                model.estimated_bytes as f64,
+            );
+        }
+        header(
+            &mut out,
+            "seekerai_model_memory_actual_bytes",
+            "gauge",
+            "加载模型前后进程常驻内存的差值",
+        );
+        for model in &memory.models {
+            if let Some(actual) = model.actual_bytes {
+                sample(
+                    &mut out,
+                    "seekerai_model_memory_actual_bytes",
+                    &[("model", &model.model_id)],
+                    actual as f64,
+                );
+            }
+        }
+        header(
+            &mut out,
+            "seekerai_loaded_models",
+            "gauge",
+            "已加载的模型数",
+        );
+        sample(
+            &mut out,
+            "seekerai_loaded_models",
+            &[],
+            memory.models.len() as f64,
+        );
+        if let Some(budget) = memory.budget_bytes {
+            header(
+                &mut out,
+                "seekerai_memory_budget_bytes",
+                "gauge",
+                "模型内存预算",
+            );
+            sample(&mut out, "seekerai_memory_budget_bytes", &[], budget as f64);
+        }
+        if let Some(resident) = memory.process_resident_bytes {
+            header(
+                &mut out,
+                "seekerai_process_resident_memory_bytes",
+                "gauge",
+                "进程常驻内存",
+            );
+            sample(
+                &mut out,
+                "seekerai_process_resident_memory_bytes",
+                &[],
+                resident as f64,
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+            .collect();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+fn histogram(out: &mut String, name: &str, labels: &[(&str, &str)], histogram: &Histogram) {
+    let bucket = format!("{}_bucket", name);
+    for (bound, count) in histogram.cumulative() {
+        let le = bound.to_string();
+        let mut bucket_labels = labels.to_vec();
+        bucket_labels.push(("le", &le));
+        sample(out, &bucket, &bucket_labels, count as f64);
+    }
+    let mut bucket_labels = labels.to_vec();
+    bucket_labels.push(("le", "+Inf"));
+    sample(out, &bucket, &bucket_labels, histogram.count as f64);
+    sample(out, &format!("{}_sum", name), labels, histogram.sum);
+    sample(
+        out,
+        &format!("{}_count", name),
+        labels,
+        histogram.count as f64,
+    );
+}
+
+/// 转义标签值中的反斜杠、引号和换行
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ai_base::FinishReason;
+
+    fn output(
+        prompt_tokens: usize,
+        cached_tokens: usize,
+        completion_tokens: usize,
+    ) -> GenerationOutput {
+        GenerationOutput {
+            text: String::new(),
+            finish_reason: FinishReason::Eos,
+            prompt_tokens,
+            cached_tokens,
+            completion_tokens,
+        }
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let metrics = InferenceMetrics::new();
+        let registry = ModelRegistry::new();
+        registry.set_budget(Some(1024));
+
+        metrics.record_request("/v1/chat/completions", 200);
+        metrics.record_request("/v1/chat/completions", 200);
+        metrics.record_request("/v1/chat/completions", 429);
+        // 模型 id 中的引号、反斜杠和换行需要转义
+        let model_id = "m\"1\"\\x\ny";
+        metrics.record_generation(
+            model_id,
+            &output(10, 4, 11),
+            Some(Duration::from_millis(200)),
+            Duration::from_secs(1),
+        );
+        // 只生成一个 token 时不记录生成速度
+        metrics.record_generation(
+            model_id,
+            &output(6, 0, 1),
+            Some(Duration::from_secs(2)),
+            Duration::ZERO,
+        );
+        metrics.record_load("m", ModelKind::Gguf, Duration::from_millis(1500));
+
+        let text = metrics.render_prometheus(&registry);
+        // 进程常驻内存随运行环境变化，不参与比较
+        let text = text
+            .split("# HELP seekerai_process_resident_memory_bytes")
+            .next()
+            .unwrap();
+        let expected = r#"# HELP seekerai_http_requests_total HTTP 请求数
+# TYPE seekerai_http_requests_total counter
+seekerai_http_requests_total{route="/v1/chat/completions",status="200"} 2
+seekerai_http_requests_total{route="/v1/chat/completions",status="429"} 1
+# HELP seekerai_generations_total 生成次数
+# TYPE seekerai_generations_total counter
+seekerai_generations_total{model="m\"1\"\\x\ny"} 2
+# HELP seekerai_prompt_tokens_total 提示词 token 数
+# TYPE seekerai_prompt_tokens_total counter
+seekerai_prompt_tokens_total{model="m\"1\"\\x\ny"} 16
+# HELP seekerai_cached_prompt_tokens_total 复用 KV cache 的提示词 token 数
+# TYPE seekerai_cached_prompt_tokens_total counter
+seekerai_cached_prompt_tokens_total{model="m\"1\"\\x\ny"} 4
+# HELP seekerai_completion_tokens_total 生成的 token 数
+# TYPE seekerai_completion_tokens_total counter
+seekerai_completion_tokens_total{model="m\"1\"\\x\ny"} 12
+# HELP seekerai_time_to_first_token_seconds 首 token 延迟
+# TYPE seekerai_time_to_first_token_seconds histogram
+seekerai_time_to_first_token_seconds_bucket{model="m\"1\"\\x\ny",le="0.05"} 0
+seekerai_time_to_first_token_seconds_bucket{model="m\"1\"\\x\ny",le="0.1"} 0
+seekerai_time_to_first_token_seconds_bucket{model="m\"1\"\\x\ny",le="0.25"} 1
+seekerai_time_to_first_token_seconds_bucket{model="m\"1\"\\x\ny",le="0.5"} 1
+seekerai_time_to_first_token_seconds_bucket{model="m\"1\"\\x\ny",le="1"} 1
+seekerai_time_to_first_token_seconds_bucket{model="m\"1\"\\x\ny",le="2.5"} 2
+seekerai_time_to_first_token_seconds_bucket{model="m\"1\"\\x\ny",le="5"} 2
+seekerai_time_to_first_token_seconds_bucket{model="m\"1\"\\x\ny",le="10"} 2
+seekerai_time_to_first_token_seconds_bucket{model="m\"1\"\\x\ny",le="30"} 2
+seekerai_time_to_first_token_seconds_bucket{model="m\"1\"\\x\ny",le="+Inf"} 2
+seekerai_time_to_first_token_seconds_sum{model="m\"1\"\\x\ny"} 2.2
+seekerai_time_to_first_token_seconds_count{model="m\"1\"\\x\ny"} 2
+# HELP seekerai_tokens_per_second 生成速度（token/秒）
+# TYPE seekerai_tokens_per_second histogram
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="1"} 0
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="2"} 0
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="5"} 0
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="10"} 1
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="20"} 1
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="30"} 1
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="50"} 1
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="75"} 1
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="100"} 1
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="200"} 1
+seekerai_tokens_per_second_bucket{model="m\"1\"\\x\ny",le="+Inf"} 1
+seekerai_tokens_per_second_sum{model="m\"1\"\\x\ny"} 10
+seekerai_tokens_per_second_count{model="m\"1\"\\x\ny"} 1
+# HELP seekerai_model_load_duration_seconds 模型加载耗时
+# TYPE seekerai_model_load_duration_seconds histogram
+seekerai_model_load_duration_seconds_bucket{model="m",kind="gguf",le="0.5"} 0
+seekerai_model_load_duration_seconds_bucket{model="m",kind="gguf",le="1"} 0
+seekerai_model_load_duration_seconds_bucket{model="m",kind="gguf",le="2.5"} 1
+seekerai_model_load_duration_seconds_bucket{model="m",kind="gguf",le="5"} 1
+seekerai_model_load_duration_seconds_bucket{model="m",kind="gguf",le="10"} 1
+seekerai_model_load_duration_seconds_bucket{model="m",kind="gguf",le="30"} 1
+seekerai_model_load_duration_seconds_bucket{model="m",kind="gguf",le="60"} 1
+seekerai_model_load_duration_seconds_bucket{model="m",kind="gguf",le="120"} 1
+seekerai_model_load_duration_seconds_bucket{model="m",kind="gguf",le="300"} 1
+seekerai_model_load_duration_seconds_bucket{model="m",kind="gguf",le="+Inf"} 1
+seekerai_model_load_duration_seconds_sum{model="m",kind="gguf"} 1.5
+seekerai_model_load_duration_seconds_count{model="m",kind="gguf"} 1
+# HELP seekerai_model_memory_estimated_bytes 已加载模型的估算权重内存
+# TYPE seekerai_model_memory_estimated_bytes gauge
+# HELP seekerai_model_memory_actual_bytes 加载模型前后进程常驻内存的差值
+# TYPE seekerai_model_memory_actual_bytes gauge
+# HELP seekerai_loaded_models 已加载的模型数
+# TYPE seekerai_loaded_models gauge
+seekerai_loaded_models 0
+# HELP seekerai_memory_budget_bytes 模型内存预算
+# TYPE seekerai_memory_budget_bytes gauge
+seekerai_memory_budget_bytes 1024
+"#;
+        assert_eq!(text, expected);
+    }
+}
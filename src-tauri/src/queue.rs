@@ -0,0 +1,399 @@
+//! HTTP 推理请求的准入队列
+//!
+//! 每个模型最多同时执行 `max_concurrent_per_model` 个生成，超出的请求按到达顺序排队，
+//! 队列达到 `max_queue_depth` 时直接拒绝（HTTP 429），客户端按 `Retry-After` 稍后重试。
+//! 排队中的请求在客户端断开（等待的 future 被丢弃）时自动出队。
+//!
+//! 同一模型上的生成由模型的锁串行执行（见 [`crate::registry::LoadedModel::lock`]），
+//! 准入队列只决定有多少请求可以开始等待模型锁，并不能让同一模型并行生成。
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+
+/// 队列配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// 每个模型同时通过准入的最大请求数
+    ///
+    /// 同一模型的生成持有模型锁依次执行，大于 1 时多出的请求在模型锁上等待而不是在队列中排队：
+    /// 不会提高吞吐量，但这些请求在队列状态中显示为执行中，也不计入 `max_queue_depth`。
+    /// 一般保持默认值 1。
+    pub max_concurrent_per_model: usize,
+    /// 每个模型最多排队的请求数，0 表示不排队
+    pub max_queue_depth: usize,
+    /// 队列已满时建议客户端等待的秒数
+    pub retry_after_secs: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_per_model: 1,
+            max_queue_depth: 16,
+            retry_after_secs: 5,
+        }
+    }
+}
+
+/// 队列已满，请求被拒绝
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFull {
+    pub retry_after_secs: u64,
+}
+
+impl std::fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "请求队列已满，请在 {} 秒后重试", self.retry_after_secs)
+    }
+}
+
+impl std::error::Error for QueueFull {}
+
+struct Entry {
+    ticket: u64,
+    request_id: String,
+    route: String,
+    enqueued_at: Instant,
+    started_at: Option<Instant>,
+}
+
+struct Waiter {
+    entry: Entry,
+    tx: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct ModelQueue {
+    in_flight: Vec<Entry>,
+    waiting: VecDeque<Waiter>,
+}
+
+/// 队列中的一个请求
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedRequest {
+    pub request_id: String,
+    pub route: String,
+    /// 0 表示正在执行，1 表示下一个执行，依此类推
+    pub position: usize,
+    /// 已等待的毫秒数
+    pub waited_ms: u64,
+    /// 已执行的毫秒数，排队中为 None
+    pub running_ms: Option<u64>,
+}
+
+/// 一个模型的队列状态
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelQueueStatus {
+    pub model_id: String,
+    pub in_flight: Vec<QueuedRequest>,
+    pub queued: Vec<QueuedRequest>,
+}
+
+/// 全部模型的队列状态
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueStatus {
+    #[serde(flatten)]
+    pub config: QueueConfig,
+    pub models: Vec<ModelQueueStatus>,
+}
+
+/// 按模型限制并发并排队的请求队列
+pub struct RequestQueue {
+    config: QueueConfig,
+    models: Mutex<HashMap<String, ModelQueue>>,
+    next_ticket: AtomicU64,
+}
+
+impl RequestQueue {
+    pub fn new(config: QueueConfig) -> Self {
+        Self {
+            config,
+            models: Mutex::new(HashMap::new()),
+            next_ticket: AtomicU64::new(0),
+        }
+    }
+
+    /// 等待在 `model_id` 上执行生成的许可
+    ///
+    /// 有空闲名额且没有更早的请求在排队时立即返回；否则排队等待，队列已满时返回 [`QueueFull`]。
+    /// 许可在离开作用域时归还，并唤醒下一个排队的请求。
+    pub async fn acquire(
+        self: &Arc<Self>,
+        model_id: &str,
+        request_id: &str,
+        route: &str,
+    ) -> Result<QueuePermit, QueueFull> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        let entry = Entry {
+            ticket,
+            request_id: request_id.to_string(),
+            route: route.to_string(),
+            enqueued_at: Instant::now(),
+            started_at: None,
+        };
+        let permit = QueuePermit {
+            queue: self.clone(),
+            model_id: model_id.to_string(),
+            ticket,
+        };
+
+        let rx = {
+            let mut models = self.models.lock().unwrap();
+            let queue = models.entry(model_id.to_string()).or_default();
+            if queue.waiting.is_empty()
+                && queue.in_flight.len() < self.config.max_concurrent_per_model.max(1)
+            {
+                queue.in_flight.push(Entry {
+                    started_at: Some(Instant::now()),
+                    ..entry
+                });
+                return Ok(permit);
+            }
+            if queue.waiting.len() >= self.config.max_queue_depth {
+                warn!("模型 {} 的请求队列已满，拒绝请求 {}", model_id, request_id);
+                return Err(QueueFull {
+                    retry_after_secs: self.config.retry_after_secs,
+                });
+            }
+            let (tx, rx) = oneshot::channel();
+            queue.waiting.push_back(Waiter { entry, tx });
+            debug!(
+                "请求 {} 在模型 {} 上排队，位置: {}",
+                request_id,
+                model_id,
+                queue.waiting.len()
+            );
+            rx
+        };
+
+        // 等待期间被丢弃时，许可的 Drop 会把请求移出队列
+        match rx.await {
+            Ok(()) => Ok(permit),
+            Err(_) => Err(QueueFull {
+                retry_after_secs: self.config.retry_after_secs,
+            }),
+        }
+    }
+
+    /// 移除请求（排队中或执行中），并让排在最前面的请求补上空出的名额
+    fn release(&self, model_id: &str, ticket: u64) {
+        let mut models = self.models.lock().unwrap();
+        let Some(queue) = models.get_mut(model_id) else {
+            return;
+        };
+        queue.in_flight.retain(|entry| entry.ticket != ticket);
+        queue.waiting.retain(|waiter| waiter.entry.ticket != ticket);
+
+        let max_concurrent = self.config.max_concurrent_per_model.max(1);
+        while queue.in_flight.len() < max_concurrent {
+            let Some(waiter) = queue.waiting.pop_front() else {
+                break;
+            };
+            // 等待方已断开时跳过它
+            if waiter.tx.send(()).is_ok() {
+                queue.in_flight.push(Entry {
+                    started_at: Some(Instant::now()),
+                    ..waiter.entry
+                });
+            }
+        }
+
+        if queue.in_flight.is_empty() && queue.waiting.is_empty() {
+            models.remove(model_id);
+        }
+    }
+
+    /// 获取各模型正在执行和排队中的请求
+    pub fn status(&self) -> QueueStatus {
+        let models = self.models.lock().unwrap();
+        let request = |entry: &Entry, position: usize| QueuedRequest {
+            request_id: entry.request_id.clone(),
+            route: entry.route.clone(),
+            position,
+            waited_ms: entry
+                .started_at
+                .unwrap_or_else(Instant::now)
+                .duration_since(entry.enqueued_at)
+                .as_millis() as u64,
+            running_ms: entry
+                .started_at
+                .map(|started| started.elapsed().as_millis() as u64),
+        };
+        let mut status: Vec<ModelQueueStatus> = models
+            .iter()
+            .map(|(model_id, queue)| ModelQueueStatus {
+                model_id: model_id.clone(),
+                in_flight: queue.in_flight.iter().map(|e| request(e, 0)).collect(),
+                queued: queue
+                    .waiting
+                    .iter()
+                    .enumerate()
+                    .map(|(index, waiter)| request(&waiter.entry, index + 1))
+                    .collect(),
+            })
+            .collect();
+        status.sort_by(|a, b| a.model_id.cmp(&b.model_id));
+
+        QueueStatus {
+            config: self.config.clone(),
+            models: status,
+        }
+    }
+}
+
+/// 执行生成的许可，离开作用域时归还
+pub struct QueuePermit {
+    queue: Arc<RequestQueue>,
+    model_id: String,
+    ticket: u64,
+}
+
+impl Drop for QueuePermit {
+    fn drop(&mut self) {
+        self.queue.release(&self.model_id, self.ticket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// 记录执行顺序和最大并发数的假推理引擎
+    #[derive(Default)]
+    struct FakeEngine {
+        order: Mutex<Vec<String>>,
+        running: AtomicU64,
+        max_running: AtomicU64,
+    }
+
+    impl FakeEngine {
+        async fn generate(&self, request_id: &str) {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            self.order.lock().unwrap().push(request_id.to_string());
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn queue(max_concurrent_per_model: usize, max_queue_depth: usize) -> Arc<RequestQueue> {
+        Arc::new(RequestQueue::new(QueueConfig {
+            max_concurrent_per_model,
+            max_queue_depth,
+            retry_after_secs: 3,
+        }))
+    }
+
+    async fn run(queue: Arc<RequestQueue>, engine: Arc<FakeEngine>, model: &str, id: String) {
+        let _permit = queue.acquire(model, &id, "test").await.unwrap();
+        engine.generate(&id).await;
+    }
+
+    #[tokio::test]
+    async fn test_requests_run_in_arrival_order() {
+        let queue = queue(1, 8);
+        let engine = Arc::new(FakeEngine::default());
+
+        let mut tasks = Vec::new();
+        for i in 0..5 {
+            let id = format!("req-{}", i);
+            tasks.push(tokio::spawn(run(queue.clone(), engine.clone(), "m", id)));
+            // 保证提交顺序
+            tokio::task::yield_now().await;
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let order = engine.order.lock().unwrap().clone();
+        let expected: Vec<String> = (0..5).map(|i| format!("req-{}", i)).collect();
+        assert_eq!(order, expected);
+        assert_eq!(engine.max_running.load(Ordering::SeqCst), 1);
+        assert!(queue.status().models.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_is_per_model() {
+        let queue = queue(2, 8);
+        let engine = Arc::new(FakeEngine::default());
+
+        let mut tasks = Vec::new();
+        for i in 0..6 {
+            let model = if i % 2 == 0 { "a" } else { "b" };
+            let id = format!("{}-{}", model, i);
+            tasks.push(tokio::spawn(run(queue.clone(), engine.clone(), model, id)));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // 两个模型各允许 2 个并发
+        assert_eq!(engine.max_running.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_when_queue_is_full() {
+        let queue = queue(1, 1);
+
+        let running = queue.acquire("m", "running", "test").await.unwrap();
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.acquire("m", "waiting", "test").await.map(|_| ()) }
+        });
+        while queue.status().models[0].queued.is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let rejected = queue.acquire("m", "rejected", "test").await;
+        assert_eq!(
+            rejected.err(),
+            Some(QueueFull {
+                retry_after_secs: 3
+            })
+        );
+        // 其他模型不受影响
+        assert!(queue.acquire("other", "free", "test").await.is_ok());
+
+        let status = queue.status();
+        assert_eq!(status.models[0].in_flight[0].request_id, "running");
+        assert_eq!(status.models[0].queued[0].request_id, "waiting");
+        assert_eq!(status.models[0].queued[0].position, 1);
+
+        drop(running);
+        assert!(waiting.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_waiter_leaves_queue() {
+        let queue = queue(1, 1);
+
+        let running = queue.acquire("m", "running", "test").await.unwrap();
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.acquire("m", "gone", "test").await.map(|_| ()) }
+        });
+        while queue.status().models[0].queued.is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        // 客户端断开：排队中的请求出队，空出的位置可以被新请求使用
+        waiting.abort();
+        let _ = waiting.await;
+        assert!(queue.status().models[0].queued.is_empty());
+
+        let next = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.acquire("m", "next", "test").await.map(|_| ()) }
+        });
+        drop(running);
+        assert!(next.await.unwrap().is_ok());
+        assert!(queue.status().models.is_empty());
+    }
+}
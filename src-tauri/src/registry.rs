@@ -0,0 +1,476 @@
+//! 已加载模型注册表
+//!
+//! 按用户指定的 id 同时持有多个已加载的模型（GGUF、Safetensors Llama、Qwen3VL），
+//! 每个模型有独立的锁，不同窗口可以同时使用不同的模型。
+//! 加载时未指定 id 的模型使用按类型区分的默认 id，再次加载会替换该默认模型。
+//!
+//! 设置内存预算后，加载新模型前按估算大小卸载最久未使用的空闲模型，
+//! 加载成功后再按实测占用检查一次，使总占用回到预算内。
+
+use ai_base::memory::{format_bytes, resident_memory_bytes};
+use ai_base::models::qwen3vl::Qwen3VLInferenceEngine;
+use ai_base::{GGUFInferenceEngine, InferenceEngine};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// 未指定 model_id 时 GGUF 模型使用的 id
+pub const DEFAULT_GGUF_MODEL_ID: &str = "gguf";
+/// 未指定 model_id 时 Safetensors 模型使用的 id
+pub const DEFAULT_SAFETENSORS_MODEL_ID: &str = "safetensors";
+
+/// 模型类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelKind {
+    Gguf,
+    Safetensors,
+    /// 通过 `init_qwen3vl_model` 加载的 Qwen3-VL 多模态模型
+    Qwen3vl,
+}
+
+/// 已加载的推理引擎
+pub enum LoadedEngine {
+    Gguf(Box<GGUFInferenceEngine>),
+    Safetensors(Box<InferenceEngine>),
+    /// 权重已加载并计入内存预算，但生成尚未实现，请求返回 `not_implemented`
+    #[allow(dead_code)]
+    Qwen3vl(Box<Qwen3VLInferenceEngine>),
+}
+
+impl LoadedEngine {
+    pub fn as_gguf_mut(&mut self) -> Option<&mut GGUFInferenceEngine> {
+        match self {
+            LoadedEngine::Gguf(engine) => Some(engine.as_mut()),
+            _ => None,
+        }
+    }
+}
+
+/// 模型内存占用
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct MemoryUsage {
+    /// 加载前估算的权重常驻字节数
+    pub estimated_bytes: u64,
+    /// 加载前后进程常驻内存的差值，不支持的平台为 None
+    pub actual_bytes: Option<u64>,
+}
+
+impl MemoryUsage {
+    /// 计入内存预算的字节数：有实测值时使用实测值
+    fn resident_bytes(&self) -> u64 {
+        self.actual_bytes.unwrap_or(self.estimated_bytes)
+    }
+}
+
+/// 注册表中的一个模型
+pub struct LoadedModel<E = LoadedEngine> {
+    pub id: String,
+    pub kind: ModelKind,
+    /// 模型文件路径或 Hub 仓库
+    pub source: String,
+    pub memory: MemoryUsage,
+    loaded_at: SystemTime,
+    last_used: Mutex<Instant>,
+    engine: Mutex<E>,
+}
+
+impl<E> LoadedModel<E> {
+    /// 获取该模型的锁，同一模型上的生成依次执行
+    pub fn lock(&self) -> MutexGuard<'_, E> {
+        let guard = self.engine.lock().unwrap_or_else(|e| e.into_inner());
+        *self.last_used.lock().unwrap() = Instant::now();
+        guard
+    }
+
+    fn last_used(&self) -> Instant {
+        *self.last_used.lock().unwrap()
+    }
+
+    pub fn info(&self) -> LoadedModelInfo {
+        LoadedModelInfo {
+            model_id: self.id.clone(),
+            kind: self.kind,
+            source: self.source.clone(),
+            loaded_at: self
+                .loaded_at
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            busy: self.engine.try_lock().is_err(),
+            estimated_bytes: self.memory.estimated_bytes,
+            actual_bytes: self.memory.actual_bytes,
+        }
+    }
+}
+
+/// 已加载模型的信息
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadedModelInfo {
+    pub model_id: String,
+    pub kind: ModelKind,
+    pub source: String,
+    /// 加载时间（Unix 秒）
+    pub loaded_at: u64,
+    /// 是否正在执行生成
+    pub busy: bool,
+    pub estimated_bytes: u64,
+    pub actual_bytes: Option<u64>,
+}
+
+/// 内存占用汇总
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryReport {
+    /// 内存预算，None 表示不限制
+    pub budget_bytes: Option<u64>,
+    pub estimated_total_bytes: u64,
+    /// 已知实测值的模型的实测总和
+    pub actual_total_bytes: u64,
+    /// 当前进程常驻内存，不支持的平台为 None
+    pub process_resident_bytes: Option<u64>,
+    pub models: Vec<LoadedModelInfo>,
+}
+
+/// 已加载模型注册表
+///
+/// `E` 为引擎类型，测试中可以用不含权重的占位类型代替
+pub struct ModelRegistry<E = LoadedEngine> {
+    models: Mutex<HashMap<String, Arc<LoadedModel<E>>>>,
+    /// 已加载模型的内存预算（字节），None 表示不限制
+    budget_bytes: Mutex<Option<u64>>,
+}
+
+impl<E> ModelRegistry<E> {
+    pub fn new() -> Self {
+        Self {
+            models: Mutex::new(HashMap::new()),
+            budget_bytes: Mutex::new(None),
+        }
+    }
+
+    pub fn budget(&self) -> Option<u64> {
+        *self.budget_bytes.lock().unwrap()
+    }
+
+    /// 设置内存预算，None 表示不限制；只在下一次加载模型时生效
+    pub fn set_budget(&self, budget_bytes: Option<u64>) {
+        *self.budget_bytes.lock().unwrap() = budget_bytes;
+    }
+
+    /// 在内存预算内执行加载，返回加载结果和内存占用
+    ///
+    /// 加载前按估算大小卸载最久未使用的空闲模型，为新模型腾出空间；
+    /// 同 id 的旧模型在加载期间仍然常驻，计入已用且不卸载，加载成功登记后才被替换。
+    /// 估算可能偏小，加载成功后再按实测占用检查一次。
+    /// 单个模型就超过预算时不加载，直接返回错误。
+    pub fn load_within_budget<T>(
+        &self,
+        id: &str,
+        estimated_bytes: u64,
+        load: impl FnOnce() -> Result<T>,
+    ) -> Result<(T, MemoryUsage)> {
+        if let Some(budget) = self.budget() {
+            if estimated_bytes > budget {
+                anyhow::bail!(
+                    "模型预计占用 {}，超过内存预算 {}",
+                    format_bytes(estimated_bytes),
+                    format_bytes(budget)
+                );
+            }
+        }
+
+        self.make_room(id, estimated_bytes, true);
+
+        let before = resident_memory_bytes();
+        let engine = load()?;
+        let actual_bytes = before
+            .zip(resident_memory_bytes())
+            .map(|(before, after)| after.saturating_sub(before));
+        info!(
+            "模型 {} 预计占用 {}，实际增加 {}",
+            id,
+            format_bytes(estimated_bytes),
+            actual_bytes.map_or_else(|| "未知".to_string(), format_bytes)
+        );
+
+        let memory = MemoryUsage {
+            estimated_bytes,
+            actual_bytes,
+        };
+        self.make_room(id, memory.resident_bytes(), false);
+        Ok((engine, memory))
+    }
+
+    /// 为即将以 `id` 登记、占用 `needed` 字节的模型卸载最久未使用的模型
+    ///
+    /// `loading` 表示新模型尚未加载，此时同 id 的旧模型仍然常驻，按正在使用处理
+    fn make_room(&self, id: &str, needed: u64, loading: bool) {
+        let Some(budget) = self.budget() else {
+            return;
+        };
+
+        let mut models = self.models.lock().unwrap();
+        // 持有注册表的锁时其他线程无法再获取模型的引用，引用计数只会减少
+        let candidates = models
+            .values()
+            .map(|m| EvictionCandidate {
+                id: m.id.clone(),
+                bytes: m.memory.resident_bytes(),
+                last_used: m.last_used(),
+                in_use: Arc::strong_count(m) > 1 || (loading && m.id == id),
+            })
+            .collect();
+        let plan = plan_eviction(candidates, id, needed, budget);
+        for victim in &plan.victims {
+            models.remove(victim);
+            info!(
+                "内存预算 {} 不足（需要 {}），卸载最久未使用的模型 {}",
+                format_bytes(budget),
+                format_bytes(needed),
+                victim
+            );
+        }
+        if plan.used + needed > budget {
+            warn!(
+                "卸载空闲模型后仍超出内存预算 {}（已用 {}，新模型 {}），正在使用的模型结束后才会释放",
+                format_bytes(budget),
+                format_bytes(plan.used),
+                format_bytes(needed)
+            );
+        }
+    }
+
+    /// 以 `id` 登记模型，已有同 id 的模型时替换它
+    pub fn insert(
+        &self,
+        id: impl Into<String>,
+        kind: ModelKind,
+        source: impl Into<String>,
+        memory: MemoryUsage,
+        engine: E,
+    ) -> Arc<LoadedModel<E>> {
+        let model = Arc::new(LoadedModel {
+            id: id.into(),
+            kind,
+            source: source.into(),
+            memory,
+            loaded_at: SystemTime::now(),
+            last_used: Mutex::new(Instant::now()),
+            engine: Mutex::new(engine),
+        });
+        let replaced = self
+            .models
+            .lock()
+            .unwrap()
+            .insert(model.id.clone(), model.clone());
+        if replaced.is_some() {
+            info!("模型 {} 已被新加载的模型替换", model.id);
+        }
+        model
+    }
+
+    /// 按 id 查找已加载的模型
+    pub fn get(&self, id: &str) -> Option<Arc<LoadedModel<E>>> {
+        self.models.lock().unwrap().get(id).cloned()
+    }
+
+    /// 卸载模型，返回被移除的模型
+    ///
+    /// 正在进行的生成持有模型的引用，引擎在其结束后释放。
+    pub fn remove(&self, id: &str) -> Option<Arc<LoadedModel<E>>> {
+        self.models.lock().unwrap().remove(id)
+    }
+
+    /// 列出全部已加载的模型（按加载时间排序）
+    pub fn list(&self) -> Vec<LoadedModelInfo> {
+        let mut models: Vec<Arc<LoadedModel<E>>> =
+            self.models.lock().unwrap().values().cloned().collect();
+        models.sort_by_key(|model| model.loaded_at);
+        models.iter().map(|model| model.info()).collect()
+    }
+
+    /// 汇总预算、估算与实测的内存占用
+    pub fn memory_report(&self) -> MemoryReport {
+        let models = self.list();
+        MemoryReport {
+            budget_bytes: self.budget(),
+            estimated_total_bytes: models.iter().map(|m| m.estimated_bytes).sum(),
+            actual_total_bytes: models.iter().filter_map(|m| m.actual_bytes).sum(),
+            process_resident_bytes: resident_memory_bytes(),
+            models,
+        }
+    }
+}
+
+impl<E> Default for ModelRegistry<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 参与卸载决策的模型
+struct EvictionCandidate {
+    id: String,
+    bytes: u64,
+    last_used: Instant,
+    /// 除注册表外还有其他引用（例如正在生成），移出注册表也不会立即释放内存
+    in_use: bool,
+}
+
+/// 卸载决策
+#[derive(Debug, PartialEq)]
+struct EvictionPlan {
+    /// 按卸载顺序排列的模型 id
+    victims: Vec<String>,
+    /// 卸载并替换同 id 模型后仍然占用的字节数
+    used: u64,
+}
+
+/// 按最久未使用的顺序选出需要卸载的模型，直到已用加上 `needed` 不超过 `budget`
+///
+/// 同 id 的模型登记新模型时会被替换，不需要卸载，空闲时直接从已用中扣除；
+/// 正在使用的模型卸载后不会立即释放，既不卸载也不计为已释放。
+fn plan_eviction(
+    mut candidates: Vec<EvictionCandidate>,
+    id: &str,
+    needed: u64,
+    budget: u64,
+) -> EvictionPlan {
+    let mut used: u64 = candidates
+        .iter()
+        .filter(|m| m.id != id || m.in_use)
+        .map(|m| m.bytes)
+        .sum();
+    candidates.retain(|m| m.id != id && !m.in_use);
+    candidates.sort_by_key(|m| m.last_used);
+
+    let mut victims = Vec::new();
+    for candidate in candidates {
+        if used + needed <= budget {
+            break;
+        }
+        used -= candidate.bytes;
+        victims.push(candidate.id);
+    }
+    EvictionPlan { victims, used }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// 创建一个在 `age_secs` 秒前使用过的候选模型
+    fn candidate(id: &str, bytes: u64, age_secs: u64, in_use: bool) -> EvictionCandidate {
+        EvictionCandidate {
+            id: id.to_string(),
+            bytes,
+            last_used: Instant::now() - Duration::from_secs(age_secs),
+            in_use,
+        }
+    }
+
+    fn plan(victims: &[&str], used: u64) -> EvictionPlan {
+        EvictionPlan {
+            victims: victims.iter().map(|id| id.to_string()).collect(),
+            used,
+        }
+    }
+
+    /// 依次登记不含引擎、各占 `bytes` 字节的模型，先登记的最久未使用
+    fn registry_with(models: &[(&str, u64)]) -> ModelRegistry<()> {
+        let registry = ModelRegistry::new();
+        for &(id, bytes) in models {
+            let memory = MemoryUsage {
+                estimated_bytes: bytes,
+                actual_bytes: None,
+            };
+            registry.insert(id, ModelKind::Gguf, id, memory, ());
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        registry
+    }
+
+    #[test]
+    fn test_evicts_before_loading() {
+        let registry = registry_with(&[("oldest", 60), ("recent", 30)]);
+        registry.set_budget(Some(100));
+        // 已用 90，新模型预计 50：加载开始前 oldest 就已卸载
+        let result = registry.load_within_budget("new", 50, || {
+            assert!(registry.get("oldest").is_none());
+            assert!(registry.get("recent").is_some());
+            Ok(())
+        });
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_same_id_model_stays_resident_while_loading() {
+        let registry = registry_with(&[("m", 40), ("other", 30)]);
+        registry.set_budget(Some(100));
+        // 旧的 m 在加载期间仍然常驻，腾出空间只能卸载 other
+        let result = registry.load_within_budget("m", 50, || {
+            assert!(registry.get("m").is_some());
+            assert!(registry.get("other").is_none());
+            Ok(())
+        });
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_first() {
+        let candidates = vec![
+            candidate("recent", 40, 0, false),
+            candidate("oldest", 30, 20, false),
+            candidate("older", 30, 10, false),
+        ];
+        // 已用 100，需要 40：卸载 oldest 后仍为 110，再卸载 older 后为 80
+        assert_eq!(
+            plan_eviction(candidates, "new", 40, 100),
+            plan(&["oldest", "older"], 40)
+        );
+    }
+
+    #[test]
+    fn test_no_eviction_when_within_budget() {
+        let candidates = vec![candidate("a", 30, 10, false), candidate("b", 30, 0, false)];
+        // 恰好等于预算时不卸载
+        assert_eq!(plan_eviction(candidates, "new", 40, 100), plan(&[], 60));
+    }
+
+    #[test]
+    fn test_same_id_model_is_replaced_not_evicted() {
+        let candidates = vec![
+            candidate("m", 60, 0, false),
+            candidate("other", 30, 10, false),
+        ];
+        // 同 id 的旧模型会被替换，只有 other 计入已用
+        assert_eq!(plan_eviction(candidates, "m", 60, 100), plan(&[], 30));
+    }
+
+    #[test]
+    fn test_in_use_models_are_not_counted_as_freed() {
+        let candidates = vec![
+            candidate("busy", 50, 20, true),
+            candidate("idle", 30, 10, false),
+        ];
+        // busy 最久未使用但正在使用，不卸载也不计为已释放；卸载 idle 后仍超出预算
+        assert_eq!(
+            plan_eviction(candidates, "new", 60, 100),
+            plan(&["idle"], 50)
+        );
+
+        // 正在使用的同 id 模型替换后要等使用结束才释放，仍计入已用
+        let candidates = vec![
+            candidate("m", 40, 0, true),
+            candidate("other", 30, 10, false),
+        ];
+        assert_eq!(
+            plan_eviction(candidates, "m", 40, 100),
+            plan(&["other"], 40)
+        );
+    }
+}
@@ -0,0 +1,415 @@
+//! 推理任务队列
+//!
+//! 模型加载和推理都是同步、CPU 密集的操作。Tauri 命令把它们作为任务提交到
+//! 专用的工作线程上执行，再异步等待结果，从而不会阻塞 async 运行时。
+//!
+//! 每个模型 id 有自己的工作线程和队列（首次提交时创建）：同一模型的任务按提交顺序依次执行，
+//! 不同模型的任务互不等待。
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+use tokio::sync::oneshot;
+use tracing::{debug, error, info};
+
+/// 任务执行失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobError {
+    /// 任务在排队期间被取消
+    Cancelled,
+    /// 任务执行过程中发生 panic
+    Aborted,
+}
+
+impl std::fmt::Display for JobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobError::Cancelled => write!(f, "任务在排队时被取消"),
+            JobError::Aborted => write!(f, "推理任务异常终止"),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+/// 队列中的任务信息
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub job_id: String,
+    /// 任务所属的模型 id
+    pub model_id: String,
+    /// 任务类型，例如 "generate"、"load_model"
+    pub kind: String,
+    /// 在所属模型队列中的位置：0 表示正在执行，1 表示下一个执行，依此类推
+    pub position: usize,
+    /// 已等待或已执行的毫秒数
+    pub elapsed_ms: u64,
+}
+
+/// 工作线程状态，按模型 id 排列
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerStatus {
+    /// 各模型正在执行的任务
+    pub running: Vec<JobInfo>,
+    pub queued: Vec<JobInfo>,
+}
+
+struct Job {
+    id: String,
+    kind: String,
+    submitted_at: Instant,
+    /// 参数为 true 表示任务在排队时被取消，只需通知等待方
+    run: Box<dyn FnOnce(bool) + Send>,
+}
+
+struct RunningJob {
+    id: String,
+    kind: String,
+    started_at: Instant,
+}
+
+/// 一个模型的任务队列，由该模型的工作线程消费
+struct Lane {
+    queue: Mutex<VecDeque<Job>>,
+    running: Mutex<Option<RunningJob>>,
+    available: Condvar,
+}
+
+/// 已提交任务的句柄
+pub struct JobHandle<T> {
+    pub job_id: String,
+    /// 提交时在所属模型队列中的位置（0 表示立即执行）
+    pub position: usize,
+    rx: oneshot::Receiver<Result<T, JobError>>,
+}
+
+impl<T> JobHandle<T> {
+    /// 等待任务完成
+    pub async fn wait(self) -> Result<T, JobError> {
+        self.rx.await.unwrap_or(Err(JobError::Aborted))
+    }
+}
+
+impl<T> JobHandle<anyhow::Result<T>> {
+    /// 等待任务完成，并把排队错误合并到任务自身的错误中
+    pub async fn join(self) -> anyhow::Result<T> {
+        self.wait().await?
+    }
+}
+
+/// 推理工作线程
+///
+/// 每个模型一个工作线程，同一模型的任务按提交顺序依次执行。
+pub struct InferenceWorker {
+    lanes: Mutex<HashMap<String, Arc<Lane>>>,
+}
+
+impl InferenceWorker {
+    pub fn new() -> Self {
+        Self {
+            lanes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 获取模型的任务队列，不存在时创建队列并启动工作线程
+    fn lane(&self, model_id: &str) -> Arc<Lane> {
+        let mut lanes = self.lanes.lock().unwrap();
+        if let Some(lane) = lanes.get(model_id) {
+            return lane.clone();
+        }
+
+        let lane = Arc::new(Lane {
+            queue: Mutex::new(VecDeque::new()),
+            running: Mutex::new(None),
+            available: Condvar::new(),
+        });
+        let worker_lane = lane.clone();
+        let name = model_id.to_string();
+        std::thread::Builder::new()
+            .name(format!("inference-worker-{}", model_id))
+            .spawn(move || Self::run(&name, worker_lane))
+            .expect("无法创建推理工作线程");
+        lanes.insert(model_id.to_string(), lane.clone());
+        lane
+    }
+
+    fn run(model_id: &str, lane: Arc<Lane>) {
+        info!("模型 {} 的推理工作线程已启动", model_id);
+        loop {
+            let job = {
+                let mut queue = lane.queue.lock().unwrap();
+                let job = loop {
+                    if let Some(job) = queue.pop_front() {
+                        break job;
+                    }
+                    queue = lane.available.wait(queue).unwrap();
+                };
+                // 持有队列锁时标记为执行中，保证状态查询看到的位置连续
+                *lane.running.lock().unwrap() = Some(RunningJob {
+                    id: job.id.clone(),
+                    kind: job.kind.clone(),
+                    started_at: Instant::now(),
+                });
+                job
+            };
+
+            debug!("开始执行任务 {} ({})", job.id, job.kind);
+
+            // 任务 panic 时结果通道被丢弃，等待方会收到 Aborted
+            if std::panic::catch_unwind(AssertUnwindSafe(|| (job.run)(false))).is_err() {
+                error!("任务 {} ({}) 执行时发生 panic", job.id, job.kind);
+            }
+
+            *lane.running.lock().unwrap() = None;
+        }
+    }
+
+    /// 把任务提交到 `model_id` 的队列，返回可等待结果的句柄
+    pub fn submit<T, F>(
+        &self,
+        model_id: &str,
+        job_id: impl Into<String>,
+        kind: impl Into<String>,
+        f: F,
+    ) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job_id = job_id.into();
+        let job = Job {
+            id: job_id.clone(),
+            kind: kind.into(),
+            submitted_at: Instant::now(),
+            run: Box::new(move |cancelled| {
+                let result = if cancelled {
+                    Err(JobError::Cancelled)
+                } else {
+                    Ok(f())
+                };
+                let _ = tx.send(result);
+            }),
+        };
+
+        let lane = self.lane(model_id);
+        let position = {
+            let mut queue = lane.queue.lock().unwrap();
+            queue.push_back(job);
+            let busy = lane.running.lock().unwrap().is_some();
+            queue.len() - 1 + usize::from(busy)
+        };
+        lane.available.notify_one();
+
+        debug!(
+            "任务 {} 已提交到模型 {}，排队位置: {}",
+            job_id, model_id, position
+        );
+        JobHandle {
+            job_id,
+            position,
+            rx,
+        }
+    }
+
+    /// 从队列中移除尚未开始的任务，返回是否找到该任务
+    pub fn cancel_queued(&self, job_id: &str) -> bool {
+        let lanes: Vec<Arc<Lane>> = self.lanes.lock().unwrap().values().cloned().collect();
+        for lane in lanes {
+            let job = {
+                let mut queue = lane.queue.lock().unwrap();
+                queue
+                    .iter()
+                    .position(|job| job.id == job_id)
+                    .and_then(|index| queue.remove(index))
+            };
+            if let Some(job) = job {
+                (job.run)(true);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 获取各模型执行中和排队中的任务
+    pub fn status(&self) -> WorkerStatus {
+        let mut lanes: Vec<(String, Arc<Lane>)> = self
+            .lanes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(model_id, lane)| (model_id.clone(), lane.clone()))
+            .collect();
+        lanes.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut status = WorkerStatus {
+            running: Vec::new(),
+            queued: Vec::new(),
+        };
+        for (model_id, lane) in lanes {
+            let queue = lane.queue.lock().unwrap();
+            let running = lane.running.lock().unwrap();
+
+            if let Some(job) = running.as_ref() {
+                status.running.push(JobInfo {
+                    job_id: job.id.clone(),
+                    model_id: model_id.clone(),
+                    kind: job.kind.clone(),
+                    position: 0,
+                    elapsed_ms: job.started_at.elapsed().as_millis() as u64,
+                });
+            }
+            let offset = usize::from(running.is_some());
+            status
+                .queued
+                .extend(queue.iter().enumerate().map(|(index, job)| JobInfo {
+                    job_id: job.id.clone(),
+                    model_id: model_id.clone(),
+                    kind: job.kind.clone(),
+                    position: index + offset,
+                    elapsed_ms: job.submitted_at.elapsed().as_millis() as u64,
+                }));
+        }
+        status
+    }
+}
+
+impl Default for InferenceWorker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    /// 提交一个阻塞 `model_id` 工作线程的任务，向返回的发送端发送消息后结束
+    async fn block_worker(
+        worker: &InferenceWorker,
+        model_id: &str,
+        job_id: &str,
+    ) -> (JobHandle<()>, mpsc::Sender<()>) {
+        let (release, gate) = mpsc::channel::<()>();
+        let handle = worker.submit(model_id, job_id, "generate", move || {
+            let _ = gate.recv();
+        });
+        while !worker
+            .status()
+            .running
+            .iter()
+            .any(|job| job.job_id == job_id)
+        {
+            tokio::task::yield_now().await;
+        }
+        (handle, release)
+    }
+
+    #[tokio::test]
+    async fn test_jobs_run_in_submission_order() {
+        let worker = InferenceWorker::new();
+        let (first, release) = block_worker(&worker, "m", "first").await;
+        assert_eq!(first.position, 0);
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let handles: Vec<_> = (1..=3)
+            .map(|i| {
+                let order = order.clone();
+                worker.submit("m", format!("job-{}", i), "generate", move || {
+                    order.lock().unwrap().push(i)
+                })
+            })
+            .collect();
+        let positions: Vec<usize> = handles.iter().map(|handle| handle.position).collect();
+        assert_eq!(positions, [1, 2, 3]);
+
+        let status = worker.status();
+        assert_eq!(status.running.len(), 1);
+        assert_eq!(status.running[0].job_id, "first");
+        let queued: Vec<(String, usize)> = status
+            .queued
+            .into_iter()
+            .map(|job| (job.job_id, job.position))
+            .collect();
+        assert_eq!(
+            queued,
+            [
+                ("job-1".to_string(), 1),
+                ("job-2".to_string(), 2),
+                ("job-3".to_string(), 3)
+            ]
+        );
+
+        release.send(()).unwrap();
+        first.wait().await.unwrap();
+        for handle in handles {
+            handle.wait().await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_queued_job() {
+        let worker = InferenceWorker::new();
+        let (first, release) = block_worker(&worker, "m", "first").await;
+        let queued = worker.submit("m", "queued", "generate", || 1);
+        let next = worker.submit("m", "next", "generate", || 2);
+
+        assert!(worker.cancel_queued("queued"));
+        // 已取消或正在执行的任务无法再取消
+        assert!(!worker.cancel_queued("queued"));
+        assert!(!worker.cancel_queued("first"));
+        assert_eq!(queued.wait().await, Err(JobError::Cancelled));
+
+        let status = worker.status();
+        assert_eq!(status.queued.len(), 1);
+        assert_eq!(status.queued[0].job_id, "next");
+        assert_eq!(status.queued[0].position, 1);
+
+        release.send(()).unwrap();
+        first.wait().await.unwrap();
+        assert_eq!(next.wait().await, Ok(2));
+    }
+
+    #[tokio::test]
+    async fn test_worker_survives_panicking_job() {
+        let worker = InferenceWorker::new();
+        let panicked = worker.submit("m", "panic", "generate", || -> i32 {
+            panic!("任务 panic")
+        });
+        let next = worker.submit("m", "next", "generate", || 42);
+
+        assert_eq!(panicked.wait().await, Err(JobError::Aborted));
+        assert_eq!(next.wait().await, Ok(42));
+    }
+
+    #[tokio::test]
+    async fn test_models_do_not_wait_for_each_other() {
+        let worker = InferenceWorker::new();
+        let (blocked, release) = block_worker(&worker, "a", "blocked").await;
+        let queued = worker.submit("a", "queued", "generate", || 8);
+        assert_eq!(queued.position, 1);
+
+        let status = worker.status();
+        let running: Vec<(&str, &str)> = status
+            .running
+            .iter()
+            .map(|job| (job.model_id.as_str(), job.job_id.as_str()))
+            .collect();
+        assert_eq!(running, [("a", "blocked")]);
+        assert_eq!(status.queued.len(), 1);
+        assert_eq!(status.queued[0].model_id, "a");
+
+        // 模型 a 的线程被占用时，模型 b 的任务立即执行
+        let other = worker.submit("b", "other", "generate", || 7);
+        assert_eq!(other.position, 0);
+        assert_eq!(other.wait().await, Ok(7));
+
+        release.send(()).unwrap();
+        blocked.wait().await.unwrap();
+        assert_eq!(queued.wait().await, Ok(8));
+    }
+}
@@ -9,6 +9,7 @@ import { Slider, SliderTrack, SliderFill, SliderThumb } from "@/components/ui/sl
 import { Alert, AlertDescription, AlertTitle } from "@/components/ui/alert";
 import { CheckCircle2, Loader2, RefreshCw } from "lucide-solid";
 import { useI18n } from "@/lib/i18n";
+import { errorMessage } from "@/lib/tauri/commands";
 import {
   Select,
   SelectContent,
@@ -20,8 +21,6 @@ import {
 
 interface InferenceResponse {
   text: string;
-  success: boolean;
-  error?: string;
 }
 
 interface UnifiedInferenceRequest {
@@ -104,16 +103,12 @@ export default function InferencePanel() {
         request,
       });
 
-      if (result.success) {
-        setResponse(result.text);
-        setMessage(t("inference.generateComplete"));
-        setModelLoaded(true);
-      } else {
-        setMessage(`${t("inference.generateFailed")}: ${result.error || t("inference.unknownError")}`);
-      }
+      setResponse(result.text);
+      setMessage(t("inference.generateComplete"));
+      setModelLoaded(true);
     } catch (error) {
       console.error("推理失败:", error);
-      setMessage(`${t("inference.error")}: ${error}`);
+      setMessage(`${t("inference.generateFailed")}: ${errorMessage(error)}`);
     } finally {
       setLoading(false);
     }
@@ -16,6 +16,7 @@ import {
   CheckCircle2
 } from "lucide-solid";
 import { useI18n } from "@/lib/i18n";
+import { errorMessage } from "@/lib/tauri/commands";
 import { Table, TableBody, TableCell, TableHead, TableHeader, TableRow } from "@/components/ui/table";
 import { Progress } from "@/components/ui/progress";
 import { OllamaManager } from "@/components/OllamaManager";
@@ -153,19 +154,13 @@ export default function ModelManagementPage() {
       };
       const response = await invoke<{
         models: RemoteModelInfo[];
-        success: boolean;
-        error?: string;
       }>("search_remote_models", { request });
 
-      if (response.success) {
-        setRemoteModels(response.models);
-        setMessage(`${t("models.searchComplete")}: ${response.models.length}`);
-      } else {
-        setMessage(response.error || t("models.searchFailed"));
-      }
+      setRemoteModels(response.models);
+      setMessage(`${t("models.searchComplete")}: ${response.models.length}`);
     } catch (error) {
       console.error("搜索远程模型失败:", error);
-      setMessage(`${t("models.error")}: ${error}`);
+      setMessage(`${t("models.searchFailed")}: ${errorMessage(error)}`);
     } finally {
       setSearchLoading(false);
     }
@@ -189,23 +184,17 @@ export default function ModelManagementPage() {
         filename,
       };
 
-      const response = await invoke<{
-        success: boolean;
+      await invoke<{
         message: string;
-        local_path?: string;
-        error?: string;
+        local_path: string;
       }>("download_model", { request });
 
-      if (response.success) {
-        setMessage(`${t("models.downloadSuccess")}: ${filename}`);
-        // 重新加载本地模型列表
-        await loadLocalModels();
-      } else {
-        setMessage(response.error || t("models.downloadFailed"));
-      }
+      setMessage(`${t("models.downloadSuccess")}: ${filename}`);
+      // 重新加载本地模型列表
+      await loadLocalModels();
     } catch (error) {
       console.error("下载模型失败:", error);
-      setMessage(`${t("models.error")}: ${error}`);
+      setMessage(`${t("models.downloadFailed")}: ${errorMessage(error)}`);
     } finally {
       setDownloading((prev) => {
         const next = new Set(prev);
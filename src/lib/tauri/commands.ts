@@ -62,6 +62,46 @@ export interface ModelFileInfo {
     type: string;
 }
 
+/** 错误码，与 HTTP 接口返回的 error.code 相同 */
+export type ErrorCode =
+    | "invalid_request"
+    | "unauthorized"
+    | "forbidden"
+    | "model_not_loaded"
+    | "model_not_found"
+    | "tokenizer_missing"
+    | "context_overflow"
+    | "request_in_progress"
+    | "queue_full"
+    | "not_implemented"
+    | "internal_error";
+
+/** 命令失败时 reject 的值 */
+export interface ApiError {
+    code: ErrorCode;
+    message: string;
+    /** 队列已满时建议等待的秒数 */
+    retry_after_secs?: number;
+}
+
+/** 判断命令 reject 的值是否为 ApiError */
+export function isApiError(error: unknown): error is ApiError {
+    return (
+        typeof error === "object" &&
+        error !== null &&
+        "code" in error &&
+        "message" in error
+    );
+}
+
+/** 取得可展示的错误信息 */
+export function errorMessage(error: unknown): string {
+    if (isApiError(error)) {
+        return error.message;
+    }
+    return error instanceof Error ? error.message : String(error);
+}
+
 // ============ 存储命令 ============
 
 /** 获取应用路径 */
@@ -194,17 +234,46 @@ export async function unifiedInference(options: {
 /** 服务器状态 */
 export interface ServerStatus {
     is_running: boolean;
+    /** 实际监听的地址 */
     address: string | null;
 }
 
+/** 服务器配置 */
+export interface ServerConfig {
+    host: string;
+    /** 0 表示由系统分配端口 */
+    port: number;
+    /** 非空时请求需要携带 Authorization: Bearer <key>；为空时 /admin 接口不可用 */
+    api_keys: string[];
+    /** 允许跨域访问的来源，为空时不允许跨域访问，["*"] 允许所有来源 */
+    allowed_origins: string[];
+    /** 推理请求的并发数与排队上限 */
+    queue: QueueConfig;
+}
+
+/** 推理请求队列配置 */
+export interface QueueConfig {
+    /** 每个模型同时通过准入的最大请求数；同一模型的生成依次执行，大于 1 不会提高吞吐量 */
+    max_concurrent_per_model: number;
+    /** 每个模型最多排队的请求数 */
+    max_queue_depth: number;
+    /** 队列已满时建议客户端等待的秒数 */
+    retry_after_secs: number;
+}
+
 /** 获取服务器状态 */
 export async function getServerStatus(): Promise<ServerStatus> {
     return invoke<ServerStatus>("get_server_status");
 }
 
-/** 启动服务器 */
-export async function startServer(): Promise<string> {
-    return invoke<string>("start_server");
+/** 获取保存的服务器配置 */
+export async function getServerConfig(): Promise<ServerConfig> {
+    return invoke<ServerConfig>("get_server_config");
+}
+
+/** 启动服务器，传入的配置会被保存；未传入时使用上次保存的配置 */
+export async function startServer(config?: ServerConfig): Promise<ServerStatus> {
+    return invoke<ServerStatus>("start_server", { config: config ?? null });
 }
 
 /** 停止服务器 */
@@ -212,6 +281,63 @@ export async function stopServer(): Promise<void> {
     return invoke("stop_server");
 }
 
+// ============ 推理统计命令 ============
+
+/** 直方图，buckets 为不超过 upper_bound 的累计观测数 */
+export interface HistogramSnapshot {
+    count: number;
+    sum: number;
+    buckets: { upper_bound: number; count: number }[];
+}
+
+/** 已加载模型的信息 */
+export interface LoadedModelInfo {
+    model_id: string;
+    kind: "gguf" | "safetensors" | "qwen3vl";
+    source: string;
+    /** 加载时间（Unix 秒） */
+    loaded_at: number;
+    busy: boolean;
+    estimated_bytes: number;
+    actual_bytes: number | null;
+}
+
+/** 推理统计 */
+export interface InferenceStats {
+    /** 各 HTTP 路由按状态码统计的请求数 */
+    requests: { route: string; status: number; count: number }[];
+    models: {
+        model_id: string;
+        generations: number;
+        prompt_tokens: number;
+        cached_tokens: number;
+        completion_tokens: number;
+        /** 首 token 延迟（秒） */
+        time_to_first_token: HistogramSnapshot;
+        /** 生成速度（token/秒） */
+        tokens_per_second: HistogramSnapshot;
+    }[];
+    loads: {
+        model_id: string;
+        kind: LoadedModelInfo["kind"];
+        /** 加载耗时（秒） */
+        duration: HistogramSnapshot;
+        last_duration_ms: number;
+    }[];
+    memory: {
+        budget_bytes: number | null;
+        estimated_total_bytes: number;
+        actual_total_bytes: number;
+        process_resident_bytes: number | null;
+        models: LoadedModelInfo[];
+    };
+}
+
+/** 获取推理统计，与 HTTP 服务的 /metrics 数据相同 */
+export async function getInferenceStats(): Promise<InferenceStats> {
+    return invoke<InferenceStats>("get_inference_stats");
+}
+
 // ============ 日志命令 ============
 
 /** 日志级别 */